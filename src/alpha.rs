@@ -0,0 +1,360 @@
+//! Conversion between straight and premultiplied alpha.
+//!
+//! All functions operate on packed 4-byte pixels with alpha in the last byte,
+//! so they apply equally to RGBA and BGRA buffers. x86 uses SSE paths when the
+//! running CPU supports them, aarch64 uses NEON and wasm32 uses `simd128` when
+//! the module is compiled with that feature; other targets use the scalar
+//! loops. Every path produces identical results.
+
+#[cfg(any(test, feature = "internal-testing"))]
+use crate::swizzle::Backend;
+use image::RgbaImage;
+
+/// How colour channels relate to the alpha channel in a pixel buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    /// Colour channels are independent of alpha.
+    Straight,
+    /// Colour channels have already been multiplied by alpha.
+    Premultiplied,
+}
+
+/// Multiplies the colour channels of every pixel by its alpha.
+pub fn premultiply(data: &mut [u8]) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("ssse3") {
+        return unsafe { x86::premultiply_ssse3(data) };
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    neon::premultiply(data);
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    wasm::premultiply(data);
+
+    #[cfg(not(any(target_arch = "aarch64", all(target_arch = "wasm32", target_feature = "simd128"))))]
+    premultiply_scalar(data);
+}
+
+/// Divides the colour channels of every pixel by its alpha.
+///
+/// Fully transparent pixels become transparent black.
+pub fn unpremultiply(data: &mut [u8]) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("sse2") {
+        return unsafe { x86::unpremultiply_sse2(data) };
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    neon::unpremultiply(data);
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    wasm::unpremultiply(data);
+
+    #[cfg(not(any(target_arch = "aarch64", all(target_arch = "wasm32", target_feature = "simd128"))))]
+    unpremultiply_scalar(data);
+}
+
+/// Multiplies the colour channels of an image by its alpha in place.
+pub fn premultiply_image(image: &mut RgbaImage) {
+    premultiply(image);
+}
+
+/// Divides the colour channels of an image by its alpha in place.
+pub fn unpremultiply_image(image: &mut RgbaImage) {
+    unpremultiply(image);
+}
+
+#[cfg(any(test, feature = "internal-testing"))]
+impl Backend {
+    /// Multiplies colour channels by alpha, as [`premultiply`] does.
+    ///
+    /// Panics if the backend is not [available](Backend::available).
+    pub fn premultiply(self, data: &mut [u8]) {
+        assert!(Backend::available().contains(&self), "{self:?} is not available");
+        match self {
+            Backend::Scalar => premultiply_scalar(data),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Ssse3 | Backend::Avx2 => unsafe { x86::premultiply_ssse3(data) },
+            #[cfg(target_arch = "aarch64")]
+            Backend::Neon => neon::premultiply(data),
+            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+            Backend::Simd128 => wasm::premultiply(data),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Divides colour channels by alpha, as [`unpremultiply`] does.
+    ///
+    /// Panics if the backend is not [available](Backend::available).
+    pub fn unpremultiply(self, data: &mut [u8]) {
+        assert!(Backend::available().contains(&self), "{self:?} is not available");
+        match self {
+            Backend::Scalar => unpremultiply_scalar(data),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Ssse3 | Backend::Avx2 => unsafe { x86::unpremultiply_sse2(data) },
+            #[cfg(target_arch = "aarch64")]
+            Backend::Neon => neon::unpremultiply(data),
+            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+            Backend::Simd128 => wasm::unpremultiply(data),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Guesses the alpha convention of a pixel buffer.
+///
+/// A buffer is treated as premultiplied when it contains at least one
+/// translucent pixel and no colour channel anywhere exceeds its alpha. Buffers
+/// that are entirely opaque or entirely transparent read the same either way
+/// and are reported as straight.
+pub fn detect_alpha_mode(data: &[u8]) -> AlphaMode {
+    let mut translucent = false;
+
+    for pixel in data.chunks_exact(4) {
+        let alpha = pixel[3];
+        if pixel[..3].iter().any(|&channel| channel > alpha) {
+            return AlphaMode::Straight;
+        }
+        translucent |= alpha != 0 && alpha != 0xFF;
+    }
+
+    if translucent {
+        AlphaMode::Premultiplied
+    } else {
+        AlphaMode::Straight
+    }
+}
+
+/// Scalar implementation of [`premultiply`], which the vector paths must match.
+fn premultiply_scalar(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        let alpha = u16::from(pixel[3]);
+        for channel in &mut pixel[..3] {
+            *channel = div255(u16::from(*channel) * alpha);
+        }
+    }
+}
+
+/// Scalar implementation of [`unpremultiply`], which the vector paths must match.
+fn unpremultiply_scalar(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        let scale = if alpha == 0 { 0.0 } else { 255.0 / f32::from(alpha) };
+        for channel in &mut pixel[..3] {
+            *channel = (f32::from(*channel) * scale + 0.5).min(255.0) as u8;
+        }
+    }
+}
+
+/// Divides by 255 with rounding; exact for every product of two bytes.
+fn div255(value: u16) -> u8 {
+    let value = value + 128;
+    ((value + (value >> 8)) >> 8) as u8
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn premultiply_ssse3(data: &mut [u8]) {
+        // Broadcast each pixel's alpha over its colour bytes and use 255 for
+        // the alpha byte itself so it survives the multiply unchanged.
+        let spread = _mm_setr_epi8(3, 3, 3, -128, 7, 7, 7, -128, 11, 11, 11, -128, 15, 15, 15, -128);
+        let keep = _mm_setr_epi8(0, 0, 0, -1, 0, 0, 0, -1, 0, 0, 0, -1, 0, 0, 0, -1);
+        let zero = _mm_setzero_si128();
+        let round = _mm_set1_epi16(128);
+
+        let mut chunks = data.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            let pixels = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            let factors = _mm_or_si128(_mm_shuffle_epi8(pixels, spread), keep);
+
+            let halves = [
+                (_mm_unpacklo_epi8(pixels, zero), _mm_unpacklo_epi8(factors, zero)),
+                (_mm_unpackhi_epi8(pixels, zero), _mm_unpackhi_epi8(factors, zero)),
+            ]
+            .map(|(values, factors)| {
+                let product = _mm_add_epi16(_mm_mullo_epi16(values, factors), round);
+                _mm_srli_epi16(_mm_add_epi16(product, _mm_srli_epi16(product, 8)), 8)
+            });
+
+            let result = _mm_packus_epi16(halves[0], halves[1]);
+            _mm_storeu_si128(chunk.as_mut_ptr() as *mut __m128i, result);
+        }
+        super::premultiply_scalar(chunks.into_remainder());
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn unpremultiply_sse2(data: &mut [u8]) {
+        let alpha_bytes = _mm_setr_epi8(0, 0, 0, -1, 0, 0, 0, -1, 0, 0, 0, -1, 0, 0, 0, -1);
+        let zero = _mm_setzero_si128();
+        let max = _mm_set1_ps(255.0);
+        let half = _mm_set1_ps(0.5);
+
+        let mut chunks = data.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            let pixels = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            let lo = _mm_unpacklo_epi8(pixels, zero);
+            let hi = _mm_unpackhi_epi8(pixels, zero);
+
+            let [p0, p1, p2, p3] = [
+                _mm_unpacklo_epi16(lo, zero),
+                _mm_unpackhi_epi16(lo, zero),
+                _mm_unpacklo_epi16(hi, zero),
+                _mm_unpackhi_epi16(hi, zero),
+            ]
+            .map(|pixel| {
+                let values = _mm_cvtepi32_ps(pixel);
+                let alpha = _mm_shuffle_ps(values, values, 0xFF);
+                // Zero the scale for transparent pixels instead of dividing by zero.
+                let scale = _mm_and_ps(_mm_div_ps(max, alpha), _mm_cmpneq_ps(alpha, _mm_setzero_ps()));
+                let scaled = _mm_min_ps(_mm_add_ps(_mm_mul_ps(values, scale), half), max);
+                _mm_cvttps_epi32(scaled)
+            });
+
+            let colours = _mm_packus_epi16(_mm_packs_epi32(p0, p1), _mm_packs_epi32(p2, p3));
+            let result = _mm_or_si128(_mm_andnot_si128(alpha_bytes, colours), _mm_and_si128(alpha_bytes, pixels));
+            _mm_storeu_si128(chunk.as_mut_ptr() as *mut __m128i, result);
+        }
+        super::unpremultiply_scalar(chunks.into_remainder());
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    /// Works on 16 pixels at a time, split into one vector per channel.
+    pub(super) fn premultiply(data: &mut [u8]) {
+        let mut chunks = data.chunks_exact_mut(64);
+        for chunk in &mut chunks {
+            unsafe {
+                let mut pixels = vld4q_u8(chunk.as_ptr());
+                pixels.0 = multiply(pixels.0, pixels.3);
+                pixels.1 = multiply(pixels.1, pixels.3);
+                pixels.2 = multiply(pixels.2, pixels.3);
+                vst4q_u8(chunk.as_mut_ptr(), pixels);
+            }
+        }
+        super::premultiply_scalar(chunks.into_remainder());
+    }
+
+    pub(super) fn unpremultiply(data: &mut [u8]) {
+        let mut chunks = data.chunks_exact_mut(64);
+        for chunk in &mut chunks {
+            unsafe {
+                let mut pixels = vld4q_u8(chunk.as_ptr());
+                let scales = scales(pixels.3);
+                pixels.0 = divide(pixels.0, scales);
+                pixels.1 = divide(pixels.1, scales);
+                pixels.2 = divide(pixels.2, scales);
+                vst4q_u8(chunk.as_mut_ptr(), pixels);
+            }
+        }
+        super::unpremultiply_scalar(chunks.into_remainder());
+    }
+
+    /// Multiplies each lane by its alpha and divides by 255 as `div255` does.
+    unsafe fn multiply(channel: uint8x16_t, alpha: uint8x16_t) -> uint8x16_t {
+        let round = vdupq_n_u16(128);
+        let [low, high] = [vmull_u8(vget_low_u8(channel), vget_low_u8(alpha)), vmull_high_u8(channel, alpha)].map(|product| {
+            let value = vaddq_u16(product, round);
+            vshrn_n_u16::<8>(vaddq_u16(value, vshrq_n_u16::<8>(value)))
+        });
+        vcombine_u8(low, high)
+    }
+
+    /// Widens 16 bytes into four vectors of floats.
+    unsafe fn widen(bytes: uint8x16_t) -> [float32x4_t; 4] {
+        let low = vmovl_u8(vget_low_u8(bytes));
+        let high = vmovl_high_u8(bytes);
+        [vmovl_u16(vget_low_u16(low)), vmovl_high_u16(low), vmovl_u16(vget_low_u16(high)), vmovl_high_u16(high)]
+            .map(|values| vcvtq_f32_u32(values))
+    }
+
+    /// Returns `255 / alpha` for every lane, zeroed for transparent pixels
+    /// instead of dividing by zero.
+    unsafe fn scales(alpha: uint8x16_t) -> [float32x4_t; 4] {
+        widen(alpha).map(|alpha| {
+            let scale = vdivq_f32(vdupq_n_f32(255.0), alpha);
+            vreinterpretq_f32_u32(vbicq_u32(vreinterpretq_u32_f32(scale), vceqzq_f32(alpha)))
+        })
+    }
+
+    unsafe fn divide(channel: uint8x16_t, scales: [float32x4_t; 4]) -> uint8x16_t {
+        let max = vdupq_n_f32(255.0);
+        let half = vdupq_n_f32(0.5);
+        let values = widen(channel);
+        let rounded: [uint32x4_t; 4] = std::array::from_fn(|i| {
+            vcvtq_u32_f32(vminq_f32(vaddq_f32(vmulq_f32(values[i], scales[i]), half), max))
+        });
+        let low = vcombine_u16(vmovn_u32(rounded[0]), vmovn_u32(rounded[1]));
+        let high = vcombine_u16(vmovn_u32(rounded[2]), vmovn_u32(rounded[3]));
+        vcombine_u8(vmovn_u16(low), vmovn_u16(high))
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod wasm {
+    use std::arch::wasm32::*;
+
+    pub(super) fn premultiply(data: &mut [u8]) {
+        // Broadcast each pixel's alpha over its colour bytes, as on x86; the
+        // out-of-range indices zero the alpha byte, which then gets 255.
+        let spread = i8x16(3, 3, 3, -1, 7, 7, 7, -1, 11, 11, 11, -1, 15, 15, 15, -1);
+        let keep = u32x4_splat(0xFF00_0000);
+        let round = u16x8_splat(128);
+
+        let mut chunks = data.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            unsafe {
+                let pixels = v128_load(chunk.as_ptr() as *const v128);
+                let factors = v128_or(i8x16_swizzle(pixels, spread), keep);
+                let [low, high] = [u16x8_extmul_low_u8x16(pixels, factors), u16x8_extmul_high_u8x16(pixels, factors)].map(|product| {
+                    let value = u16x8_add(product, round);
+                    u16x8_shr(u16x8_add(value, u16x8_shr(value, 8)), 8)
+                });
+                v128_store(chunk.as_mut_ptr() as *mut v128, u8x16_narrow_i16x8(low, high));
+            }
+        }
+        super::premultiply_scalar(chunks.into_remainder());
+    }
+
+    pub(super) fn unpremultiply(data: &mut [u8]) {
+        let alpha_bytes = u32x4_splat(0xFF00_0000);
+        let max = f32x4_splat(255.0);
+        let half = f32x4_splat(0.5);
+
+        let mut chunks = data.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            unsafe {
+                let pixels = v128_load(chunk.as_ptr() as *const v128);
+                let low = u16x8_extend_low_u8x16(pixels);
+                let high = u16x8_extend_high_u8x16(pixels);
+
+                let [p0, p1, p2, p3] = [
+                    u32x4_extend_low_u16x8(low),
+                    u32x4_extend_high_u16x8(low),
+                    u32x4_extend_low_u16x8(high),
+                    u32x4_extend_high_u16x8(high),
+                ]
+                .map(|pixel| {
+                    let values = f32x4_convert_u32x4(pixel);
+                    let alpha = i32x4_shuffle::<3, 3, 3, 3>(values, values);
+                    // Zero the scale for transparent pixels instead of dividing by zero.
+                    let scale = v128_and(f32x4_div(max, alpha), f32x4_ne(alpha, f32x4_splat(0.0)));
+                    i32x4_trunc_sat_f32x4(f32x4_min(f32x4_add(f32x4_mul(values, scale), half), max))
+                });
+
+                let colours = u8x16_narrow_i16x8(i16x8_narrow_i32x4(p0, p1), i16x8_narrow_i32x4(p2, p3));
+                v128_store(chunk.as_mut_ptr() as *mut v128, v128_bitselect(pixels, colours, alpha_bytes));
+            }
+        }
+        super::unpremultiply_scalar(chunks.into_remainder());
+    }
+}
@@ -0,0 +1,272 @@
+//! Reading of animated cursor (`.ani`) files and their export to APNG and GIF.
+//!
+//! An animated cursor is a RIFF `ACON` container holding an `anih` header,
+//! optional `rate` and `seq ` chunks, and a `LIST` of `fram` whose `icon`
+//! chunks are complete icon or cursor files.
+
+use crate::bytes::{array_at, slice_at, u16_at, u32_at};
+use crate::cur::{self, CURSOR_TYPE};
+use crate::error::{IconError, Stage};
+use crate::ico::{self, PNG_SIGNATURE};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{imageops, Delay, Frame, RgbaImage};
+use std::path::Path;
+use std::time::Duration;
+
+/// `anih` flag: frames are icon or cursor files rather than raw bitmaps.
+const AF_ICON: u32 = 0x1;
+const ANIH_SIZE: usize = 36;
+/// Display rates are counted in jiffies.
+const JIFFIES_PER_SECOND: u64 = 60;
+/// GIF delays are a `u16` count of centiseconds.
+const MAX_GIF_DELAY: Duration = Duration::from_millis(u16::MAX as u64 * 10);
+
+/// One step of an animation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AniFrame {
+    pub image: RgbaImage,
+    /// Click point; the centre for frames stored as icons.
+    pub hotspot: (u32, u32),
+    /// How long the frame stays on screen.
+    pub duration: Duration,
+}
+
+/// The frames of an animated cursor in playback order.
+///
+/// Frames the sequence shows more than once appear once per showing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnimatedCursor {
+    pub frames: Vec<AniFrame>,
+}
+
+struct Chunk<'a> {
+    id: [u8; 4],
+    data: &'a [u8],
+}
+
+/// Splits the body of a RIFF container or list into its word-aligned chunks.
+fn chunks(mut data: &[u8]) -> Result<Vec<Chunk<'_>>, IconError> {
+    let truncated = || IconError::Truncated { stage: Stage::RiffChunk };
+
+    let mut chunks = Vec::new();
+    while !data.is_empty() {
+        let id = array_at(data, 0).ok_or_else(truncated)?;
+        let len = u32_at(data, 4).ok_or_else(truncated)? as usize;
+        chunks.push(Chunk { id, data: slice_at(data, 8, len).ok_or_else(truncated)? });
+        data = data.get(8 + len + len % 2..).unwrap_or_default();
+    }
+    Ok(chunks)
+}
+
+fn u32_list(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4).map(|value| u32::from_le_bytes(value.try_into().unwrap())).collect()
+}
+
+fn jiffies(count: u32) -> Duration {
+    Duration::from_nanos(u64::from(count) * 1_000_000_000 / JIFFIES_PER_SECOND)
+}
+
+/// Decodes the largest image of an embedded icon or cursor file.
+fn decode_frame(data: &[u8]) -> Result<(RgbaImage, (u32, u32)), IconError> {
+    if u16_at(data, 2) == Some(CURSOR_TYPE) {
+        let cursor = cur::parse(data)?;
+        let largest = (0..cursor.images.len())
+            .max_by_key(|&index| u64::from(cursor.images[index].width()) * u64::from(cursor.images[index].height()))
+            .ok_or(IconError::Malformed { stage: Stage::IconDirectory })?;
+        return Ok((cursor.images[largest].clone(), cursor.hotspots[largest]));
+    }
+
+    let entries = ico::parse(data)?;
+    let image = ico::select_entry(&entries, u32::MAX)
+        .ok_or(IconError::Malformed { stage: Stage::IconDirectory })?
+        .image
+        .clone();
+    let hotspot = (image.width() / 2, image.height() / 2);
+    Ok((image, hotspot))
+}
+
+/// Parses an animated cursor held in memory and decodes every frame.
+pub fn parse(data: &[u8]) -> Result<AnimatedCursor, IconError> {
+    let malformed_header = IconError::Malformed { stage: Stage::AniHeader };
+
+    if data.len() < 12 {
+        return Err(IconError::Truncated { stage: Stage::RiffChunk });
+    }
+    if &data[0..4] != b"RIFF" || &data[8..12] != b"ACON" {
+        return Err(IconError::Malformed { stage: Stage::RiffChunk });
+    }
+    // Some writers get the RIFF size wrong, so never read past either end.
+    let riff_len = (u32_at(data, 4).unwrap() as usize).max(4);
+    let body = &data[12..data.len().min(riff_len.saturating_add(8))];
+
+    let mut header = None;
+    let mut rates = None;
+    let mut sequence = None;
+    let mut icons = Vec::new();
+    for chunk in chunks(body)? {
+        match &chunk.id {
+            b"anih" => header = Some(slice_at(chunk.data, 0, ANIH_SIZE).ok_or(IconError::Truncated { stage: Stage::AniHeader })?),
+            b"rate" => rates = Some(u32_list(chunk.data)),
+            b"seq " => sequence = Some(u32_list(chunk.data)),
+            b"LIST" if chunk.data.starts_with(b"fram") => {
+                icons.extend(chunks(&chunk.data[4..])?.into_iter().filter(|icon| &icon.id == b"icon").map(|icon| icon.data));
+            }
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or(IconError::Malformed { stage: Stage::AniHeader })?;
+    let display_rate = u32_at(header, 28).unwrap();
+    if u32_at(header, 32).unwrap() & AF_ICON == 0 {
+        // Raw bitmap frames are not produced by any known tool.
+        return Err(malformed_header);
+    }
+
+    let images = icons.into_iter().map(decode_frame).collect::<Result<Vec<_>, IconError>>()?;
+    if images.is_empty() {
+        return Err(malformed_header);
+    }
+
+    let sequence = sequence.unwrap_or_else(|| (0..images.len() as u32).collect());
+    if rates.as_ref().is_some_and(|rates| rates.len() < sequence.len()) {
+        return Err(malformed_header);
+    }
+
+    let frames = sequence
+        .iter()
+        .enumerate()
+        .map(|(step, &index)| {
+            let (image, hotspot) = images.get(index as usize).ok_or(IconError::Malformed { stage: Stage::AniHeader })?;
+            let rate = rates.as_ref().map_or(display_rate, |rates| rates[step]);
+            Ok(AniFrame { image: image.clone(), hotspot: *hotspot, duration: jiffies(rate) })
+        })
+        .collect::<Result<Vec<_>, IconError>>()?;
+
+    Ok(AnimatedCursor { frames })
+}
+
+/// Reads and decodes the animated cursor at `path`.
+pub fn read(path: &Path) -> Result<AnimatedCursor, IconError> {
+    let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+    parse(&data)
+}
+
+/// Returns every frame drawn at the top-left of a transparent canvas large enough for all of them.
+///
+/// An animation without frames has nothing to encode and is rejected.
+fn canvas_frames(cursor: &AnimatedCursor) -> Result<Vec<(RgbaImage, Duration)>, IconError> {
+    if cursor.frames.is_empty() {
+        return Err(IconError::Malformed { stage: Stage::AniHeader });
+    }
+    let width = cursor.frames.iter().map(|frame| frame.image.width()).max().unwrap_or(0);
+    let height = cursor.frames.iter().map(|frame| frame.image.height()).max().unwrap_or(0);
+
+    Ok(cursor
+        .frames
+        .iter()
+        .map(|frame| {
+            if frame.image.dimensions() == (width, height) {
+                return (frame.image.clone(), frame.duration);
+            }
+            let mut canvas = RgbaImage::new(width, height);
+            imageops::replace(&mut canvas, &frame.image, 0, 0);
+            (canvas, frame.duration)
+        })
+        .collect())
+}
+
+/// Encodes the animation as a looping GIF.
+///
+/// GIF timing has a resolution of 10ms and transparency is one bit deep.
+pub fn encode_gif(cursor: &AnimatedCursor) -> Result<Vec<u8>, IconError> {
+    let mut data = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut data);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(
+            canvas_frames(cursor)?
+                .into_iter()
+                .map(|(image, duration)| Frame::from_parts(image, 0, 0, Delay::from_saturating_duration(duration.min(MAX_GIF_DELAY)))),
+        )?;
+    }
+    Ok(data)
+}
+
+/// Encodes the animation as a looping APNG.
+///
+/// Frames are encoded as ordinary PNGs and their image data re-wrapped in
+/// APNG frame chunks, as the `image` crate cannot write animated PNGs itself.
+pub fn encode_apng(cursor: &AnimatedCursor) -> Result<Vec<u8>, IconError> {
+    let frames = canvas_frames(cursor)?;
+    let frame_count = u32::try_from(frames.len()).map_err(|_| IconError::Malformed { stage: Stage::AniHeader })?;
+
+    let mut data = PNG_SIGNATURE.to_vec();
+    let mut sequence = 0u32;
+    for (index, (image, duration)) in frames.iter().enumerate() {
+        let png = ico::encode_png(image)?;
+        let chunks = png_chunks(&png);
+
+        if index == 0 {
+            if let Some(header) = chunks.iter().find(|(id, _)| id == b"IHDR") {
+                write_png_chunk(&mut data, b"IHDR", header.1);
+            }
+            write_png_chunk(&mut data, b"acTL", &[frame_count.to_be_bytes(), 0u32.to_be_bytes()].concat());
+        }
+
+        let delay_ms = duration.as_millis().min(u128::from(u16::MAX)) as u16;
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&sequence.to_be_bytes());
+        control.extend_from_slice(&image.width().to_be_bytes());
+        control.extend_from_slice(&image.height().to_be_bytes());
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&delay_ms.to_be_bytes());
+        control.extend_from_slice(&1000u16.to_be_bytes());
+        // Leave the canvas as is afterwards, and replace rather than blend.
+        control.extend_from_slice(&[0, 0]);
+        write_png_chunk(&mut data, b"fcTL", &control);
+        sequence += 1;
+
+        for (_, image_data) in chunks.iter().filter(|(id, _)| id == b"IDAT") {
+            if index == 0 {
+                write_png_chunk(&mut data, b"IDAT", image_data);
+            } else {
+                write_png_chunk(&mut data, b"fdAT", &[&sequence.to_be_bytes()[..], image_data].concat());
+                sequence += 1;
+            }
+        }
+    }
+    write_png_chunk(&mut data, b"IEND", &[]);
+    Ok(data)
+}
+
+/// Splits a PNG file written by the `image` crate into its chunks.
+fn png_chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut offset = PNG_SIGNATURE.len();
+    while let (Some(len), Some(id)) = (array_at(png, offset), array_at(png, offset + 4)) {
+        let len = u32::from_be_bytes(len) as usize;
+        let Some(data) = slice_at(png, offset + 8, len) else { break };
+        chunks.push((id, data));
+        offset += 12 + len;
+    }
+    chunks
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(id);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc32(&[id, data]).to_be_bytes());
+}
+
+/// The CRC-32 that PNG chunks end with.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in parts.iter().copied().flatten() {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
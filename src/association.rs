@@ -0,0 +1,151 @@
+//! File type associations read from registry data: the chain from an
+//! extension through its ProgID to the `DefaultIcon` its files are shown with.
+//!
+//! The classes of `HKEY_CLASSES_ROOT` are a merged view of the user's
+//! `HKCU\Software\Classes` over the machine's `HKLM\Software\Classes`, so
+//! several class roots are consulted in turn.
+
+use crate::error::IconError;
+use crate::location::{IconFile, IconLocation};
+use crate::provider::normalize_extension;
+use crate::registry::Registry;
+use crate::size::IconSize;
+use crate::windows_tree::WindowsTree;
+use image::RgbaImage;
+use std::collections::{HashMap, HashSet};
+
+/// Explorer's per-user record of each extension, below `HKCU`.
+const FILE_EXTS: &str = r"Software\Microsoft\Windows\CurrentVersion\Explorer\FileExts";
+const SYSTEM_FILE_ASSOCIATIONS: &str = "SystemFileAssociations";
+const CONTENT_TYPES: &str = r"MIME\Database\Content Type";
+
+/// A registry key serving as the root of some part of the association data.
+struct Root<'a> {
+    registry: Box<dyn Registry + 'a>,
+    path: String,
+}
+
+impl Root<'_> {
+    fn join(&self, key: &str) -> String {
+        match self.path.as_str() {
+            "" => key.to_string(),
+            path => format!("{path}\\{key}"),
+        }
+    }
+
+    fn string(&self, key: &str, name: &str) -> Result<Option<String>, IconError> {
+        Ok(self.registry.string(&self.join(key), name)?.filter(|value| !value.is_empty()))
+    }
+
+    fn value_names(&self, key: &str) -> Result<Vec<String>, IconError> {
+        Ok(self.registry.value_names(&self.join(key))?.unwrap_or_default())
+    }
+}
+
+/// Resolves the icons of file extensions from one or more registries.
+///
+/// For a `SOFTWARE` hive the classes root is `Classes`; for `UsrClass.dat` it
+/// is the hive's root. The user root, such as the root of `NTUSER.DAT`, holds
+/// the choices made through "Open with".
+#[derive(Default)]
+pub struct Associations<'a> {
+    classes: Vec<Root<'a>>,
+    user: Option<Root<'a>>,
+}
+
+impl<'a> Associations<'a> {
+    pub fn new() -> Associations<'a> {
+        Associations::default()
+    }
+
+    /// Adds a classes root, consulted after those added before it.
+    pub fn push_classes(&mut self, registry: impl Registry + 'a, path: &str) {
+        self.classes.push(Root { registry: Box::new(registry), path: path.to_string() });
+    }
+
+    /// Sets the root of the user's settings, the equivalent of `HKCU`.
+    pub fn set_user(&mut self, registry: impl Registry + 'a, path: &str) {
+        self.user = Some(Root { registry: Box::new(registry), path: path.to_string() });
+    }
+
+    /// Returns the first string value of `key` in the classes roots.
+    fn class_string(&self, key: &str, name: &str) -> Result<Option<String>, IconError> {
+        for root in &self.classes {
+            if let Some(value) = root.string(key, name)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the ProgIDs that may supply an extension's icon, most preferred first.
+    ///
+    /// These are the user's choice from "Open with", the extension's default
+    /// value, the ProgIDs listed under `OpenWithProgids`, and finally the
+    /// extension key itself. The hash guarding `UserChoice` is not verified.
+    pub fn prog_ids(&self, extension: &str) -> Result<Vec<String>, IconError> {
+        let extension = format!(".{}", normalize_extension(extension));
+        let user_key = format!("{FILE_EXTS}\\{extension}");
+        let mut prog_ids = Vec::new();
+
+        if let Some(user) = &self.user {
+            prog_ids.extend(user.string(&format!("{user_key}\\UserChoice"), "ProgId")?);
+        }
+        prog_ids.extend(self.class_string(&extension, "")?);
+        for root in &self.classes {
+            prog_ids.extend(root.value_names(&format!("{extension}\\OpenWithProgids"))?);
+        }
+        if let Some(user) = &self.user {
+            prog_ids.extend(user.value_names(&format!("{user_key}\\OpenWithProgids"))?);
+        }
+        prog_ids.push(extension);
+
+        let mut seen = HashSet::new();
+        prog_ids.retain(|prog_id| !prog_id.is_empty() && seen.insert(prog_id.to_lowercase()));
+        Ok(prog_ids)
+    }
+
+    /// Returns the extension registered for a MIME type, with its leading dot.
+    pub fn extension_for_mime(&self, mime: &str) -> Result<Option<String>, IconError> {
+        self.class_string(&format!("{CONTENT_TYPES}\\{mime}"), "Extension")
+    }
+
+    /// Returns the unparsed `DefaultIcon` value that applies to an extension.
+    ///
+    /// The first of [`prog_ids`](Self::prog_ids) with a `DefaultIcon` wins.
+    /// Failing that, `SystemFileAssociations` is consulted for the extension
+    /// and then for its `PerceivedType`.
+    pub fn default_icon(&self, extension: &str) -> Result<Option<String>, IconError> {
+        for prog_id in self.prog_ids(extension)? {
+            if let Some(icon) = self.class_string(&format!("{prog_id}\\DefaultIcon"), "")? {
+                return Ok(Some(icon));
+            }
+        }
+
+        let extension = format!(".{}", normalize_extension(extension));
+        let mut fallbacks = vec![extension.clone()];
+        fallbacks.extend(self.class_string(&extension, "PerceivedType")?);
+        for key in fallbacks {
+            if let Some(icon) = self.class_string(&format!("{SYSTEM_FILE_ASSOCIATIONS}\\{key}\\DefaultIcon"), "")? {
+                return Ok(Some(icon));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the parsed icon location for an extension, expanding variables from `environment`.
+    pub fn icon_location(&self, extension: &str, environment: &HashMap<String, String>) -> Result<Option<IconLocation>, IconError> {
+        self.default_icon(extension)?.map(|icon| IconLocation::parse(&icon, environment)).transpose()
+    }
+
+    /// Decodes the icon for an extension from the files of a mounted Windows tree.
+    ///
+    /// Types whose icon is `%1`, which differs from file to file, have no
+    /// icon for the extension alone.
+    pub fn load_icon_for_extension(&self, extension: &str, tree: &WindowsTree, size: IconSize) -> Result<RgbaImage, IconError> {
+        match self.icon_location(extension, &tree.environment())? {
+            Some(location) if location.file != IconFile::Itself => tree.load_icon_location(&location, "", size),
+            _ => Err(IconError::NoIconForExtension(extension.to_string())),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Drive icons customised through `autorun.inf` on removable media.
+//!
+//! The `icon` key of the `[autorun]` section names a file relative to the
+//! root of the medium, optionally followed by an icon index. 64-bit Windows
+//! reads `[autorun.amd64]` first and falls back to `[autorun]`.
+
+use crate::desktop_ini::load_icon_near;
+use crate::error::IconError;
+use crate::ini::{decode_text, Ini};
+use crate::location::{IconFile, IconLocation};
+use crate::provider::IconProvider;
+use crate::size::IconSize;
+use crate::windows_tree::resolve_component;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const FILE_NAME: &str = "autorun.inf";
+const SECTION: &str = "autorun";
+const AMD64_SECTION: &str = "autorun.amd64";
+
+/// The icon settings of an `autorun.inf` file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Autorun {
+    /// `icon` from `[autorun]`.
+    pub icon: Option<String>,
+    /// `icon` from `[autorun.amd64]`.
+    pub amd64_icon: Option<String>,
+}
+
+impl Autorun {
+    /// Parses an `autorun.inf` file held in memory.
+    pub fn parse(data: &[u8]) -> Autorun {
+        let ini = Ini::parse(&decode_text(data));
+        let icon = |section| ini.get(section, "icon").filter(|value| !value.is_empty()).map(str::to_string);
+        Autorun { icon: icon(SECTION), amd64_icon: icon(AMD64_SECTION) }
+    }
+
+    /// Reads and parses the `autorun.inf` file at `path`.
+    pub fn read(path: &Path) -> Result<Autorun, IconError> {
+        let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+        Ok(Autorun::parse(&data))
+    }
+
+    /// Returns the drive's icon location, preferring `[autorun.amd64]` if `amd64` is set.
+    ///
+    /// The path is made relative to the root of the medium, so a leading
+    /// backslash is dropped. Environment variables are not expanded.
+    pub fn icon_location(&self, amd64: bool) -> Result<Option<IconLocation>, IconError> {
+        let icon = if amd64 { self.amd64_icon.as_ref().or(self.icon.as_ref()) } else { self.icon.as_ref() };
+        let Some(icon) = icon else { return Ok(None) };
+        let mut location = IconLocation::parse(icon, &HashMap::new())?;
+        if let IconFile::Path(path) = &mut location.file {
+            *path = path.trim_start_matches(['\\', '/']).to_string();
+        }
+        Ok(Some(location))
+    }
+}
+
+/// Returns the icon `autorun.inf` gives the medium mounted at `root`, as 64-bit Windows shows it.
+pub fn drive_icon(root: &Path, size: IconSize) -> Result<Option<RgbaImage>, IconError> {
+    let inf = resolve_component(root, FILE_NAME);
+    if !inf.is_file() {
+        return Ok(None);
+    }
+
+    let Some(location) = Autorun::read(&inf)?.icon_location(true)? else { return Ok(None) };
+    load_icon_near(root, &location, "", None, size).map(Some)
+}
+
+/// Serves drive icons customised by `autorun.inf` for the media mounted at
+/// `roots`, deferring to another provider for everything else.
+///
+/// Drives whose custom icon cannot be loaded get the inner provider's icon.
+pub struct AutorunIconProvider<P> {
+    inner: P,
+    roots: Vec<PathBuf>,
+}
+
+impl<P: IconProvider> AutorunIconProvider<P> {
+    pub fn new(inner: P, roots: Vec<PathBuf>) -> AutorunIconProvider<P> {
+        AutorunIconProvider { inner, roots }
+    }
+}
+
+impl<P: IconProvider> IconProvider for AutorunIconProvider<P> {
+    fn icon_for_path(&self, path: &Path, size: IconSize) -> Result<RgbaImage, IconError> {
+        if self.roots.iter().any(|root| root.components().eq(path.components())) {
+            if let Ok(Some(image)) = drive_icon(path, size) {
+                return Ok(image);
+            }
+        }
+        self.inner.icon_for_path(path, size)
+    }
+
+    fn icon_for_extension(&self, extension: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        self.inner.icon_for_extension(extension, size)
+    }
+
+    fn icon_for_mime(&self, mime: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        self.inner.icon_for_mime(mime, size)
+    }
+}
@@ -0,0 +1,39 @@
+//! Bounds-checked little-endian reads used by the binary format parsers.
+
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(array_at(data, offset)?))
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(array_at(data, offset)?))
+}
+
+pub(crate) fn i32_at(data: &[u8], offset: usize) -> Option<i32> {
+    Some(i32::from_le_bytes(array_at(data, offset)?))
+}
+
+pub(crate) fn array_at<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+pub(crate) fn slice_at(data: &[u8], offset: usize, len: usize) -> Option<&[u8]> {
+    data.get(offset..offset.checked_add(len)?)
+}
+
+/// Returns the bytes of a NUL-terminated string of single-byte characters, without the NUL.
+pub(crate) fn ansi_string_at(data: &[u8], offset: usize) -> Option<&[u8]> {
+    let bytes = data.get(offset..)?;
+    let len = bytes.iter().position(|&byte| byte == 0)?;
+    Some(&bytes[..len])
+}
+
+/// Reads up to `units` UTF-16 code units, stopping early at a NUL.
+pub(crate) fn utf16_at(data: &[u8], offset: usize, units: usize) -> Option<String> {
+    let bytes = slice_at(data, offset, units.checked_mul(2)?)?;
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    Some(String::from_utf16_lossy(&units))
+}
@@ -0,0 +1,188 @@
+//! Conversion between packed pixel layouts.
+//!
+//! Conversions between the four 32-bit channel orders and from 24-bit RGB/BGR
+//! to any 32-bit order go through the SIMD paths in [`crate::swizzle`]; every
+//! other pair goes through a scalar per-pixel loop. 16-bit layouts are read
+//! and written little-endian, as GDI stores them.
+
+use crate::error::IconError;
+use crate::swizzle::{self, OPAQUE};
+
+/// Byte layout of a single pixel in a packed buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PixelLayout {
+    /// 8-bit red, green, blue, alpha.
+    Rgba,
+    /// 8-bit blue, green, red, alpha, as used by GDI and Direct2D.
+    Bgra,
+    /// 8-bit alpha, red, green, blue.
+    Argb,
+    /// 8-bit alpha, blue, green, red.
+    Abgr,
+    /// 8-bit red, green, blue with no alpha.
+    Rgb,
+    /// 8-bit blue, green, red with no alpha, as in 24-bit DIBs.
+    Bgr,
+    /// 16-bit 5-6-5 red, green, blue.
+    Rgb565,
+    /// 16-bit 5-5-5 red, green, blue with the top bit unused.
+    Rgb555,
+}
+
+impl PixelLayout {
+    /// Returns the number of bytes a single pixel occupies.
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelLayout::Rgba | PixelLayout::Bgra | PixelLayout::Argb | PixelLayout::Abgr => 4,
+            PixelLayout::Rgb | PixelLayout::Bgr => 3,
+            PixelLayout::Rgb565 | PixelLayout::Rgb555 => 2,
+        }
+    }
+
+    /// Byte offsets of red, green, blue and alpha for byte-per-channel layouts.
+    fn offsets(self) -> Option<[u8; 4]> {
+        match self {
+            PixelLayout::Rgba => Some([0, 1, 2, 3]),
+            PixelLayout::Bgra => Some([2, 1, 0, 3]),
+            PixelLayout::Argb => Some([1, 2, 3, 0]),
+            PixelLayout::Abgr => Some([3, 2, 1, 0]),
+            PixelLayout::Rgb => Some([0, 1, 2, OPAQUE]),
+            PixelLayout::Bgr => Some([2, 1, 0, OPAQUE]),
+            PixelLayout::Rgb565 | PixelLayout::Rgb555 => None,
+        }
+    }
+
+    /// Reads one pixel as `[r, g, b, a]`.
+    fn read(self, pixel: &[u8]) -> [u8; 4] {
+        match self {
+            PixelLayout::Rgb565 => {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                [expand5(value >> 11), expand6(value >> 5), expand5(value), 0xFF]
+            }
+            PixelLayout::Rgb555 => {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                [expand5(value >> 10), expand5(value >> 5), expand5(value), 0xFF]
+            }
+            _ => {
+                let offsets = self.offsets().unwrap();
+                offsets.map(|offset| if offset == OPAQUE { 0xFF } else { pixel[offset as usize] })
+            }
+        }
+    }
+
+    /// Writes one `[r, g, b, a]` pixel.
+    fn write(self, rgba: [u8; 4], pixel: &mut [u8]) {
+        let [r, g, b, _] = rgba.map(u16::from);
+        match self {
+            PixelLayout::Rgb565 => {
+                let value = (r >> 3) << 11 | (g >> 2) << 5 | b >> 3;
+                pixel.copy_from_slice(&value.to_le_bytes());
+            }
+            PixelLayout::Rgb555 => {
+                let value = (r >> 3) << 10 | (g >> 3) << 5 | b >> 3;
+                pixel.copy_from_slice(&value.to_le_bytes());
+            }
+            _ => {
+                let offsets = self.offsets().unwrap();
+                for (&channel, &offset) in rgba.iter().zip(&offsets) {
+                    if offset != OPAQUE {
+                        pixel[offset as usize] = channel;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Converts `src` from `src_layout` into the caller-supplied `dst` buffer in `dst_layout`.
+///
+/// `src` must hold a whole number of pixels and `dst` must be exactly large
+/// enough for the same number of pixels in the destination layout.
+pub fn convert(
+    src: &[u8],
+    src_layout: PixelLayout,
+    dst: &mut [u8],
+    dst_layout: PixelLayout,
+) -> Result<(), IconError> {
+    let src_bpp = src_layout.bytes_per_pixel();
+    let dst_bpp = dst_layout.bytes_per_pixel();
+
+    if !src.len().is_multiple_of(src_bpp) {
+        return Err(IconError::BufferSize { expected: src.len() / src_bpp * src_bpp, actual: src.len() });
+    }
+    if dst.len() != src.len() / src_bpp * dst_bpp {
+        return Err(IconError::BufferSize { expected: src.len() / src_bpp * dst_bpp, actual: dst.len() });
+    }
+
+    match (src_layout.offsets(), dst_layout.offsets()) {
+        (Some(from), Some(to)) if src_bpp == 4 && dst_bpp == 4 => {
+            dst.copy_from_slice(src);
+            if from != to {
+                swizzle::permute(dst, shuffle_order(from, to));
+            }
+        }
+        (Some(from), Some(to)) if src_bpp == 3 && dst_bpp == 4 => {
+            swizzle::expand(src, dst, shuffle_order(from, to));
+        }
+        _ => {
+            for (source, pixel) in src.chunks_exact(src_bpp).zip(dst.chunks_exact_mut(dst_bpp)) {
+                dst_layout.write(src_layout.read(source), pixel);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts `data` from `from` to `to` in place; both layouts must have the same pixel size.
+pub fn convert_in_place(
+    data: &mut [u8],
+    from: PixelLayout,
+    to: PixelLayout,
+) -> Result<(), IconError> {
+    let bpp = from.bytes_per_pixel();
+
+    if bpp != to.bytes_per_pixel() {
+        return Err(IconError::IncompatibleLayouts { from, to });
+    }
+    if !data.len().is_multiple_of(bpp) {
+        return Err(IconError::BufferSize { expected: data.len() / bpp * bpp, actual: data.len() });
+    }
+
+    match (from.offsets(), to.offsets()) {
+        (Some(src), Some(dst)) if bpp == 4 => {
+            if src != dst {
+                swizzle::permute(data, shuffle_order(src, dst));
+            }
+        }
+        _ => {
+            for pixel in data.chunks_exact_mut(bpp) {
+                let rgba = from.read(pixel);
+                to.write(rgba, pixel);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the per-pixel byte order that moves each channel from its `from` offset to its `to` offset.
+fn shuffle_order(from: [u8; 4], to: [u8; 4]) -> [u8; 4] {
+    let mut order = [OPAQUE; 4];
+    for (&source, &target) in from.iter().zip(&to) {
+        if target != OPAQUE {
+            order[target as usize] = source;
+        }
+    }
+    order
+}
+
+fn expand5(value: u16) -> u8 {
+    let value = (value & 0x1F) as u8;
+    value << 3 | value >> 2
+}
+
+fn expand6(value: u16) -> u8 {
+    let value = (value & 0x3F) as u8;
+    value << 2 | value >> 4
+}
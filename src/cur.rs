@@ -0,0 +1,71 @@
+//! Reading and writing of `.cur` files.
+//!
+//! Cursor files share the icon file layout, except that each directory entry
+//! stores the image's hotspot where an icon stores its planes and bit depth.
+
+use crate::error::IconError;
+use crate::ico::{self, EncodedEntry};
+use image::RgbaImage;
+use std::path::Path;
+
+/// `ICONDIR` resource type for cursors.
+pub(crate) const CURSOR_TYPE: u16 = 2;
+
+/// The images of a cursor file and the click point of each.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cursor {
+    pub images: Vec<RgbaImage>,
+    /// Hotspot of the image at the same index, in pixels from its top-left corner.
+    pub hotspots: Vec<(u32, u32)>,
+}
+
+/// Parses a cursor file held in memory and decodes every entry.
+pub fn parse(data: &[u8]) -> Result<Cursor, IconError> {
+    let mut cursor = Cursor::default();
+    for entry in ico::parse_directory(data, CURSOR_TYPE)? {
+        let (_, _, image) = ico::decode_entry(entry.data)?;
+        cursor.images.push(image);
+        cursor.hotspots.push((u32::from(entry.planes), u32::from(entry.bit_count)));
+    }
+    Ok(cursor)
+}
+
+/// Reads and decodes every entry of the cursor file at `path`.
+pub fn read(path: &Path) -> Result<Cursor, IconError> {
+    let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+    parse(&data)
+}
+
+/// Encodes a cursor file, storing 256px images as PNG and the rest as 32-bpp BMP.
+///
+/// Every image needs a hotspot that lies within it.
+pub fn encode(cursor: &Cursor) -> Result<Vec<u8>, IconError> {
+    if cursor.images.len() != cursor.hotspots.len() {
+        return Err(IconError::HotspotCount { images: cursor.images.len(), hotspots: cursor.hotspots.len() });
+    }
+
+    let entries = cursor
+        .images
+        .iter()
+        .zip(&cursor.hotspots)
+        .map(|(image, &(x, y))| {
+            ico::check_dimensions(image)?;
+            let (width, height) = image.dimensions();
+            if x >= width || y >= height {
+                return Err(IconError::InvalidHotspot { hotspot: (x, y), width, height });
+            }
+
+            let data = ico::encode_as(image, ico::default_format(image))?;
+            // Dimensions are at most 256, so the hotspot fits the 16-bit fields.
+            Ok(EncodedEntry { width, height, color_count: 0, planes: x as u16, bit_count: y as u16, data })
+        })
+        .collect::<Result<Vec<_>, IconError>>()?;
+
+    ico::write_directory(CURSOR_TYPE, &entries)
+}
+
+/// Encodes a cursor with [`encode`] and writes it to `path`.
+pub fn write(path: &Path, cursor: &Cursor) -> Result<(), IconError> {
+    let data = encode(cursor)?;
+    std::fs::write(path, data).map_err(|source| IconError::Io { path: path.to_path_buf(), source })
+}
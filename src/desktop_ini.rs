@@ -0,0 +1,145 @@
+//! Folder icons customised through `desktop.ini`.
+//!
+//! Windows Vista and later read `IconResource` from the `[.ShellClassInfo]`
+//! section; earlier versions read `IconFile` and `IconIndex`, which are still
+//! honoured when `IconResource` is absent.
+
+use crate::error::{IconError, Stage};
+use crate::ini::{decode_text, Ini};
+use crate::location::{expand_environment, IconFile, IconLocation};
+use crate::provider::IconProvider;
+use crate::resource;
+use crate::size::IconSize;
+use crate::windows_tree::{resolve_component, WindowsTree};
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::path::Path;
+
+const FILE_NAME: &str = "desktop.ini";
+const SECTION: &str = ".ShellClassInfo";
+
+/// The icon settings of a `desktop.ini` file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DesktopIni {
+    /// `IconResource`: a complete icon location.
+    pub icon_resource: Option<String>,
+    /// `IconFile`: the file holding the icon, paired with `icon_index`.
+    pub icon_file: Option<String>,
+    /// `IconIndex`, or 0 if absent or not a number.
+    pub icon_index: i32,
+}
+
+impl DesktopIni {
+    /// Parses a `desktop.ini` file held in memory, in any encoding Windows writes.
+    pub fn parse(data: &[u8]) -> DesktopIni {
+        let ini = Ini::parse(&decode_text(data));
+        let value = |key| ini.get(SECTION, key).filter(|value| !value.is_empty()).map(str::to_string);
+        DesktopIni {
+            icon_resource: value("IconResource"),
+            icon_file: value("IconFile"),
+            icon_index: ini.get(SECTION, "IconIndex").and_then(|index| index.trim().parse().ok()).unwrap_or(0),
+        }
+    }
+
+    /// Reads and parses the `desktop.ini` file at `path`.
+    pub fn read(path: &Path) -> Result<DesktopIni, IconError> {
+        let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+        Ok(DesktopIni::parse(&data))
+    }
+
+    /// Returns the folder's icon location, expanding variables from `environment`.
+    pub fn icon_location(&self, environment: &HashMap<String, String>) -> Result<Option<IconLocation>, IconError> {
+        if let Some(resource) = &self.icon_resource {
+            return IconLocation::parse(resource, environment).map(Some);
+        }
+        Ok(self.icon_file.as_ref().map(|file| IconLocation {
+            file: IconFile::Path(expand_environment(file, environment)),
+            index: self.icon_index,
+        }))
+    }
+}
+
+/// Returns true if a Windows path names neither a drive, a share nor an unexpanded variable.
+fn is_relative(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    !(bytes.get(1) == Some(&b':') || path.starts_with(['\\', '/']) || path.contains('%'))
+}
+
+/// Loads the icon at `location`, resolving relative paths against the host directory `base`
+/// and anything else within `tree`.
+///
+/// Relative paths come from files on the scanned volume, so ones that would
+/// leave `base` through `..` or a drive-qualified component are refused.
+pub(crate) fn load_icon_near(
+    base: &Path,
+    location: &IconLocation,
+    document: &str,
+    tree: Option<&WindowsTree>,
+    size: IconSize,
+) -> Result<RgbaImage, IconError> {
+    let path = location.path(document);
+    if is_relative(path) {
+        let components: Vec<&str> = path.split(['\\', '/']).filter(|component| !matches!(*component, "" | ".")).collect();
+        if components.iter().any(|component| *component == ".." || component.contains(':')) {
+            return Err(IconError::Malformed { stage: Stage::IconLocation });
+        }
+        let host = components.into_iter().fold(base.to_path_buf(), |dir, component| resolve_component(&dir, component));
+        if host.is_file() {
+            return resource::load_icon_as_image(&host, location.index, size);
+        }
+    }
+
+    match tree {
+        Some(tree) => tree.load_icon_as_image(path, location.index, size),
+        None => Err(IconError::Io { path: base.join(path), source: std::io::ErrorKind::NotFound.into() }),
+    }
+}
+
+/// Serves folder icons customised by `desktop.ini`, deferring to another
+/// provider for everything else.
+///
+/// Relative icon paths are resolved against the folder. Absolute ones are
+/// resolved in `tree` when one is given, which also supplies the environment
+/// for variable expansion. Folders whose custom icon cannot be loaded get the
+/// inner provider's icon, as Windows falls back to the generic folder icon.
+pub struct FolderIconProvider<P> {
+    inner: P,
+    tree: Option<WindowsTree>,
+}
+
+impl<P: IconProvider> FolderIconProvider<P> {
+    pub fn new(inner: P, tree: Option<WindowsTree>) -> FolderIconProvider<P> {
+        FolderIconProvider { inner, tree }
+    }
+
+    /// Returns the custom icon `desktop.ini` gives the folder at `path`.
+    pub fn folder_icon(&self, path: &Path, size: IconSize) -> Result<Option<RgbaImage>, IconError> {
+        let ini = resolve_component(path, FILE_NAME);
+        if !ini.is_file() {
+            return Ok(None);
+        }
+
+        let environment = self.tree.as_ref().map(WindowsTree::environment).unwrap_or_default();
+        let Some(location) = DesktopIni::read(&ini)?.icon_location(&environment)? else { return Ok(None) };
+        load_icon_near(path, &location, &path.to_string_lossy(), self.tree.as_ref(), size).map(Some)
+    }
+}
+
+impl<P: IconProvider> IconProvider for FolderIconProvider<P> {
+    fn icon_for_path(&self, path: &Path, size: IconSize) -> Result<RgbaImage, IconError> {
+        if path.is_dir() {
+            if let Ok(Some(image)) = self.folder_icon(path, size) {
+                return Ok(image);
+            }
+        }
+        self.inner.icon_for_path(path, size)
+    }
+
+    fn icon_for_extension(&self, extension: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        self.inner.icon_for_extension(extension, size)
+    }
+
+    fn icon_for_mime(&self, mime: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        self.inner.icon_for_mime(mime, size)
+    }
+}
@@ -0,0 +1,257 @@
+//! Platform-independent decoding of device-independent bitmaps.
+//!
+//! Handles every header revision from `BITMAPCOREHEADER` to `BITMAPV5HEADER`,
+//! 1/4/8-bpp palettes, 16/32-bpp `BI_BITFIELDS` masks, 24-bpp and 32-bpp pixels,
+//! and both bottom-up and top-down row order. The fourth byte of 32-bpp
+//! `BI_RGB` pixels is read as alpha, as icons use it.
+
+use crate::bytes::{i32_at, slice_at, u16_at, u32_at};
+use crate::convert::{convert, PixelLayout};
+use crate::error::{IconError, Stage};
+use crate::geometry::BitmapGeometry;
+use crate::swizzle::bgra_to_rgba;
+use image::RgbaImage;
+
+const CORE_HEADER_SIZE: u32 = 12;
+const INFO_HEADER_SIZE: u32 = 40;
+
+/// Uncompressed pixels.
+pub const BI_RGB: u32 = 0;
+/// 16/32-bpp pixels described by red, green and blue masks.
+pub const BI_BITFIELDS: u32 = 3;
+/// 16/32-bpp pixels described by red, green, blue and alpha masks.
+pub const BI_ALPHABITFIELDS: u32 = 6;
+
+const TRUNCATED_HEADER: IconError = IconError::Truncated { stage: Stage::BitmapHeader };
+
+const DEFAULT_MASKS_16: [u32; 4] = [0x7C00, 0x03E0, 0x001F, 0];
+const DEFAULT_MASKS_32: [u32; 4] = [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000];
+const MASKS_565: [u32; 4] = [0xF800, 0x07E0, 0x001F, 0];
+
+/// The fields of a bitmap header that matter for decoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DibHeader {
+    /// Size of the header structure itself, which identifies its revision.
+    pub header_size: u32,
+    pub width: i32,
+    /// Row count; negative for top-down bitmaps.
+    pub height: i32,
+    pub planes: u16,
+    pub bit_count: u16,
+    pub compression: u32,
+    pub colors_used: u32,
+    /// Red, green, blue and alpha masks for `BI_BITFIELDS`/`BI_ALPHABITFIELDS` data.
+    pub masks: Option<[u32; 4]>,
+}
+
+impl DibHeader {
+    /// Parses a header, along with any masks that trail a `BITMAPINFOHEADER`.
+    pub fn parse(data: &[u8]) -> Result<DibHeader, IconError> {
+        let header_size = u32_at(data, 0).ok_or(TRUNCATED_HEADER)?;
+
+        if header_size == CORE_HEADER_SIZE {
+            let field = |offset| u16_at(data, offset).ok_or(TRUNCATED_HEADER);
+            return Ok(DibHeader {
+                header_size,
+                width: i32::from(field(4)?),
+                height: i32::from(field(6)?),
+                planes: field(8)?,
+                bit_count: field(10)?,
+                compression: BI_RGB,
+                colors_used: 0,
+                masks: None,
+            });
+        }
+
+        if header_size < INFO_HEADER_SIZE || (data.len() as u64) < u64::from(header_size) {
+            return Err(TRUNCATED_HEADER);
+        }
+
+        let compression = u32_at(data, 16).unwrap();
+        let masks = match compression {
+            BI_BITFIELDS | BI_ALPHABITFIELDS => {
+                let count = if compression == BI_ALPHABITFIELDS || header_size >= 56 { 4 } else { 3 };
+                let mut masks = [0; 4];
+                for (i, mask) in masks.iter_mut().enumerate().take(count) {
+                    *mask = u32_at(data, INFO_HEADER_SIZE as usize + i * 4).ok_or(TRUNCATED_HEADER)?;
+                }
+                Some(masks)
+            }
+            _ => None,
+        };
+
+        Ok(DibHeader {
+            header_size,
+            width: i32_at(data, 4).unwrap(),
+            height: i32_at(data, 8).unwrap(),
+            planes: u16_at(data, 12).unwrap(),
+            bit_count: u16_at(data, 14).unwrap(),
+            compression,
+            colors_used: u32_at(data, 32).unwrap(),
+            masks,
+        })
+    }
+
+    /// Returns true if the first row in memory is the top row of the image.
+    pub fn is_top_down(&self) -> bool {
+        self.height < 0
+    }
+
+    /// Returns the number of bytes in each row, including padding to a 4-byte boundary.
+    pub fn stride(&self) -> usize {
+        (self.width.unsigned_abs() as usize * usize::from(self.bit_count)).div_ceil(32) * 4
+    }
+
+    /// Returns the number of colour table entries following the header and masks.
+    pub fn palette_len(&self) -> usize {
+        match (self.bit_count, self.colors_used) {
+            (1..=8, 0) => 1 << self.bit_count,
+            (1..=8, used) => (used as usize).min(1 << self.bit_count),
+            (_, used) => used as usize,
+        }
+    }
+
+    /// Returns the size of a single colour table entry.
+    fn palette_entry_size(&self) -> usize {
+        if self.header_size == CORE_HEADER_SIZE { 3 } else { 4 }
+    }
+
+    /// Returns the offset of the colour table from the start of the header.
+    fn palette_offset(&self) -> usize {
+        let trailing_masks = match self.compression {
+            BI_BITFIELDS if self.header_size == INFO_HEADER_SIZE => 12,
+            BI_ALPHABITFIELDS if self.header_size == INFO_HEADER_SIZE => 16,
+            _ => 0,
+        };
+        self.header_size as usize + trailing_masks
+    }
+
+    /// Returns the offset of the pixel bits in a packed DIB, where they directly follow the colour table.
+    ///
+    /// Returns `None` if a huge `colors_used` puts the offset beyond `usize`.
+    pub fn bits_offset(&self) -> Option<usize> {
+        self.palette_len().checked_mul(self.palette_entry_size())?.checked_add(self.palette_offset())
+    }
+}
+
+/// Decodes a bitmap from its header (with masks and colour table) and pixel bits.
+pub fn decode(header: &[u8], bits: &[u8]) -> Result<RgbaImage, IconError> {
+    let info = DibHeader::parse(header)?;
+    decode_with_header(&info, header, bits)
+}
+
+/// Decodes a packed DIB, where the pixel bits directly follow the header and colour table.
+pub fn decode_packed(data: &[u8]) -> Result<RgbaImage, IconError> {
+    let info = DibHeader::parse(data)?;
+    let bits = info
+        .bits_offset()
+        .and_then(|offset| data.get(offset..))
+        .ok_or(IconError::Truncated { stage: Stage::PixelData })?;
+    decode_with_header(&info, data, bits)
+}
+
+/// Decodes pixel bits using an already parsed header.
+///
+/// `header` is the raw header the colour table is read from.
+pub fn decode_with_header(info: &DibHeader, header: &[u8], bits: &[u8]) -> Result<RgbaImage, IconError> {
+    if !matches!(info.compression, BI_RGB | BI_BITFIELDS | BI_ALPHABITFIELDS) {
+        return Err(IconError::UnsupportedCompression(info.compression));
+    }
+
+    let geometry = BitmapGeometry::from_header(info);
+    let (width, height) = geometry.validate_data(bits.len())?;
+    let (width, height) = (width as usize, height as usize);
+    let stride = geometry.stride;
+    let row_len = width * 4;
+
+    let row_decoder = RowDecoder::new(info, header)?;
+    let mut pixels = vec![0; row_len * height];
+
+    for (y, out) in pixels.chunks_exact_mut(row_len).enumerate() {
+        let source_row = if info.is_top_down() { y } else { height - 1 - y };
+        row_decoder.decode(&bits[source_row * stride..][..stride], out, width);
+    }
+
+    Ok(RgbaImage::from_raw(width as u32, height as u32, pixels).unwrap())
+}
+
+/// Converts one row of source pixels into RGBA.
+enum RowDecoder {
+    Indexed { bit_count: u16, palette: Vec<[u8; 4]> },
+    Layout(PixelLayout),
+    Masks { masks: [u32; 4], bytes: usize },
+}
+
+impl RowDecoder {
+    fn new(info: &DibHeader, header: &[u8]) -> Result<RowDecoder, IconError> {
+        match info.bit_count {
+            1 | 4 | 8 => {
+                let entry_size = info.palette_entry_size();
+                let table = info
+                    .palette_len()
+                    .checked_mul(entry_size)
+                    .and_then(|len| slice_at(header, info.palette_offset(), len))
+                    .ok_or(IconError::Truncated { stage: Stage::ColorTable })?;
+                let palette = table.chunks_exact(entry_size).map(|entry| [entry[2], entry[1], entry[0], 0xFF]).collect();
+                Ok(RowDecoder::Indexed { bit_count: info.bit_count, palette })
+            }
+            16 => match info.masks.unwrap_or(DEFAULT_MASKS_16) {
+                DEFAULT_MASKS_16 => Ok(RowDecoder::Layout(PixelLayout::Rgb555)),
+                MASKS_565 => Ok(RowDecoder::Layout(PixelLayout::Rgb565)),
+                masks => Ok(RowDecoder::Masks { masks, bytes: 2 }),
+            },
+            24 => Ok(RowDecoder::Layout(PixelLayout::Bgr)),
+            32 => match info.masks.unwrap_or(DEFAULT_MASKS_32) {
+                DEFAULT_MASKS_32 => Ok(RowDecoder::Layout(PixelLayout::Bgra)),
+                masks => Ok(RowDecoder::Masks { masks, bytes: 4 }),
+            },
+            bit_count => Err(IconError::UnsupportedBitDepth(bit_count)),
+        }
+    }
+
+    fn decode(&self, row: &[u8], out: &mut [u8], width: usize) {
+        match self {
+            RowDecoder::Indexed { bit_count, palette } => {
+                let per_byte = 8 / usize::from(*bit_count);
+                let index_mask = (1u16 << bit_count) - 1;
+                for (x, pixel) in out.chunks_exact_mut(4).enumerate() {
+                    let shift = (per_byte - 1 - x % per_byte) * usize::from(*bit_count);
+                    let index = (u16::from(row[x / per_byte]) >> shift) & index_mask;
+                    // Out-of-range indices render black, as GDI does.
+                    pixel.copy_from_slice(palette.get(usize::from(index)).unwrap_or(&[0, 0, 0, 0xFF]));
+                }
+            }
+            RowDecoder::Layout(PixelLayout::Bgra) => {
+                out.copy_from_slice(&row[..width * 4]);
+                bgra_to_rgba(out);
+            }
+            RowDecoder::Layout(layout) => {
+                convert(&row[..width * layout.bytes_per_pixel()], *layout, out, PixelLayout::Rgba).unwrap();
+            }
+            RowDecoder::Masks { masks, bytes } => {
+                for (source, pixel) in row.chunks_exact(*bytes).zip(out.chunks_exact_mut(4)) {
+                    let value = match *bytes {
+                        2 => u32::from(u16::from_le_bytes([source[0], source[1]])),
+                        _ => u32::from_le_bytes([source[0], source[1], source[2], source[3]]),
+                    };
+                    for (channel, &mask) in pixel.iter_mut().zip(masks) {
+                        *channel = extract_channel(value, mask);
+                    }
+                    if masks[3] == 0 {
+                        pixel[3] = 0xFF;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Extracts the bits selected by `mask` and scales them to 8 bits.
+fn extract_channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = u64::from(mask >> mask.trailing_zeros());
+    let raw = u64::from((value & mask) >> mask.trailing_zeros());
+    ((raw * 255 + max / 2) / max) as u8
+}
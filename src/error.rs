@@ -0,0 +1,232 @@
+//! The error type shared by every fallible operation in the crate.
+
+use crate::convert::PixelLayout;
+use crate::resource::ResourceId;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The step of icon extraction or decoding that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Stage {
+    /// Looking up the shell's icon index for a file.
+    FileInfo,
+    /// Retrieving a system image list.
+    ImageList,
+    /// Retrieving an icon from an image list.
+    GetIcon,
+    /// Reading the bitmaps behind an icon handle.
+    IconInfo,
+    /// Reading colour pixels from a bitmap.
+    ColorBits,
+    /// Reading AND-mask pixels from a bitmap.
+    MaskBits,
+    /// Releasing GDI or icon handles.
+    Cleanup,
+    /// Parsing a bitmap header.
+    BitmapHeader,
+    /// Reading a bitmap colour table.
+    ColorTable,
+    /// Reading bitmap pixel data.
+    PixelData,
+    /// Reading a 1-bpp AND mask.
+    Mask,
+    /// Reading an icon or cursor directory.
+    IconDirectory,
+    /// Reading the image data of an icon or cursor directory entry.
+    IconEntry,
+    /// Walking the chunks of a RIFF container.
+    RiffChunk,
+    /// Reading the header, rates and sequence of an animated cursor.
+    AniHeader,
+    /// Reading the headers of an executable image.
+    ExecutableHeader,
+    /// Walking the resource directory of an executable image.
+    ResourceDirectory,
+    /// Reading a shell link.
+    ShellLink,
+    /// Reading the `LinkInfo` structure of a shell link.
+    LinkInfo,
+    /// Parsing an icon location string.
+    IconLocation,
+    /// Reading the cells of a registry hive.
+    RegistryHive,
+    /// Parsing a registry export.
+    RegistryFile,
+}
+
+/// Errors returned while extracting, decoding or converting icons.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IconError {
+    /// The shell has no icon for the file.
+    NoIcon { path: PathBuf },
+    /// No icon is registered for the file extension.
+    NoIconForExtension(String),
+    /// No icon is registered for the MIME type.
+    NoIconForMime(String),
+    /// A Windows API call failed.
+    #[cfg(windows)]
+    Windows {
+        stage: Stage,
+        path: Option<PathBuf>,
+        source: windows::core::Error,
+    },
+    /// A file could not be read or written.
+    Io { path: PathBuf, source: std::io::Error },
+    /// An embedded image could not be decoded.
+    Image(image::ImageError),
+    /// The data ended before the structure being read was complete.
+    Truncated { stage: Stage },
+    /// The data does not have the structure expected at this stage.
+    Malformed { stage: Stage },
+    /// A bitmap uses a bit depth that cannot be decoded.
+    UnsupportedBitDepth(u16),
+    /// A bitmap uses a compression scheme that cannot be decoded.
+    UnsupportedCompression(u32),
+    /// A bitmap has no pixels or too many to address.
+    InvalidDimensions { width: i64, height: i64 },
+    /// A bitmap's row stride is too small for its width and bit depth.
+    InvalidStride { stride: usize, minimum: usize },
+    /// A pixel buffer does not have the size its layout requires.
+    BufferSize { expected: usize, actual: usize },
+    /// Two pixel layouts cannot share a buffer for in-place conversion.
+    IncompatibleLayouts { from: PixelLayout, to: PixelLayout },
+    /// A cursor does not have exactly one hotspot per image.
+    HotspotCount { images: usize, hotspots: usize },
+    /// A cursor hotspot lies outside its image.
+    InvalidHotspot { hotspot: (u32, u32), width: u32, height: u32 },
+    /// An executable has no resource of this type and name.
+    MissingResource { resource_type: u16, name: ResourceId },
+    /// An executable has no icon at this `ExtractIconEx` index.
+    NoIconAtIndex(i32),
+}
+
+impl IconError {
+    #[cfg(windows)]
+    pub(crate) fn windows(stage: Stage, path: Option<&Path>, source: windows::core::Error) -> IconError {
+        IconError::Windows { stage, path: path.map(Path::to_path_buf), source }
+    }
+
+    /// Attaches `path` to errors that were raised without one.
+    #[cfg(windows)]
+    pub(crate) fn with_path(self, path: &Path) -> IconError {
+        match self {
+            IconError::Windows { stage, path: None, source } => IconError::windows(stage, Some(path), source),
+            error => error,
+        }
+    }
+
+    /// Returns the path being processed when the error occurred, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            IconError::NoIcon { path } | IconError::Io { path, .. } => Some(path),
+            #[cfg(windows)]
+            IconError::Windows { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the step that failed, if the error is tied to one.
+    pub fn stage(&self) -> Option<Stage> {
+        match self {
+            IconError::NoIcon { .. } | IconError::NoIconForExtension(_) | IconError::NoIconForMime(_) => {
+                Some(Stage::FileInfo)
+            }
+            #[cfg(windows)]
+            IconError::Windows { stage, .. } => Some(*stage),
+            IconError::Truncated { stage } | IconError::Malformed { stage } => Some(*stage),
+            IconError::MissingResource { .. } | IconError::NoIconAtIndex(_) => Some(Stage::ResourceDirectory),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::FileInfo => "retrieving file info",
+            Stage::ImageList => "retrieving the image list",
+            Stage::GetIcon => "retrieving the icon",
+            Stage::IconInfo => "retrieving icon information",
+            Stage::ColorBits => "retrieving bitmap data",
+            Stage::MaskBits => "retrieving mask data",
+            Stage::Cleanup => "releasing handles",
+            Stage::BitmapHeader => "reading the bitmap header",
+            Stage::ColorTable => "reading the colour table",
+            Stage::PixelData => "reading pixel data",
+            Stage::Mask => "reading the mask",
+            Stage::IconDirectory => "reading the icon directory",
+            Stage::IconEntry => "reading an icon entry",
+            Stage::RiffChunk => "reading a RIFF chunk",
+            Stage::AniHeader => "reading the animated cursor header",
+            Stage::ExecutableHeader => "reading the executable headers",
+            Stage::ResourceDirectory => "reading the resource directory",
+            Stage::ShellLink => "reading the shell link",
+            Stage::LinkInfo => "reading the shell link target",
+            Stage::IconLocation => "parsing the icon location",
+            Stage::RegistryHive => "reading the registry hive",
+            Stage::RegistryFile => "parsing the registry file",
+        })
+    }
+}
+
+impl fmt::Display for IconError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IconError::NoIcon { path } => write!(f, "No icon found for {}", path.display()),
+            IconError::NoIconForExtension(extension) => write!(f, "No icon found for extension {extension}"),
+            IconError::NoIconForMime(mime) => write!(f, "No icon found for MIME type {mime}"),
+            #[cfg(windows)]
+            IconError::Windows { stage, path: Some(path), source } => {
+                write!(f, "Failed {stage} for {}: {source}", path.display())
+            }
+            #[cfg(windows)]
+            IconError::Windows { stage, path: None, source } => write!(f, "Failed {stage}: {source}"),
+            IconError::Io { path, source } => write!(f, "I/O error on {}: {source}", path.display()),
+            IconError::Image(source) => write!(f, "Failed to decode embedded image: {source}"),
+            IconError::Truncated { stage } => write!(f, "Data is truncated while {stage}"),
+            IconError::Malformed { stage } => write!(f, "Data is malformed while {stage}"),
+            IconError::UnsupportedBitDepth(bits) => write!(f, "Unsupported bit depth: {bits}"),
+            IconError::UnsupportedCompression(compression) => write!(f, "Unsupported bitmap compression: {compression}"),
+            IconError::InvalidDimensions { width, height } => write!(f, "Invalid image dimensions: {width}x{height}"),
+            IconError::InvalidStride { stride, minimum } => {
+                write!(f, "Row stride of {stride} bytes is below the minimum of {minimum}")
+            }
+            IconError::BufferSize { expected, actual } => {
+                write!(f, "Buffer holds {actual} bytes but {expected} are required")
+            }
+            IconError::IncompatibleLayouts { from, to } => {
+                write!(f, "Cannot convert {from:?} to {to:?} in place")
+            }
+            IconError::HotspotCount { images, hotspots } => {
+                write!(f, "Cursor has {images} images but {hotspots} hotspots")
+            }
+            IconError::InvalidHotspot { hotspot: (x, y), width, height } => {
+                write!(f, "Hotspot ({x}, {y}) lies outside a {width}x{height} cursor image")
+            }
+            IconError::MissingResource { resource_type, name } => {
+                write!(f, "No resource {name} of type {resource_type}")
+            }
+            IconError::NoIconAtIndex(index) => write!(f, "No icon at index {index}"),
+        }
+    }
+}
+
+impl std::error::Error for IconError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(windows)]
+            IconError::Windows { source, .. } => Some(source),
+            IconError::Io { source, .. } => Some(source),
+            IconError::Image(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for IconError {
+    fn from(source: image::ImageError) -> IconError {
+        IconError::Image(source)
+    }
+}
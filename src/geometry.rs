@@ -0,0 +1,86 @@
+//! Consistency checks between a bitmap's declared size, row stride, bit depth
+//! and the pixel data backing it.
+
+use crate::dib::DibHeader;
+use crate::error::{IconError, Stage};
+
+/// Dimensions and memory layout of a bitmap as reported by its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitmapGeometry {
+    pub width: i32,
+    /// Row count; negative for top-down bitmaps.
+    pub height: i32,
+    pub bits_per_pixel: u16,
+    /// Bytes from the start of one row to the start of the next.
+    pub stride: usize,
+}
+
+impl BitmapGeometry {
+    /// Returns the geometry of a DIB, whose rows are padded to a 4-byte boundary.
+    pub fn from_header(header: &DibHeader) -> BitmapGeometry {
+        BitmapGeometry {
+            width: header.width,
+            height: header.height,
+            bits_per_pixel: header.bit_count,
+            stride: header.stride(),
+        }
+    }
+
+    /// Returns the geometry of a tightly laid out top-down DIB of the given size.
+    pub fn top_down(width: u32, height: u32, bits_per_pixel: u16) -> BitmapGeometry {
+        let width = width.min(i32::MAX as u32) as i32;
+        let height = height.min(i32::MAX as u32) as i32;
+        let stride = (width as usize * usize::from(bits_per_pixel)).div_ceil(32) * 4;
+        BitmapGeometry { width, height: -height, bits_per_pixel, stride }
+    }
+
+    /// Returns the number of bytes needed to hold one row of pixels, without padding.
+    pub fn packed_row_len(&self) -> usize {
+        (self.width.unsigned_abs() as usize * usize::from(self.bits_per_pixel)).div_ceil(8)
+    }
+
+    /// Checks that size, bit depth and stride agree, returning the width and row count.
+    pub fn validate(&self) -> Result<(u32, u32), IconError> {
+        let invalid_dimensions = IconError::InvalidDimensions {
+            width: i64::from(self.width),
+            height: i64::from(self.height),
+        };
+
+        if self.width <= 0 || self.height == 0 {
+            return Err(invalid_dimensions);
+        }
+        if !matches!(self.bits_per_pixel, 1 | 4 | 8 | 16 | 24 | 32) {
+            return Err(IconError::UnsupportedBitDepth(self.bits_per_pixel));
+        }
+
+        let width = self.width as u32;
+        let height = self.height.unsigned_abs();
+
+        // The decoded image must fit in a single 32-bit RGBA allocation.
+        let rgba_len = (width as usize).checked_mul(height as usize).and_then(|pixels| pixels.checked_mul(4));
+        if rgba_len.is_none_or(|len| len > isize::MAX as usize) {
+            return Err(invalid_dimensions);
+        }
+
+        let minimum = self.packed_row_len();
+        if self.stride < minimum {
+            return Err(IconError::InvalidStride { stride: self.stride, minimum });
+        }
+
+        Ok((width, height))
+    }
+
+    /// Returns the number of bytes the pixel data occupies, if it fits in memory.
+    pub fn data_len(&self) -> Option<usize> {
+        self.stride.checked_mul(self.height.unsigned_abs() as usize)
+    }
+
+    /// Validates the geometry and checks that `data_len` bytes are enough to hold the pixels.
+    pub fn validate_data(&self, data_len: usize) -> Result<(u32, u32), IconError> {
+        let dimensions = self.validate()?;
+        if self.data_len().is_none_or(|needed| data_len < needed) {
+            return Err(IconError::Truncated { stage: Stage::PixelData });
+        }
+        Ok(dimensions)
+    }
+}
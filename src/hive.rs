@@ -0,0 +1,306 @@
+//! Offline registry hives in the `regf` format, such as `SOFTWARE`,
+//! `NTUSER.DAT` and `UsrClass.dat`.
+//!
+//! A hive is a 4 KiB base block followed by bins of cells. Every cell starts
+//! with its size, negative while allocated, and offsets between cells are
+//! relative to the end of the base block. Keys are `nk` cells, values `vk`
+//! cells, and a key's subkeys are listed by `lf`, `lh` or `li` cells, or by an
+//! `ri` cell indexing several of those. Values larger than a cell are split
+//! across the segments of a `db` cell.
+
+use crate::bytes::{i32_at, slice_at, u16_at, u32_at};
+use crate::error::{IconError, Stage};
+use crate::registry::{names_match, path_components, RegValue, Registry};
+
+const BASE_BLOCK_SIZE: usize = 4096;
+const SIGNATURE: &[u8] = b"regf";
+const ROOT_CELL_OFFSET: usize = 0x24;
+/// Offset values that mark an absent cell.
+const NO_CELL: u32 = 0xffff_ffff;
+
+/// `nk` flag: the name is stored in Latin-1 rather than UTF-16LE.
+const KEY_COMP_NAME: u16 = 0x20;
+const KEY_NAME_OFFSET: usize = 0x4c;
+/// `vk` flag: the name is stored in Latin-1 rather than UTF-16LE.
+const VALUE_COMP_NAME: u16 = 0x1;
+const VALUE_NAME_OFFSET: usize = 0x14;
+/// Set in a value's data size when the data is stored in the offset field itself.
+const DATA_INLINE: u32 = 0x8000_0000;
+/// The most data a single cell holds; larger values use a `db` cell.
+const BIG_DATA_SEGMENT: usize = 16344;
+/// How deeply `ri` lists may nest, which stops cycles in corrupt hives.
+const MAX_INDEX_DEPTH: usize = 8;
+
+/// A registry hive held in memory.
+#[derive(Clone, Copy, Debug)]
+pub struct Hive<'a> {
+    data: &'a [u8],
+    root: u32,
+}
+
+impl<'a> Hive<'a> {
+    /// Parses the base block of a hive and checks that its root key is readable.
+    pub fn parse(data: &'a [u8]) -> Result<Hive<'a>, IconError> {
+        if slice_at(data, 0, 4) != Some(SIGNATURE) {
+            return Err(IconError::Malformed { stage: Stage::RegistryHive });
+        }
+        let root = u32_at(data, ROOT_CELL_OFFSET).ok_or(IconError::Truncated { stage: Stage::RegistryHive })?;
+        let hive = Hive { data, root };
+        hive.root()?;
+        Ok(hive)
+    }
+
+    /// Returns the root key.
+    pub fn root(&self) -> Result<HiveKey<'a>, IconError> {
+        self.key_at(self.root)
+    }
+
+    /// Returns the key at a backslash-separated path below the root, or `None` if there is none.
+    pub fn key(&self, path: &str) -> Result<Option<HiveKey<'a>>, IconError> {
+        let mut key = self.root()?;
+        for component in path_components(path) {
+            match key.subkey(component)? {
+                Some(subkey) => key = subkey,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(key))
+    }
+
+    /// Returns the data of the cell at `offset`, without its size field.
+    fn cell(&self, offset: u32) -> Result<&'a [u8], IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::RegistryHive };
+        let start = BASE_BLOCK_SIZE.checked_add(offset as usize).ok_or_else(truncated)?;
+        let size = i32_at(self.data, start).ok_or_else(truncated)?.unsigned_abs() as usize;
+        if size < 4 {
+            return Err(IconError::Malformed { stage: Stage::RegistryHive });
+        }
+        slice_at(self.data, start.checked_add(4).ok_or_else(truncated)?, size - 4).ok_or_else(truncated)
+    }
+
+    fn key_at(&self, offset: u32) -> Result<HiveKey<'a>, IconError> {
+        let cell = self.cell(offset)?;
+        if !cell.starts_with(b"nk") || cell.len() < KEY_NAME_OFFSET {
+            return Err(IconError::Malformed { stage: Stage::RegistryHive });
+        }
+        Ok(HiveKey { hive: *self, cell })
+    }
+
+    /// Returns a subkey list cell with its entry count and entry size.
+    fn subkey_list(&self, offset: u32) -> Result<(&'a [u8], usize, usize), IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::RegistryHive };
+
+        let list = self.cell(offset)?;
+        let count = usize::from(u16_at(list, 2).ok_or_else(truncated)?);
+        // `lf` and `lh` entries pair each offset with a name hint.
+        let stride = match slice_at(list, 0, 2).ok_or_else(truncated)? {
+            b"lf" | b"lh" => 8,
+            b"li" | b"ri" => 4,
+            _ => return Err(IconError::Malformed { stage: Stage::RegistryHive }),
+        };
+        Ok((list, stride, count))
+    }
+
+    /// Appends the keys listed by the subkey list at `offset`, descending into `ri` lists.
+    fn collect_subkeys(&self, offset: u32, depth: usize, keys: &mut Vec<HiveKey<'a>>) -> Result<(), IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::RegistryHive };
+
+        let (list, stride, count) = self.subkey_list(offset)?;
+        for index in 0..count {
+            let entry = u32_at(list, 4 + index * stride).ok_or_else(truncated)?;
+            if list.starts_with(b"ri") {
+                if depth == MAX_INDEX_DEPTH {
+                    return Err(IconError::Malformed { stage: Stage::RegistryHive });
+                }
+                self.collect_subkeys(entry, depth + 1, keys)?;
+            } else {
+                keys.push(self.key_at(entry)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the key called `name` in the subkey list at `offset`, decoding
+    /// only the names whose `lf` or `lh` hints allow a match.
+    fn find_subkey(&self, offset: u32, depth: usize, name: &str) -> Result<Option<HiveKey<'a>>, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::RegistryHive };
+
+        let (list, stride, count) = self.subkey_list(offset)?;
+        for index in 0..count {
+            let entry = u32_at(list, 4 + index * stride).ok_or_else(truncated)?;
+            if list.starts_with(b"ri") {
+                if depth == MAX_INDEX_DEPTH {
+                    return Err(IconError::Malformed { stage: Stage::RegistryHive });
+                }
+                if let Some(key) = self.find_subkey(entry, depth + 1, name)? {
+                    return Ok(Some(key));
+                }
+                continue;
+            }
+            if stride == 8 && !hint_matches(&list[..2], u32_at(list, 8 + index * stride).ok_or_else(truncated)?, name) {
+                continue;
+            }
+            let key = self.key_at(entry)?;
+            if names_match(&key.name(), name) {
+                return Ok(Some(key));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Registry for Hive<'_> {
+    fn subkey_names(&self, key: &str) -> Result<Option<Vec<String>>, IconError> {
+        let Some(key) = self.key(key)? else { return Ok(None) };
+        Ok(Some(key.subkeys()?.iter().map(HiveKey::name).collect()))
+    }
+
+    fn value_names(&self, key: &str) -> Result<Option<Vec<String>>, IconError> {
+        self.key(key)?.map(|key| key.value_names()).transpose()
+    }
+
+    fn value(&self, key: &str, name: &str) -> Result<Option<RegValue>, IconError> {
+        match self.key(key)? {
+            Some(key) => key.value(name),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A key of a [`Hive`].
+#[derive(Clone, Copy, Debug)]
+pub struct HiveKey<'a> {
+    hive: Hive<'a>,
+    cell: &'a [u8],
+}
+
+impl<'a> HiveKey<'a> {
+    pub fn name(&self) -> String {
+        let len = usize::from(u16_at(self.cell, 0x48).unwrap());
+        let compressed = u16_at(self.cell, 2).unwrap() & KEY_COMP_NAME != 0;
+        decode_name(self.cell.get(KEY_NAME_OFFSET..).unwrap_or_default(), len, compressed)
+    }
+
+    /// Returns the key's subkeys, in the order the hive lists them.
+    pub fn subkeys(&self) -> Result<Vec<HiveKey<'a>>, IconError> {
+        let mut keys = Vec::new();
+        if let Some(offset) = self.subkey_list() {
+            self.hive.collect_subkeys(offset, 0, &mut keys)?;
+        }
+        Ok(keys)
+    }
+
+    /// Returns the subkey called `name`, ignoring case.
+    pub fn subkey(&self, name: &str) -> Result<Option<HiveKey<'a>>, IconError> {
+        match self.subkey_list() {
+            Some(offset) => self.hive.find_subkey(offset, 0, name),
+            None => Ok(None),
+        }
+    }
+
+    fn subkey_list(&self) -> Option<u32> {
+        let offset = u32_at(self.cell, 0x1c).unwrap();
+        (u32_at(self.cell, 0x14).unwrap() != 0 && offset != NO_CELL).then_some(offset)
+    }
+
+    /// Returns the names of the key's values; the default value is named by the empty string.
+    pub fn value_names(&self) -> Result<Vec<String>, IconError> {
+        Ok(self.value_cells()?.into_iter().map(value_name).collect())
+    }
+
+    /// Returns the value called `name`, ignoring case.
+    pub fn value(&self, name: &str) -> Result<Option<RegValue>, IconError> {
+        match self.value_cells()?.into_iter().find(|&cell| names_match(&value_name(cell), name)) {
+            Some(cell) => self.value_data(cell).map(|data| Some(RegValue::from_bytes(u32_at(cell, 0xc).unwrap(), &data))),
+            None => Ok(None),
+        }
+    }
+
+    fn value_cells(&self) -> Result<Vec<&'a [u8]>, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::RegistryHive };
+
+        let count = u32_at(self.cell, 0x24).unwrap() as usize;
+        let offset = u32_at(self.cell, 0x28).unwrap();
+        if count == 0 || offset == NO_CELL {
+            return Ok(Vec::new());
+        }
+        let list = self.hive.cell(offset)?;
+        (0..count)
+            .map(|index| {
+                let entry = index.checked_mul(4).and_then(|at| u32_at(list, at)).ok_or_else(truncated)?;
+                let cell = self.hive.cell(entry)?;
+                if !cell.starts_with(b"vk") || cell.len() < VALUE_NAME_OFFSET {
+                    return Err(IconError::Malformed { stage: Stage::RegistryHive });
+                }
+                Ok(cell)
+            })
+            .collect()
+    }
+
+    fn value_data(&self, cell: &'a [u8]) -> Result<Vec<u8>, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::RegistryHive };
+        let malformed = || IconError::Malformed { stage: Stage::RegistryHive };
+
+        let size = u32_at(cell, 4).unwrap();
+        if size & DATA_INLINE != 0 {
+            // Inline data lives in the four bytes of the offset field.
+            let len = (size & !DATA_INLINE) as usize;
+            return cell.get(8..8 + len).filter(|_| len <= 4).map(<[u8]>::to_vec).ok_or_else(malformed);
+        }
+
+        let size = size as usize;
+        let data = self.hive.cell(u32_at(cell, 8).unwrap())?;
+        if size <= BIG_DATA_SEGMENT || !data.starts_with(b"db") {
+            return Ok(data.get(..size).ok_or_else(truncated)?.to_vec());
+        }
+
+        let segments = usize::from(u16_at(data, 2).ok_or_else(truncated)?);
+        if size > segments * BIG_DATA_SEGMENT {
+            return Err(malformed());
+        }
+        let list = self.hive.cell(u32_at(data, 4).ok_or_else(truncated)?)?;
+        let mut bytes = Vec::with_capacity(size);
+        for index in 0..segments {
+            let segment = self.hive.cell(u32_at(list, index * 4).ok_or_else(truncated)?)?;
+            let len = (size - bytes.len()).min(BIG_DATA_SEGMENT).min(segment.len());
+            bytes.extend_from_slice(&segment[..len]);
+        }
+        if bytes.len() < size {
+            return Err(truncated());
+        }
+        Ok(bytes)
+    }
+}
+
+/// Returns false if the hint of an `lf` or `lh` entry rules out a key called `name`.
+///
+/// An `lf` hint holds the first four characters of the key's name and an `lh`
+/// hint a hash of the uppercased name. Only ASCII names are checked, as their
+/// uppercase is certain, and zero hints, which some writers leave, are ignored.
+fn hint_matches(signature: &[u8], hint: u32, name: &str) -> bool {
+    if hint == 0 || !name.is_ascii() {
+        return true;
+    }
+    if signature == b"lh" {
+        return hint == name.bytes().fold(0u32, |hash, byte| hash.wrapping_mul(37).wrapping_add(u32::from(byte.to_ascii_uppercase())));
+    }
+    let prefix = name.bytes().chain(std::iter::repeat(0)).take(4);
+    hint.to_le_bytes().iter().zip(prefix).all(|(hint, byte)| hint.eq_ignore_ascii_case(&byte))
+}
+
+fn value_name(cell: &[u8]) -> String {
+    let len = usize::from(u16_at(cell, 2).unwrap());
+    let compressed = u16_at(cell, 0x10).unwrap() & VALUE_COMP_NAME != 0;
+    decode_name(&cell[VALUE_NAME_OFFSET..], len, compressed)
+}
+
+/// Decodes a name of `len` bytes, stored in Latin-1 if `compressed` and in UTF-16LE otherwise.
+fn decode_name(data: &[u8], len: usize, compressed: bool) -> String {
+    let data = &data[..len.min(data.len())];
+    if compressed {
+        data.iter().map(|&byte| char::from(byte)).collect()
+    } else {
+        let units: Vec<u16> = data.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+        String::from_utf16_lossy(&units)
+    }
+}
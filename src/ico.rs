@@ -0,0 +1,417 @@
+//! Reading and writing of `.ico` files.
+//!
+//! Both classic DIB entries, whose transparency comes from an AND mask, and the
+//! PNG-compressed entries introduced with Windows Vista are decoded and encoded.
+
+use crate::bytes::{slice_at, u16_at, u32_at};
+use crate::dib::{self, DibHeader};
+use crate::error::{IconError, Stage};
+use crate::geometry::BitmapGeometry;
+use crate::mask::{apply_and_mask, mask_stride};
+use crate::palette;
+use crate::size::{IconSize, DEFAULT_DPI};
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, RgbaImage};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+
+/// `ICONDIR` resource type for icons.
+pub(crate) const ICON_TYPE: u16 = 1;
+
+pub(crate) const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const DIR_HEADER_SIZE: usize = 6;
+const DIR_ENTRY_SIZE: usize = 16;
+const INFO_HEADER_SIZE: u32 = 40;
+/// Largest edge length an icon directory can describe.
+const MAX_DIMENSION: u32 = 256;
+
+/// How an entry's image data is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntryFormat {
+    /// A packed DIB followed by a 1-bpp AND mask.
+    Bmp,
+    /// A complete PNG file.
+    Png,
+}
+
+/// One decoded image from an icon file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IcoEntry {
+    /// Width declared in the directory, with 0 read as 256.
+    pub width: u32,
+    /// Height declared in the directory, with 0 read as 256.
+    pub height: u32,
+    /// Bit depth declared in the directory, or taken from the image data when the directory leaves it 0.
+    pub bit_depth: u16,
+    pub format: EntryFormat,
+    pub image: RgbaImage,
+}
+
+/// An `ICONDIRENTRY` before its image is decoded.
+pub(crate) struct DirEntry<'a> {
+    pub width: u32,
+    pub height: u32,
+    /// Colour planes for icons, hotspot x for cursors.
+    pub planes: u16,
+    /// Bit depth for icons, hotspot y for cursors.
+    pub bit_count: u16,
+    pub data: &'a [u8],
+}
+
+/// Parses an `ICONDIR` of the given resource type and slices out every entry's data.
+pub(crate) fn parse_directory(data: &[u8], resource_type: u16) -> Result<Vec<DirEntry<'_>>, IconError> {
+    let truncated = || IconError::Truncated { stage: Stage::IconDirectory };
+
+    let reserved = u16_at(data, 0).ok_or_else(truncated)?;
+    let found = u16_at(data, 2).ok_or_else(truncated)?;
+    if reserved != 0 || found != resource_type {
+        return Err(IconError::Malformed { stage: Stage::IconDirectory });
+    }
+
+    let count = usize::from(u16_at(data, 4).ok_or_else(truncated)?);
+    (0..count)
+        .map(|index| {
+            let entry = slice_at(data, DIR_HEADER_SIZE + index * DIR_ENTRY_SIZE, DIR_ENTRY_SIZE).ok_or_else(truncated)?;
+            let size = u32_at(entry, 8).unwrap() as usize;
+            let offset = u32_at(entry, 12).unwrap() as usize;
+
+            Ok(DirEntry {
+                width: declared_dimension(entry[0]),
+                height: declared_dimension(entry[1]),
+                planes: u16_at(entry, 4).unwrap(),
+                bit_count: u16_at(entry, 6).unwrap(),
+                data: slice_at(data, offset, size).ok_or(IconError::Truncated { stage: Stage::IconEntry })?,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn declared_dimension(value: u8) -> u32 {
+    if value == 0 { 256 } else { u32::from(value) }
+}
+
+/// Decodes the image data of a single icon or cursor entry, returning its format and source bit depth.
+pub(crate) fn decode_entry(data: &[u8]) -> Result<(EntryFormat, u16, RgbaImage), IconError> {
+    if data.starts_with(PNG_SIGNATURE) {
+        let image = image::load_from_memory_with_format(data, ImageFormat::Png)?.to_rgba8();
+        let bit_depth = png_bits_per_pixel(data).ok_or(IconError::Malformed { stage: Stage::IconEntry })?;
+        return Ok((EntryFormat::Png, bit_depth, image));
+    }
+
+    let mut header = DibHeader::parse(data)?;
+
+    // The DIB height covers the colour bitmap and the AND mask stacked together.
+    header.height /= 2;
+    let geometry = BitmapGeometry::from_header(&header);
+    let (width, height) = geometry.validate()?;
+
+    let bits = header
+        .bits_offset()
+        .and_then(|offset| data.get(offset..))
+        .ok_or(IconError::Truncated { stage: Stage::PixelData })?;
+    let mut image = dib::decode_with_header(&header, data, bits)?;
+
+    let mask = bits.get(geometry.data_len().unwrap()..).unwrap_or_default();
+    let mask_len = mask_stride(width) * height as usize;
+
+    if header.bit_count < 32 {
+        // Palette and 16/24-bpp colours carry no alpha of their own.
+        image.pixels_mut().for_each(|pixel| pixel[3] = 0);
+        apply_and_mask(&mut image, mask, header.is_top_down())?;
+    } else if mask.len() >= mask_len {
+        apply_and_mask(&mut image, mask, header.is_top_down())?;
+    }
+
+    Ok((EntryFormat::Bmp, header.bit_count, image))
+}
+
+/// Returns the bits per pixel a PNG's `IHDR` declares: its sample depth times
+/// the samples its colour type carries.
+fn png_bits_per_pixel(data: &[u8]) -> Option<u16> {
+    let depth = u16::from(*data.get(24)?);
+    let samples = match *data.get(25)? {
+        // Greyscale and palette indices.
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return None,
+    };
+    Some(depth * samples)
+}
+
+/// Parses an icon file held in memory and decodes every entry.
+pub fn parse(data: &[u8]) -> Result<Vec<IcoEntry>, IconError> {
+    parse_directory(data, ICON_TYPE)?
+        .into_iter()
+        .map(|entry| {
+            let (format, source_bit_depth, image) = decode_entry(entry.data)?;
+            Ok(IcoEntry {
+                width: entry.width,
+                height: entry.height,
+                bit_depth: if entry.bit_count == 0 { source_bit_depth } else { entry.bit_count },
+                format,
+                image,
+            })
+        })
+        .collect()
+}
+
+/// Reads and decodes every entry of the icon file at `path`.
+pub fn read(path: &Path) -> Result<Vec<IcoEntry>, IconError> {
+    let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+    parse(&data)
+}
+
+/// Picks the entry best suited to display at `pixels` square.
+///
+/// This is the smallest entry at least that large, preferring higher bit
+/// depths, or the largest entry if none is.
+pub fn select_entry(entries: &[IcoEntry], pixels: u32) -> Option<&IcoEntry> {
+    let edge = |entry: &IcoEntry| entry.image.width().max(entry.image.height());
+
+    entries
+        .iter()
+        .filter(|entry| edge(entry) >= pixels)
+        .min_by_key(|entry| (edge(entry), std::cmp::Reverse(entry.bit_depth)))
+        .or_else(|| entries.iter().max_by_key(|entry| (edge(entry), entry.bit_depth)))
+}
+
+/// Loads the icon file at `path` as an image of the requested size, as
+/// `fetch_icon_as_image` does for the shell.
+///
+/// [`IconSize::Pixels`] sizes are resampled from the nearest entry.
+pub fn load_icon_as_image(path: &Path, size: IconSize) -> Result<RgbaImage, IconError> {
+    let entries = read(path)?;
+    image_for_size(&entries, size).ok_or_else(|| IconError::NoIcon { path: path.to_path_buf() })
+}
+
+/// Picks the entry for `size` at the default DPI, resampling for [`IconSize::Pixels`].
+pub(crate) fn image_for_size(entries: &[IcoEntry], size: IconSize) -> Option<RgbaImage> {
+    let pixels = size.pixels(DEFAULT_DPI);
+    let image = &select_entry(entries, pixels)?.image;
+
+    match size {
+        IconSize::Pixels(pixels) if image.dimensions() != (pixels, pixels) && pixels > 0 => {
+            Some(imageops::resize(image, pixels, pixels, FilterType::Lanczos3))
+        }
+        _ => Some(image.clone()),
+    }
+}
+
+/// Controls how [`encode`] stores each image.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// The format of each image, by position. Images past the end of the list
+    /// use [`default_format`]: PNG for 256px images only, as Windows itself writes them.
+    pub formats: Vec<EntryFormat>,
+    /// Also store an 8-bpp paletted BMP of every image.
+    pub paletted_8bpp: bool,
+    /// Also store a 4-bpp paletted BMP of every image.
+    pub paletted_4bpp: bool,
+}
+
+/// Returns the format Windows writes an image in: PNG at 256 pixels, 32-bpp BMP below.
+pub fn default_format(image: &RgbaImage) -> EntryFormat {
+    let (width, height) = image.dimensions();
+    if width.max(height) >= MAX_DIMENSION { EntryFormat::Png } else { EntryFormat::Bmp }
+}
+
+/// An encoded entry waiting to be written into a directory.
+pub(crate) struct EncodedEntry {
+    pub width: u32,
+    pub height: u32,
+    pub color_count: u8,
+    /// Colour planes for icons, hotspot x for cursors.
+    pub planes: u16,
+    /// Bit depth for icons, hotspot y for cursors.
+    pub bit_count: u16,
+    pub data: Vec<u8>,
+}
+
+/// Writes an `ICONDIR` of the given resource type followed by every entry's data.
+pub(crate) fn write_directory(resource_type: u16, entries: &[EncodedEntry]) -> Result<Vec<u8>, IconError> {
+    let count = u16::try_from(entries.len()).map_err(|_| IconError::Malformed { stage: Stage::IconDirectory })?;
+
+    let mut file = Vec::new();
+    file.extend_from_slice(&0u16.to_le_bytes());
+    file.extend_from_slice(&resource_type.to_le_bytes());
+    file.extend_from_slice(&count.to_le_bytes());
+
+    let mut offset = DIR_HEADER_SIZE + entries.len() * DIR_ENTRY_SIZE;
+    for entry in entries {
+        let overflow = || IconError::Malformed { stage: Stage::IconEntry };
+        let size = u32::try_from(entry.data.len()).map_err(|_| overflow())?;
+        let start = u32::try_from(offset).map_err(|_| overflow())?;
+
+        file.extend_from_slice(&[stored_dimension(entry.width), stored_dimension(entry.height), entry.color_count, 0]);
+        file.extend_from_slice(&entry.planes.to_le_bytes());
+        file.extend_from_slice(&entry.bit_count.to_le_bytes());
+        file.extend_from_slice(&size.to_le_bytes());
+        file.extend_from_slice(&start.to_le_bytes());
+        offset += entry.data.len();
+    }
+
+    for entry in entries {
+        file.extend_from_slice(&entry.data);
+    }
+    Ok(file)
+}
+
+fn stored_dimension(value: u32) -> u8 {
+    if value >= MAX_DIMENSION { 0 } else { value as u8 }
+}
+
+/// Checks that an image fits in an icon directory entry.
+pub(crate) fn check_dimensions(image: &RgbaImage) -> Result<(), IconError> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(IconError::InvalidDimensions { width: i64::from(width), height: i64::from(height) });
+    }
+    Ok(())
+}
+
+/// Encodes an image as PNG or as a 32-bpp BMP.
+pub(crate) fn encode_as(image: &RgbaImage, format: EntryFormat) -> Result<Vec<u8>, IconError> {
+    match format {
+        EntryFormat::Png => encode_png(image),
+        EntryFormat::Bmp => encode_bmp(image, 32),
+    }
+}
+
+/// Encodes an image as a complete PNG file.
+pub(crate) fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, IconError> {
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+    Ok(data)
+}
+
+/// Encodes an image as a bottom-up DIB of the given bit depth followed by its AND mask.
+///
+/// At 32 bpp the alpha channel is kept and only fully transparent pixels are
+/// masked. Paletted depths have no alpha, so pixels below half opacity are
+/// masked and drawn black, and the rest are reduced to the palette.
+pub(crate) fn encode_bmp(image: &RgbaImage, bit_count: u16) -> Result<Vec<u8>, IconError> {
+    let (width, height) = image.dimensions();
+    let geometry = BitmapGeometry::top_down(width, height, bit_count);
+    geometry.validate()?;
+
+    let masked = |alpha: u8| if bit_count == 32 { alpha == 0 } else { alpha < 0x80 };
+    let palette = match bit_count {
+        32 => Vec::new(),
+        4 | 8 => build_palette(image, 1 << bit_count, masked),
+        depth => return Err(IconError::UnsupportedBitDepth(depth)),
+    };
+
+    let color_len = geometry.data_len().unwrap();
+    let mask_len = mask_stride(width) * height as usize;
+    let image_len = u32::try_from(color_len + mask_len).map_err(|_| IconError::Malformed { stage: Stage::IconEntry })?;
+
+    let mut data = Vec::with_capacity(INFO_HEADER_SIZE as usize + palette.len() * 4 + color_len + mask_len);
+    data.extend_from_slice(&INFO_HEADER_SIZE.to_le_bytes());
+    data.extend_from_slice(&(width as i32).to_le_bytes());
+    data.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&bit_count.to_le_bytes());
+    data.extend_from_slice(&dib::BI_RGB.to_le_bytes());
+    data.extend_from_slice(&image_len.to_le_bytes());
+    data.extend_from_slice(&[0; 16]);
+    for [red, green, blue] in &palette {
+        data.extend_from_slice(&[*blue, *green, *red, 0]);
+    }
+
+    let mut indices = HashMap::new();
+    for y in (0..height).rev() {
+        let mut row = vec![0u8; geometry.stride];
+        for x in 0..width {
+            let [red, green, blue, alpha] = image.get_pixel(x, y).0;
+            let x = x as usize;
+            match bit_count {
+                32 => row[x * 4..x * 4 + 4].copy_from_slice(&[blue, green, red, alpha]),
+                _ if masked(alpha) => {}
+                depth => {
+                    let color = [red, green, blue];
+                    let index = *indices.entry(color).or_insert_with(|| palette::nearest(&palette, color)) as u8;
+                    if depth == 8 {
+                        row[x] = index;
+                    } else {
+                        row[x / 2] |= if x.is_multiple_of(2) { index << 4 } else { index };
+                    }
+                }
+            }
+        }
+        data.extend_from_slice(&row);
+    }
+
+    for y in (0..height).rev() {
+        let mut row = vec![0u8; mask_stride(width)];
+        for x in 0..width {
+            if masked(image.get_pixel(x, y)[3]) {
+                row[x as usize / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        data.extend_from_slice(&row);
+    }
+
+    Ok(data)
+}
+
+/// Builds a full-size palette for the visible pixels of `image`.
+///
+/// Entry 0 is kept black when any pixel is masked so that masked pixels leave
+/// the screen unchanged rather than inverting it.
+fn build_palette(image: &RgbaImage, capacity: usize, masked: impl Fn(u8) -> bool) -> Vec<[u8; 3]> {
+    let visible: Vec<[u8; 3]> = image
+        .pixels()
+        .filter(|pixel| !masked(pixel[3]))
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+
+    let mut palette = if visible.len() < image.pixels().len() {
+        let mut palette = vec![[0, 0, 0]];
+        palette.extend(palette::quantize(&visible, capacity - 1));
+        palette
+    } else {
+        palette::quantize(&visible, capacity)
+    };
+    palette.resize(capacity, [0, 0, 0]);
+    palette
+}
+
+/// Encodes images as an icon file.
+///
+/// Each image becomes one entry, stored as PNG or 32-bpp BMP according to
+/// `options.formats`, followed by any paletted variants requested. Images must be
+/// between 1 and 256 pixels on each side.
+pub fn encode(images: &[RgbaImage], options: &EncodeOptions) -> Result<Vec<u8>, IconError> {
+    let mut entries = Vec::new();
+    for (index, image) in images.iter().enumerate() {
+        check_dimensions(image)?;
+        let (width, height) = image.dimensions();
+        let entry = |bit_count: u16, color_count: u8, data: Vec<u8>| EncodedEntry {
+            width,
+            height,
+            color_count,
+            planes: 1,
+            bit_count,
+            data,
+        };
+
+        let format = options.formats.get(index).copied().unwrap_or_else(|| default_format(image));
+        entries.push(entry(32, 0, encode_as(image, format)?));
+        if options.paletted_8bpp {
+            entries.push(entry(8, 0, encode_bmp(image, 8)?));
+        }
+        if options.paletted_4bpp {
+            entries.push(entry(4, 16, encode_bmp(image, 4)?));
+        }
+    }
+    write_directory(ICON_TYPE, &entries)
+}
+
+/// Encodes images with [`encode`] and writes the icon file to `path`.
+pub fn write(path: &Path, images: &[RgbaImage], options: &EncodeOptions) -> Result<(), IconError> {
+    let data = encode(images, options)?;
+    std::fs::write(path, data).map_err(|source| IconError::Io { path: path.to_path_buf(), source })
+}
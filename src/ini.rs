@@ -0,0 +1,83 @@
+//! The INI dialect read by `GetPrivateProfileString`.
+
+/// Windows-1252 characters for bytes 0x80 to 0x9f; the five bytes it leaves
+/// undefined map to the control characters of the same value, as Windows maps them.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// Decodes text in the ANSI code page, taken to be Windows-1252.
+pub(crate) fn decode_ansi(data: &[u8]) -> String {
+    data.iter()
+        .map(|&byte| match byte {
+            0x80..=0x9f => WINDOWS_1252_HIGH[usize::from(byte - 0x80)],
+            _ => char::from(byte),
+        })
+        .collect()
+}
+
+/// Decodes a text file by its byte order mark: UTF-16 in either byte order,
+/// UTF-8, or without one UTF-8 if valid and Windows-1252 otherwise.
+pub(crate) fn decode_text(data: &[u8]) -> String {
+    let utf16 = |data: &[u8], decode: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = data.chunks_exact(2).map(|unit| decode([unit[0], unit[1]])).collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    match data {
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xef, 0xbb, 0xbf, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        _ => match std::str::from_utf8(data) {
+            Ok(text) => text.to_string(),
+            Err(_) => decode_ansi(data),
+        },
+    }
+}
+
+/// The sections of an INI file and their entries, in file order.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Ini {
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Ini {
+    /// Parses INI text; lines that are neither sections nor `key=value` entries are skipped.
+    pub fn parse(text: &str) -> Ini {
+        let mut ini = Ini::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.split_once(']')) {
+                ini.sections.push((name.0.trim().to_string(), Vec::new()));
+            } else if let (Some((key, value)), Some((_, entries))) = (line.split_once('='), ini.sections.last_mut()) {
+                entries.push((key.trim().to_string(), unquote(value.trim()).to_string()));
+            }
+        }
+        ini
+    }
+
+    /// Returns the first value of `key` in the first `section` that has it, ignoring case in both names.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(section))
+            .flat_map(|(_, entries)| entries)
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Strips one pair of matching quotes surrounding a value, as `GetPrivateProfileString` does.
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|value| value.strip_suffix(quote)) {
+            return inner;
+        }
+    }
+    value
+}
@@ -0,0 +1,114 @@
+//! Internet shortcut (`.url`) files.
+//!
+//! Browsers cache a site's favicon locally and point the shortcut's
+//! `IconFile` at it; other shortcuts point at an icon in an executable.
+
+use crate::desktop_ini::load_icon_near;
+use crate::error::IconError;
+use crate::ini::{decode_text, Ini};
+use crate::location::{expand_environment, IconFile, IconLocation};
+use crate::size::IconSize;
+use crate::windows_tree::WindowsTree;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::path::Path;
+
+const SECTION: &str = "InternetShortcut";
+
+/// Where a shortcut's decoded icon came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InternetShortcutMetadata {
+    /// The site the shortcut opens.
+    pub url: Option<String>,
+    /// The icon location after `file:` URL conversion and variable expansion.
+    pub icon_location: IconLocation,
+}
+
+/// The contents of an `[InternetShortcut]` section.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InternetShortcut {
+    pub url: Option<String>,
+    /// `IconFile`: a path or `file:` URL of the file holding the icon.
+    pub icon_file: Option<String>,
+    /// `IconIndex`, or 0 if absent or not a number.
+    pub icon_index: i32,
+}
+
+impl InternetShortcut {
+    /// Parses a `.url` file held in memory.
+    pub fn parse(data: &[u8]) -> InternetShortcut {
+        let ini = Ini::parse(&decode_text(data));
+        let value = |key| ini.get(SECTION, key).filter(|value| !value.is_empty()).map(str::to_string);
+        InternetShortcut {
+            url: value("URL"),
+            icon_file: value("IconFile"),
+            icon_index: ini.get(SECTION, "IconIndex").and_then(|index| index.trim().parse().ok()).unwrap_or(0),
+        }
+    }
+
+    /// Reads and parses the `.url` file at `path`.
+    pub fn read(path: &Path) -> Result<InternetShortcut, IconError> {
+        let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+        Ok(InternetShortcut::parse(&data))
+    }
+
+    /// Returns the location of the shortcut's icon, if it is a local file.
+    ///
+    /// `file:` URLs are converted to paths; icons on the web have no location.
+    pub fn icon_location(&self, environment: &HashMap<String, String>) -> Option<IconLocation> {
+        let file = self.icon_file.as_deref()?;
+        let path = match file.split_once(':') {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("file") => file_url_path(rest),
+            Some((scheme, _)) if scheme.len() > 1 => return None,
+            _ => file.to_string(),
+        };
+        Some(IconLocation { file: IconFile::Path(expand_environment(&path, environment)), index: self.icon_index })
+    }
+
+    /// Decodes the shortcut's icon, resolving relative paths against the
+    /// host directory `base` and absolute ones within `tree`.
+    pub fn load_icon_as_image(&self, base: &Path, tree: Option<&WindowsTree>, size: IconSize) -> Result<RgbaImage, IconError> {
+        self.load_icon_with_metadata(base, tree, size).map(|(image, _)| image)
+    }
+
+    /// Like [`load_icon_as_image`](Self::load_icon_as_image), also returning the URL and icon location.
+    pub fn load_icon_with_metadata(
+        &self,
+        base: &Path,
+        tree: Option<&WindowsTree>,
+        size: IconSize,
+    ) -> Result<(RgbaImage, InternetShortcutMetadata), IconError> {
+        let environment = tree.map(WindowsTree::environment).unwrap_or_default();
+        let location = self
+            .icon_location(&environment)
+            .ok_or_else(|| IconError::NoIcon { path: base.join(self.icon_file.as_deref().unwrap_or_default()) })?;
+        let image = load_icon_near(base, &location, "", tree, size)?;
+        Ok((image, InternetShortcutMetadata { url: self.url.clone(), icon_location: location }))
+    }
+}
+
+/// Converts the part of a `file:` URL after the scheme to a Windows path.
+fn file_url_path(rest: &str) -> String {
+    // `file://server/share` names a share; `file:///C:/dir` a local path.
+    let (prefix, rest) = match rest.strip_prefix("//") {
+        Some(rest) if !rest.starts_with('/') => ("\\\\", rest),
+        _ => ("", rest.trim_start_matches('/')),
+    };
+
+    let mut bytes = Vec::with_capacity(rest.len());
+    let mut input = rest.as_bytes();
+    while let Some((&byte, tail)) = input.split_first() {
+        match tail {
+            [high, low, tail @ ..] if byte == b'%' && high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                let hex = [*high, *low];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).unwrap(), 16).unwrap());
+                input = tail;
+            }
+            _ => {
+                bytes.push(byte);
+                input = tail;
+            }
+        }
+    }
+    format!("{prefix}{}", String::from_utf8_lossy(&bytes).replace('/', "\\"))
+}
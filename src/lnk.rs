@@ -0,0 +1,213 @@
+//! Shell link (`.lnk`) files, as described by MS-SHLLINK.
+//!
+//! Only the parts that decide which icon a shortcut shows are kept: the
+//! target, the string data and the environment and known-folder blocks.
+
+use crate::bytes::{ansi_string_at, array_at, i32_at, slice_at, u16_at, u32_at, utf16_at};
+use crate::error::{IconError, Stage};
+use crate::ini::decode_ansi;
+use crate::size::IconSize;
+use crate::windows_tree::WindowsTree;
+use image::RgbaImage;
+use std::path::Path;
+
+const HEADER_SIZE: u32 = 0x4c;
+/// `CLSID_ShellLink`, {00021401-0000-0000-C000-000000000046}.
+const LINK_CLSID: [u8; 16] = [0x01, 0x14, 0x02, 0, 0, 0, 0, 0, 0xc0, 0, 0, 0, 0, 0, 0, 0x46];
+
+const HAS_LINK_TARGET_ID_LIST: u32 = 0x1;
+const HAS_LINK_INFO: u32 = 0x2;
+const IS_UNICODE: u32 = 0x80;
+/// Flags of the optional strings, in the order they are stored.
+const STRING_FLAGS: [u32; 5] = [0x4, 0x8, 0x10, 0x20, 0x40];
+
+/// `LinkInfo` flag: the target is on a local volume.
+const VOLUME_ID_AND_LOCAL_BASE_PATH: u32 = 0x1;
+/// `LinkInfo` flag: the target is on a network share.
+const COMMON_NETWORK_RELATIVE_LINK: u32 = 0x2;
+/// `LinkInfo` header size from which Unicode path offsets are present.
+const LINK_INFO_UNICODE_HEADER_SIZE: u32 = 0x24;
+
+const ENVIRONMENT_PROPS: u32 = 0xa000_0001;
+const ICON_ENVIRONMENT_PROPS: u32 = 0xa000_0007;
+const KNOWN_FOLDER_PROPS: u32 = 0xa000_000b;
+/// Offset of the Unicode path in both environment blocks, after the 260-byte ANSI one.
+const ENVIRONMENT_UNICODE_OFFSET: usize = 8 + MAX_PATH;
+const MAX_PATH: usize = 260;
+
+/// The known folder a link target lives in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KnownFolder {
+    /// The folder's `KNOWNFOLDERID`, formatted as `{xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}`.
+    pub id: String,
+    /// Offset of the folder's item within the target ID list.
+    pub offset: u32,
+}
+
+/// The icon-related contents of a shell link.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShellLink {
+    /// Local or network path of the target, from the link info.
+    pub target_path: Option<String>,
+    pub name: Option<String>,
+    pub relative_path: Option<String>,
+    pub working_dir: Option<String>,
+    pub arguments: Option<String>,
+    /// File holding the link's icon.
+    pub icon_location: Option<String>,
+    /// Index of the icon within its file, with `ExtractIconEx` semantics.
+    pub icon_index: i32,
+    /// Target path with environment variables left unexpanded.
+    pub environment_target: Option<String>,
+    /// Icon location with environment variables left unexpanded.
+    pub environment_icon: Option<String>,
+    pub known_folder: Option<KnownFolder>,
+}
+
+impl ShellLink {
+    /// Parses a shell link held in memory.
+    pub fn parse(data: &[u8]) -> Result<ShellLink, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::ShellLink };
+
+        if u32_at(data, 0).ok_or_else(truncated)? != HEADER_SIZE || array_at(data, 4).ok_or_else(truncated)? != LINK_CLSID {
+            return Err(IconError::Malformed { stage: Stage::ShellLink });
+        }
+        let flags = u32_at(data, 20).ok_or_else(truncated)?;
+        let mut link = ShellLink { icon_index: i32_at(data, 56).ok_or_else(truncated)?, ..ShellLink::default() };
+
+        let mut offset = HEADER_SIZE as usize;
+        if flags & HAS_LINK_TARGET_ID_LIST != 0 {
+            offset += 2 + usize::from(u16_at(data, offset).ok_or_else(truncated)?);
+        }
+        if flags & HAS_LINK_INFO != 0 {
+            let size = u32_at(data, offset).ok_or_else(truncated)? as usize;
+            link.target_path = link_info_path(slice_at(data, offset, size).ok_or_else(truncated)?)?;
+            offset += size;
+        }
+
+        let unicode = flags & IS_UNICODE != 0;
+        let mut strings = Vec::new();
+        for flag in STRING_FLAGS {
+            if flags & flag == 0 {
+                strings.push(None);
+                continue;
+            }
+            let count = usize::from(u16_at(data, offset).ok_or_else(truncated)?);
+            let len = if unicode { count * 2 } else { count };
+            let bytes = slice_at(data, offset + 2, len).ok_or_else(truncated)?;
+            strings.push(Some(if unicode { utf16_at(bytes, 0, count).unwrap() } else { decode_ansi(bytes) }));
+            offset += 2 + len;
+        }
+        let [name, relative_path, working_dir, arguments, icon_location] = strings.try_into().unwrap();
+        link.name = name;
+        link.relative_path = relative_path;
+        link.working_dir = working_dir;
+        link.arguments = arguments;
+        link.icon_location = icon_location;
+
+        // Extra data blocks run until one smaller than a block header.
+        while let Some(size) = u32_at(data, offset).map(|size| size as usize).filter(|&size| size >= 8) {
+            let block = slice_at(data, offset, size).ok_or_else(truncated)?;
+            match u32_at(block, 4).unwrap() {
+                ENVIRONMENT_PROPS => link.environment_target = environment_path(block),
+                ICON_ENVIRONMENT_PROPS => link.environment_icon = environment_path(block),
+                KNOWN_FOLDER_PROPS => {
+                    let id = array_at(block, 8).ok_or_else(truncated)?;
+                    let offset = u32_at(block, 24).ok_or_else(truncated)?;
+                    link.known_folder = Some(KnownFolder { id: format_guid(&id), offset });
+                }
+                _ => {}
+            }
+            offset += size;
+        }
+
+        Ok(link)
+    }
+
+    /// Reads and parses the shell link at `path`.
+    pub fn read(path: &Path) -> Result<ShellLink, IconError> {
+        let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+        ShellLink::parse(&data)
+    }
+
+    /// Returns the file and index the shell takes the link's icon from.
+    ///
+    /// An explicit icon location wins, preferring its unexpanded form;
+    /// otherwise the target's own icon is used.
+    pub fn icon_source(&self) -> Option<(&str, i32)> {
+        self.environment_icon
+            .as_deref()
+            .or(self.icon_location.as_deref())
+            .or(self.environment_target.as_deref())
+            .or(self.target_path.as_deref())
+            .filter(|path| !path.is_empty())
+            .map(|path| (path, self.icon_index))
+    }
+
+    /// Decodes the link's icon from a mounted Windows tree.
+    pub fn load_icon_as_image(&self, tree: &WindowsTree, size: IconSize) -> Result<RgbaImage, IconError> {
+        let (path, index) = self.icon_source().ok_or(IconError::Malformed { stage: Stage::ShellLink })?;
+        tree.load_icon_as_image(path, index, size)
+    }
+}
+
+/// Returns the target path recorded in a `LinkInfo` structure, preferring its Unicode form.
+fn link_info_path(info: &[u8]) -> Result<Option<String>, IconError> {
+    let truncated = || IconError::Truncated { stage: Stage::LinkInfo };
+
+    let header_size = u32_at(info, 4).ok_or_else(truncated)?;
+    let flags = u32_at(info, 8).ok_or_else(truncated)?;
+    let unicode = header_size >= LINK_INFO_UNICODE_HEADER_SIZE;
+    let string_at = |ansi_field: usize, unicode_field: usize| -> Result<String, IconError> {
+        if unicode {
+            let offset = u32_at(info, unicode_field).ok_or_else(truncated)? as usize;
+            if offset != 0 {
+                let units = info.len().saturating_sub(offset) / 2;
+                return utf16_at(info, offset, units).ok_or_else(truncated);
+            }
+        }
+        let offset = u32_at(info, ansi_field).ok_or_else(truncated)? as usize;
+        ansi_string_at(info, offset).map(decode_ansi).ok_or_else(truncated)
+    };
+
+    let suffix = string_at(24, 32)?;
+    if flags & VOLUME_ID_AND_LOCAL_BASE_PATH != 0 {
+        return Ok(Some(string_at(16, 28)? + &suffix));
+    }
+    if flags & COMMON_NETWORK_RELATIVE_LINK != 0 {
+        let network = u32_at(info, 20).ok_or_else(truncated)? as usize;
+        let share_offset = network.checked_add(8).and_then(|at| u32_at(info, at)).ok_or_else(truncated)?;
+        let share = network
+            .checked_add(share_offset as usize)
+            .and_then(|at| ansi_string_at(info, at))
+            .map(decode_ansi)
+            .ok_or_else(truncated)?;
+        return Ok(Some(if suffix.is_empty() { share } else { format!("{share}\\{suffix}") }));
+    }
+    Ok(None)
+}
+
+/// Returns the path of an environment block, preferring its Unicode form.
+fn environment_path(block: &[u8]) -> Option<String> {
+    utf16_at(block, ENVIRONMENT_UNICODE_OFFSET, MAX_PATH)
+        .filter(|path| !path.is_empty())
+        .or_else(|| ansi_string_at(block.get(..ENVIRONMENT_UNICODE_OFFSET)?, 8).map(decode_ansi))
+        .filter(|path| !path.is_empty())
+}
+
+fn format_guid(guid: &[u8; 16]) -> String {
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        guid[8],
+        guid[9],
+        guid[10],
+        guid[11],
+        guid[12],
+        guid[13],
+        guid[14],
+        guid[15],
+    )
+}
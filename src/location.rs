@@ -0,0 +1,111 @@
+//! Icon location strings such as `"%SystemRoot%\System32\imageres.dll",-102`.
+//!
+//! The same syntax appears in registry `DefaultIcon` values, `desktop.ini`
+//! files and shell links: a path, optionally quoted, optionally followed by a
+//! comma and an `ExtractIconEx` index.
+
+use crate::error::{IconError, Stage};
+use std::collections::HashMap;
+
+/// The file an icon location points at.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IconFile {
+    Path(String),
+    /// `%1`: the file whose icon is being looked up, such as an `.ico` or `.exe` with its own icon.
+    Itself,
+}
+
+/// A parsed icon location.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IconLocation {
+    pub file: IconFile,
+    /// Index with `ExtractIconEx` semantics: an ordinal if non-negative, a resource ID if negative.
+    pub index: i32,
+}
+
+impl IconLocation {
+    /// Parses an icon location, expanding `%VAR%` references from `environment`.
+    ///
+    /// Variable names match case-insensitively and unknown variables are left
+    /// as written, as `ExpandEnvironmentStrings` does. A leading `@`, used by
+    /// indirect strings, is ignored.
+    pub fn parse(text: &str, environment: &HashMap<String, String>) -> Result<IconLocation, IconError> {
+        let malformed = || IconError::Malformed { stage: Stage::IconLocation };
+
+        let text = text.trim();
+        let text = text.strip_prefix('@').unwrap_or(text);
+
+        let (path, index) = if let Some(quoted) = text.strip_prefix('"') {
+            let (path, rest) = quoted.split_once('"').ok_or_else(malformed)?;
+            let rest = rest.trim_start();
+            match rest.strip_prefix(',') {
+                Some(index) => (path, parse_index(index).ok_or_else(malformed)?),
+                None if rest.is_empty() => split_index(path),
+                None => return Err(malformed()),
+            }
+        } else {
+            split_index(text)
+        };
+
+        let path = path.trim();
+        if path.is_empty() {
+            return Err(malformed());
+        }
+        let file = match path {
+            "%1" => IconFile::Itself,
+            path => IconFile::Path(expand_environment(path, environment)),
+        };
+        Ok(IconLocation { file, index })
+    }
+
+    /// Returns the path of the icon file, substituting `document` for `%1`.
+    pub fn path<'a>(&'a self, document: &'a str) -> &'a str {
+        match &self.file {
+            IconFile::Path(path) => path,
+            IconFile::Itself => document,
+        }
+    }
+}
+
+fn parse_index(text: &str) -> Option<i32> {
+    match text.trim() {
+        "" => Some(0),
+        index => index.parse().ok(),
+    }
+}
+
+/// Splits a trailing `,index` off `text`, leaving commas that are not followed by a number in the path.
+fn split_index(text: &str) -> (&str, i32) {
+    text.rsplit_once(',')
+        .and_then(|(path, index)| Some((path, parse_index(index)?)))
+        .unwrap_or((text, 0))
+}
+
+/// Replaces `%VAR%` references with their values from `environment`.
+pub fn expand_environment(text: &str, environment: &HashMap<String, String>) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('%') else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let name = &after[..end];
+        match environment.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)) {
+            Some((_, value)) if !name.is_empty() => {
+                expanded.push_str(value);
+                rest = &after[end + 1..];
+            }
+            // Leave the first `%` as written; the second may open a variable.
+            _ => {
+                expanded.push('%');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
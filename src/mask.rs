@@ -0,0 +1,105 @@
+//! Transparency from the 1-bpp AND masks that accompany icon and cursor bitmaps.
+//!
+//! A set mask bit marks a pixel as transparent. Where such a pixel also has a
+//! non-black colour, Windows inverts the screen beneath it instead; those
+//! pixels come back transparent and are listed in [`MaskOutcome::inverted`].
+
+use crate::error::{IconError, Stage};
+use image::RgbaImage;
+
+/// What combining a colour bitmap with its AND mask did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MaskOutcome {
+    /// True if alpha was built from the mask because the colour bitmap carried none.
+    pub alpha_from_mask: bool,
+    /// Coordinates of pixels that invert the screen rather than draw a colour.
+    pub inverted: Vec<(u32, u32)>,
+}
+
+/// Returns the number of bytes in each row of a 1-bpp mask, padded to a 4-byte boundary.
+pub fn mask_stride(width: u32) -> usize {
+    (width as usize).div_ceil(32) * 4
+}
+
+/// Combines a decoded colour bitmap with its AND mask.
+///
+/// The colour alpha wins whenever any pixel has one; otherwise alpha is built
+/// from the mask. `top_down` gives the row order of `mask`.
+pub fn apply_and_mask(image: &mut RgbaImage, mask: &[u8], top_down: bool) -> Result<MaskOutcome, IconError> {
+    let (width, height) = image.dimensions();
+    let rows = MaskRows::new(mask, width, height, top_down)?;
+
+    if image.pixels().any(|pixel| pixel[3] != 0) {
+        return Ok(MaskOutcome::default());
+    }
+
+    let mut outcome = MaskOutcome { alpha_from_mask: true, inverted: Vec::new() };
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if rows.is_set(x, y) {
+            pixel[3] = 0;
+            if pixel.0[..3] != [0, 0, 0] {
+                outcome.inverted.push((x, y));
+            }
+        } else {
+            pixel[3] = 0xFF;
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Decodes a monochrome icon, whose mask holds the AND bitmap above the XOR bitmap.
+///
+/// `width` and `height` are the icon dimensions, so `mask` holds `2 * height`
+/// rows. XOR bits select white over black for opaque pixels.
+pub fn decode_monochrome(
+    mask: &[u8],
+    width: u32,
+    height: u32,
+    top_down: bool,
+) -> Result<(RgbaImage, MaskOutcome), IconError> {
+    let double_height = height.checked_mul(2).ok_or(IconError::InvalidDimensions {
+        width: i64::from(width),
+        height: i64::from(height),
+    })?;
+    let rows = MaskRows::new(mask, width, double_height, top_down)?;
+
+    let mut outcome = MaskOutcome { alpha_from_mask: true, inverted: Vec::new() };
+    let image = RgbaImage::from_fn(width, height, |x, y| {
+        match (rows.is_set(x, y), rows.is_set(x, y + height)) {
+            (false, false) => image::Rgba([0, 0, 0, 0xFF]),
+            (false, true) => image::Rgba([0xFF, 0xFF, 0xFF, 0xFF]),
+            (true, false) => image::Rgba([0, 0, 0, 0]),
+            (true, true) => {
+                outcome.inverted.push((x, y));
+                image::Rgba([0xFF, 0xFF, 0xFF, 0])
+            }
+        }
+    });
+
+    Ok((image, outcome))
+}
+
+/// Row-order-aware bit lookup into a 1-bpp mask.
+struct MaskRows<'a> {
+    mask: &'a [u8],
+    stride: usize,
+    height: u32,
+    top_down: bool,
+}
+
+impl<'a> MaskRows<'a> {
+    fn new(mask: &'a [u8], width: u32, height: u32, top_down: bool) -> Result<MaskRows<'a>, IconError> {
+        let stride = mask_stride(width);
+        if stride.checked_mul(height as usize).is_none_or(|needed| mask.len() < needed) {
+            return Err(IconError::Truncated { stage: Stage::Mask });
+        }
+        Ok(MaskRows { mask, stride, height, top_down })
+    }
+
+    fn is_set(&self, x: u32, y: u32) -> bool {
+        let row = if self.top_down { y } else { self.height - 1 - y };
+        let byte = self.mask[row as usize * self.stride + x as usize / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
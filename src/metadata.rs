@@ -0,0 +1,25 @@
+//! Information about an extracted icon beyond its pixels.
+
+use crate::alpha::AlphaMode;
+
+/// Describes where an icon image came from and how it was stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IconMetadata {
+    pub width: u32,
+    pub height: u32,
+    /// Click point for cursors; the centre for icons created by the shell.
+    pub hotspot: (u32, u32),
+    /// True if the handle describes a cursor rather than an icon.
+    pub is_cursor: bool,
+    /// Bit depth of the source bitmap before conversion to RGBA.
+    pub bit_depth: u16,
+    /// Alpha convention the source bitmap appears to use, as guessed by
+    /// [`detect_alpha_mode`](crate::detect_alpha_mode). The pixels are returned
+    /// unchanged; pass them to [`unpremultiply_image`](crate::unpremultiply_image)
+    /// to act on a `Premultiplied` guess.
+    pub alpha_mode: AlphaMode,
+    /// True if alpha was built from the AND mask because the colour bitmap carried none.
+    pub alpha_from_mask: bool,
+    /// Pixels that invert the screen beneath them; they come back transparent.
+    pub inverted: Vec<(u32, u32)>,
+}
@@ -0,0 +1,130 @@
+//! Resources of 16-bit NE executables.
+
+use crate::bytes::{slice_at, u16_at, u32_at};
+use crate::error::{IconError, Stage};
+use crate::resource::{IconResources, ResourceId};
+
+/// Offset of the resource table, relative to the NE header.
+const RESOURCE_TABLE_OFFSET: usize = 0x24;
+/// Offset of the resident name table, which follows the resource table.
+const RESIDENT_NAMES_OFFSET: usize = 0x26;
+const TYPE_INFO_SIZE: usize = 8;
+const NAME_INFO_SIZE: usize = 12;
+/// Set on type and resource IDs that are integers rather than string offsets.
+const INTEGER_ID: u16 = 0x8000;
+
+struct NeResource {
+    resource_type: ResourceId,
+    name: ResourceId,
+    offset: usize,
+    len: usize,
+}
+
+/// An NE image and its resource table.
+pub struct NeFile<'a> {
+    data: &'a [u8],
+    resources: Vec<NeResource>,
+}
+
+impl<'a> NeFile<'a> {
+    /// Parses the header and resource table of an NE image.
+    pub fn parse(data: &'a [u8]) -> Result<NeFile<'a>, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::ExecutableHeader };
+
+        if !data.starts_with(b"MZ") {
+            return Err(IconError::Malformed { stage: Stage::ExecutableHeader });
+        }
+        let header = u32_at(data, 0x3c).ok_or_else(truncated)? as usize;
+        if slice_at(data, header, 2).ok_or_else(truncated)? != b"NE" {
+            return Err(IconError::Malformed { stage: Stage::ExecutableHeader });
+        }
+
+        let table_offset = usize::from(u16_at(data, header + RESOURCE_TABLE_OFFSET).ok_or_else(truncated)?);
+        let names_offset = usize::from(u16_at(data, header + RESIDENT_NAMES_OFFSET).ok_or_else(truncated)?);
+        if table_offset == names_offset {
+            return Ok(NeFile { data, resources: Vec::new() });
+        }
+
+        let resources = NeFile::resource_table(data, header + table_offset)?;
+        Ok(NeFile { data, resources })
+    }
+
+    /// Reads every `TYPEINFO` and `NAMEINFO` record of the resource table at `table`.
+    fn resource_table(data: &[u8], table: usize) -> Result<Vec<NeResource>, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::ResourceDirectory };
+
+        // Offsets and lengths are stored in units of 2^shift bytes.
+        let shift = u16_at(data, table).ok_or_else(truncated)?;
+        if shift >= 32 {
+            return Err(IconError::Malformed { stage: Stage::ResourceDirectory });
+        }
+
+        let mut resources = Vec::new();
+        let mut type_info = table + 2;
+        loop {
+            let type_id = u16_at(data, type_info).ok_or_else(truncated)?;
+            if type_id == 0 {
+                break;
+            }
+            let resource_type = NeFile::id(data, table, type_id)?;
+            let count = usize::from(u16_at(data, type_info + 2).ok_or_else(truncated)?);
+
+            for index in 0..count {
+                let name_info = slice_at(data, type_info + TYPE_INFO_SIZE + index * NAME_INFO_SIZE, NAME_INFO_SIZE).ok_or_else(truncated)?;
+                resources.push(NeResource {
+                    resource_type: resource_type.clone(),
+                    name: NeFile::id(data, table, u16_at(name_info, 6).unwrap())?,
+                    offset: (u16_at(name_info, 0).unwrap() as usize) << shift,
+                    len: (u16_at(name_info, 2).unwrap() as usize) << shift,
+                });
+            }
+            type_info += TYPE_INFO_SIZE + count * NAME_INFO_SIZE;
+        }
+        Ok(resources)
+    }
+
+    /// Decodes an integer ID or a length-prefixed name stored at an offset from the resource table.
+    fn id(data: &[u8], table: usize, raw: u16) -> Result<ResourceId, IconError> {
+        if raw & INTEGER_ID != 0 {
+            return Ok(ResourceId::Id(raw & !INTEGER_ID));
+        }
+
+        let truncated = || IconError::Truncated { stage: Stage::ResourceDirectory };
+        let string = table + usize::from(raw);
+        let len = usize::from(*data.get(string).ok_or_else(truncated)?);
+        let name = slice_at(data, string + 1, len).ok_or_else(truncated)?;
+        Ok(ResourceId::Name(name.iter().map(|&byte| char::from(byte)).collect()))
+    }
+
+    /// Returns the data of a resource.
+    pub(crate) fn find(&self, resource_type: u16, name: &ResourceId) -> Result<Option<&'a [u8]>, IconError> {
+        let resource_type = ResourceId::Id(resource_type);
+        let Some(resource) = self
+            .resources
+            .iter()
+            .find(|resource| resource.resource_type.matches(&resource_type) && resource.name.matches(name))
+        else {
+            return Ok(None);
+        };
+
+        // Lengths are rounded up to the alignment, so the last resource may run past the end of the file.
+        let data = self.data.get(resource.offset..).ok_or(IconError::Truncated { stage: Stage::ResourceDirectory })?;
+        Ok(Some(&data[..resource.len.min(data.len())]))
+    }
+}
+
+impl IconResources for NeFile<'_> {
+    fn resource_names(&self, resource_type: u16) -> Result<Vec<ResourceId>, IconError> {
+        let resource_type = ResourceId::Id(resource_type);
+        Ok(self
+            .resources
+            .iter()
+            .filter(|resource| resource.resource_type.matches(&resource_type))
+            .map(|resource| resource.name.clone())
+            .collect())
+    }
+
+    fn resource(&self, resource_type: u16, name: &ResourceId) -> Result<Option<&[u8]>, IconError> {
+        self.find(resource_type, name)
+    }
+}
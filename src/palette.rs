@@ -0,0 +1,72 @@
+//! Colour reduction for paletted bitmaps.
+
+/// Builds a palette of at most `capacity` colours for `colors`.
+///
+/// Colours are kept exactly when they fit; otherwise the palette is built by
+/// median cut, repeatedly halving the box of colours with the widest channel.
+pub(crate) fn quantize(colors: &[[u8; 3]], capacity: usize) -> Vec<[u8; 3]> {
+    let mut unique = colors.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+    if unique.len() <= capacity {
+        return unique;
+    }
+
+    let mut boxes = vec![colors.to_vec()];
+    while boxes.len() < capacity {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, colors)| {
+                let (channel, range) = widest_channel(colors);
+                (index, channel, range)
+            })
+            .filter(|&(_, _, range)| range > 0)
+            .max_by_key(|&(_, _, range)| range);
+        let Some((index, channel, _)) = widest else { break };
+
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|color| color[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|colors| average(colors)).collect()
+}
+
+/// Returns the channel with the largest spread of values and that spread.
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|color| color[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
+
+fn average(colors: &[[u8; 3]]) -> [u8; 3] {
+    let mut sums = [0u64; 3];
+    for color in colors {
+        for (sum, &value) in sums.iter_mut().zip(color) {
+            *sum += u64::from(value);
+        }
+    }
+    let count = colors.len().max(1) as u64;
+    sums.map(|sum| ((sum + count / 2) / count) as u8)
+}
+
+/// Returns the index of the palette entry closest to `color`.
+pub(crate) fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> usize {
+    let distance = |entry: &[u8; 3]| -> u32 {
+        entry.iter().zip(color).map(|(&a, b)| (i32::from(a) - i32::from(b)).pow(2) as u32).sum()
+    };
+
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry))
+        .map_or(0, |(index, _)| index)
+}
@@ -0,0 +1,174 @@
+//! Resources of PE32 and PE32+ executables, read without the Windows loader.
+
+use crate::bytes::{slice_at, u16_at, u32_at};
+use crate::error::{IconError, Stage};
+use crate::resource::{IconResources, ResourceId};
+
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const COFF_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+/// Index of the resource table among the optional header's data directories.
+const RESOURCE_DIRECTORY_INDEX: usize = 2;
+/// Set on directory entries that name a string or point to a subdirectory.
+const HIGH_BIT: u32 = 0x8000_0000;
+
+struct Section {
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_offset: u32,
+    raw_size: u32,
+}
+
+/// A PE image and its resource directory.
+pub struct PeFile<'a> {
+    data: &'a [u8],
+    sections: Vec<Section>,
+    /// File offset of the root resource directory, if the image has resources.
+    resources: Option<usize>,
+}
+
+impl<'a> PeFile<'a> {
+    /// Parses the headers and section table of a PE32 or PE32+ image.
+    pub fn parse(data: &'a [u8]) -> Result<PeFile<'a>, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::ExecutableHeader };
+        let malformed = || IconError::Malformed { stage: Stage::ExecutableHeader };
+
+        if !data.starts_with(b"MZ") {
+            return Err(malformed());
+        }
+        let pe_offset = u32_at(data, 0x3c).ok_or_else(truncated)? as usize;
+        if slice_at(data, pe_offset, 4).ok_or_else(truncated)? != b"PE\0\0" {
+            return Err(malformed());
+        }
+
+        let coff = pe_offset + 4;
+        let section_count = usize::from(u16_at(data, coff + 2).ok_or_else(truncated)?);
+        let optional_size = usize::from(u16_at(data, coff + 16).ok_or_else(truncated)?);
+        let optional = slice_at(data, coff + COFF_HEADER_SIZE, optional_size).ok_or_else(truncated)?;
+
+        let (count_offset, directories_offset) = match u16_at(optional, 0).ok_or_else(truncated)? {
+            PE32_MAGIC => (92, 96),
+            PE32_PLUS_MAGIC => (108, 112),
+            _ => return Err(malformed()),
+        };
+
+        let section_table = coff + COFF_HEADER_SIZE + optional_size;
+        let sections = (0..section_count)
+            .map(|index| {
+                let header = slice_at(data, section_table + index * SECTION_HEADER_SIZE, SECTION_HEADER_SIZE).ok_or_else(truncated)?;
+                Ok(Section {
+                    virtual_size: u32_at(header, 8).unwrap(),
+                    virtual_address: u32_at(header, 12).unwrap(),
+                    raw_size: u32_at(header, 16).unwrap(),
+                    raw_offset: u32_at(header, 20).unwrap(),
+                })
+            })
+            .collect::<Result<Vec<_>, IconError>>()?;
+
+        let mut file = PeFile { data, sections, resources: None };
+
+        let directory_count = u32_at(optional, count_offset).unwrap_or(0) as usize;
+        if directory_count > RESOURCE_DIRECTORY_INDEX {
+            let rva = u32_at(optional, directories_offset + RESOURCE_DIRECTORY_INDEX * 8).ok_or_else(truncated)?;
+            if rva != 0 {
+                file.resources = Some(file.rva_to_offset(rva).ok_or_else(truncated)?);
+            }
+        }
+        Ok(file)
+    }
+
+    /// Maps a relative virtual address to an offset in the file.
+    fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        self.sections.iter().find_map(|section| {
+            let delta = rva.checked_sub(section.virtual_address)?;
+            let size = section.virtual_size.max(section.raw_size);
+            if delta >= size {
+                return None;
+            }
+            (section.raw_offset as usize).checked_add(delta as usize)
+        })
+    }
+
+    /// Lists a resource directory as `(name, offset to data)` pairs, with the
+    /// offsets still carrying the subdirectory flag.
+    fn directory(&self, root: usize, offset: usize) -> Result<Vec<(ResourceId, u32)>, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::ResourceDirectory };
+
+        let start = root.checked_add(offset).ok_or_else(truncated)?;
+        let header = slice_at(self.data, start, 16).ok_or_else(truncated)?;
+        let named = usize::from(u16_at(header, 12).unwrap());
+        let ids = usize::from(u16_at(header, 14).unwrap());
+        let entries = start + header.len();
+
+        (0..named + ids)
+            .map(|index| {
+                let entry = entries.checked_add(index * 8).and_then(|at| slice_at(self.data, at, 8)).ok_or_else(truncated)?;
+                let name = u32_at(entry, 0).unwrap();
+                let target = u32_at(entry, 4).unwrap();
+
+                let name = if name & HIGH_BIT != 0 {
+                    let string = root.checked_add((name & !HIGH_BIT) as usize).ok_or_else(truncated)?;
+                    let len = usize::from(u16_at(self.data, string).ok_or_else(truncated)?);
+                    let units = slice_at(self.data, string + 2, len * 2).ok_or_else(truncated)?;
+                    let units: Vec<u16> = units.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+                    ResourceId::Name(String::from_utf16_lossy(&units))
+                } else {
+                    ResourceId::Id(name as u16)
+                };
+                Ok((name, target))
+            })
+            .collect()
+    }
+
+    /// Returns the subdirectory a directory entry points to.
+    fn subdirectory(target: u32) -> Result<usize, IconError> {
+        if target & HIGH_BIT == 0 {
+            return Err(IconError::Malformed { stage: Stage::ResourceDirectory });
+        }
+        Ok((target & !HIGH_BIT) as usize)
+    }
+
+    /// Returns the name directory for a resource type.
+    fn type_directory(&self, root: usize, resource_type: u16) -> Result<Option<usize>, IconError> {
+        let wanted = ResourceId::Id(resource_type);
+        match self.directory(root, 0)?.into_iter().find(|(name, _)| name.matches(&wanted)) {
+            Some((_, target)) => Ok(Some(PeFile::subdirectory(target)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the first language of a resource, as `FindResource` does for a neutral thread.
+    pub(crate) fn find(&self, resource_type: u16, name: &ResourceId) -> Result<Option<&'a [u8]>, IconError> {
+        let truncated = || IconError::Truncated { stage: Stage::ResourceDirectory };
+
+        let Some(root) = self.resources else { return Ok(None) };
+        let Some(names) = self.type_directory(root, resource_type)? else { return Ok(None) };
+        let Some((_, target)) = self.directory(root, names)?.into_iter().find(|(found, _)| found.matches(name)) else {
+            return Ok(None);
+        };
+        let Some(&(_, data_entry)) = self.directory(root, PeFile::subdirectory(target)?)?.first() else {
+            return Ok(None);
+        };
+        if data_entry & HIGH_BIT != 0 {
+            return Err(IconError::Malformed { stage: Stage::ResourceDirectory });
+        }
+
+        let entry = root.checked_add(data_entry as usize).and_then(|at| slice_at(self.data, at, 8)).ok_or_else(truncated)?;
+        let offset = self.rva_to_offset(u32_at(entry, 0).unwrap()).ok_or_else(truncated)?;
+        let len = u32_at(entry, 4).unwrap() as usize;
+        Ok(Some(slice_at(self.data, offset, len).ok_or_else(truncated)?))
+    }
+}
+
+impl IconResources for PeFile<'_> {
+    fn resource_names(&self, resource_type: u16) -> Result<Vec<ResourceId>, IconError> {
+        let Some(root) = self.resources else { return Ok(Vec::new()) };
+        let Some(names) = self.type_directory(root, resource_type)? else { return Ok(Vec::new()) };
+        Ok(self.directory(root, names)?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    fn resource(&self, resource_type: u16, name: &ResourceId) -> Result<Option<&[u8]>, IconError> {
+        self.find(resource_type, name)
+    }
+}
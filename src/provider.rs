@@ -0,0 +1,153 @@
+//! Pluggable sources of icons.
+//!
+//! [`IconProvider`] abstracts over where icons come from so that code built on
+//! this crate can run against the live shell on Windows, against in-memory
+//! fixtures in tests, or against any other backend. Providers can be layered
+//! with [`ProviderChain`].
+
+use crate::error::IconError;
+use crate::size::IconSize;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A source of icons for files, extensions and MIME types.
+pub trait IconProvider {
+    /// Returns the icon the file at `path` is displayed with.
+    fn icon_for_path(&self, path: &Path, size: IconSize) -> Result<RgbaImage, IconError>;
+
+    /// Returns the icon for files with extension `extension`, given with or without its leading dot.
+    fn icon_for_extension(&self, extension: &str, size: IconSize) -> Result<RgbaImage, IconError>;
+
+    /// Returns the icon for files of MIME type `mime`.
+    fn icon_for_mime(&self, mime: &str, size: IconSize) -> Result<RgbaImage, IconError>;
+}
+
+impl<P: IconProvider + ?Sized> IconProvider for Box<P> {
+    fn icon_for_path(&self, path: &Path, size: IconSize) -> Result<RgbaImage, IconError> {
+        (**self).icon_for_path(path, size)
+    }
+
+    fn icon_for_extension(&self, extension: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        (**self).icon_for_extension(extension, size)
+    }
+
+    fn icon_for_mime(&self, mime: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        (**self).icon_for_mime(mime, size)
+    }
+}
+
+/// Lower-cases an extension and strips its leading dot.
+pub(crate) fn normalize_extension(extension: &str) -> String {
+    extension.strip_prefix('.').unwrap_or(extension).to_ascii_lowercase()
+}
+
+/// An in-memory provider that serves fixed images, for tests and non-Windows hosts.
+///
+/// Images are returned as stored regardless of the requested size. Extension
+/// and MIME lookups are case-insensitive.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryIconProvider {
+    paths: HashMap<PathBuf, RgbaImage>,
+    extensions: HashMap<String, RgbaImage>,
+    mimes: HashMap<String, RgbaImage>,
+}
+
+impl MemoryIconProvider {
+    pub fn new() -> MemoryIconProvider {
+        MemoryIconProvider::default()
+    }
+
+    /// Registers the icon returned for `path`.
+    pub fn insert_path(&mut self, path: impl Into<PathBuf>, image: RgbaImage) {
+        self.paths.insert(path.into(), image);
+    }
+
+    /// Registers the icon returned for `extension`.
+    pub fn insert_extension(&mut self, extension: &str, image: RgbaImage) {
+        self.extensions.insert(normalize_extension(extension), image);
+    }
+
+    /// Registers the icon returned for `mime`.
+    pub fn insert_mime(&mut self, mime: &str, image: RgbaImage) {
+        self.mimes.insert(mime.to_ascii_lowercase(), image);
+    }
+}
+
+impl IconProvider for MemoryIconProvider {
+    /// Returns the icon registered for `path`, falling back to the one registered for its extension.
+    fn icon_for_path(&self, path: &Path, size: IconSize) -> Result<RgbaImage, IconError> {
+        if let Some(image) = self.paths.get(path) {
+            return Ok(image.clone());
+        }
+
+        path.extension()
+            .and_then(|extension| self.icon_for_extension(&extension.to_string_lossy(), size).ok())
+            .ok_or_else(|| IconError::NoIcon { path: path.to_path_buf() })
+    }
+
+    fn icon_for_extension(&self, extension: &str, _size: IconSize) -> Result<RgbaImage, IconError> {
+        self.extensions
+            .get(&normalize_extension(extension))
+            .cloned()
+            .ok_or_else(|| IconError::NoIconForExtension(extension.to_string()))
+    }
+
+    fn icon_for_mime(&self, mime: &str, _size: IconSize) -> Result<RgbaImage, IconError> {
+        self.mimes
+            .get(&mime.to_ascii_lowercase())
+            .cloned()
+            .ok_or_else(|| IconError::NoIconForMime(mime.to_string()))
+    }
+}
+
+/// Tries a list of providers in order and returns the first icon found.
+///
+/// If every provider fails, the error from the last one is returned.
+#[derive(Default)]
+pub struct ProviderChain {
+    providers: Vec<Box<dyn IconProvider>>,
+}
+
+impl ProviderChain {
+    pub fn new(providers: Vec<Box<dyn IconProvider>>) -> ProviderChain {
+        ProviderChain { providers }
+    }
+
+    /// Appends a provider that is consulted after all existing ones.
+    pub fn push(&mut self, provider: impl IconProvider + 'static) {
+        self.providers.push(Box::new(provider));
+    }
+
+    fn first_success(
+        &self,
+        not_found: IconError,
+        lookup: impl Fn(&dyn IconProvider) -> Result<RgbaImage, IconError>,
+    ) -> Result<RgbaImage, IconError> {
+        let mut last_error = not_found;
+        for provider in &self.providers {
+            match lookup(provider.as_ref()) {
+                Ok(image) => return Ok(image),
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+impl IconProvider for ProviderChain {
+    fn icon_for_path(&self, path: &Path, size: IconSize) -> Result<RgbaImage, IconError> {
+        let not_found = IconError::NoIcon { path: path.to_path_buf() };
+        self.first_success(not_found, |provider| provider.icon_for_path(path, size))
+    }
+
+    fn icon_for_extension(&self, extension: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        let not_found = IconError::NoIconForExtension(extension.to_string());
+        self.first_success(not_found, |provider| provider.icon_for_extension(extension, size))
+    }
+
+    fn icon_for_mime(&self, mime: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        let not_found = IconError::NoIconForMime(mime.to_string());
+        self.first_success(not_found, |provider| provider.icon_for_mime(mime, size))
+    }
+}
@@ -0,0 +1,384 @@
+//! Registry exports in the text format written by `regedit`, either
+//! `REGEDIT4` or `Windows Registry Editor Version 5.00`, and the registry
+//! files of a Wine prefix, which use a dialect of the same format.
+//!
+//! An export is a list of changes applied in order: `[key]` creates a key and
+//! `[-key]` deletes it with its subkeys, `"name"=data` sets a value and
+//! `"name"=-` deletes it. Version 5 files are UTF-16 and store the strings of
+//! `hex(2)` and `hex(7)` values in UTF-16LE; `REGEDIT4` files use the ANSI
+//! code page, read as Windows-1252.
+//! Value lines ending in a backslash continue on the next line.
+//!
+//! Wine's files escape key paths and strings as C does, follow each key with
+//! its modification time, write typed strings as `str(type):"..."`, and hold
+//! keys relative to the predefined key named in a `;; All keys relative to`
+//! comment.
+
+use crate::association::Associations;
+use crate::error::{IconError, Stage};
+use crate::ini::{decode_ansi, decode_text};
+use crate::registry::{fold_name, path_components, RegValue, Registry, REG_BINARY, REG_EXPAND_SZ, REG_MULTI_SZ, REG_SZ};
+use std::collections::HashMap;
+use std::path::Path;
+
+const REGEDIT4: &str = "REGEDIT4";
+const REGEDIT5: &str = "Windows Registry Editor Version 5.00";
+const WINE: &str = "WINE REGISTRY Version 2";
+const WINE_RELATIVE_TO: &str = ";; All keys relative to ";
+
+/// The variants of the text format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dialect {
+    Regedit4,
+    Regedit5,
+    Wine,
+}
+
+/// Abbreviated names of the predefined keys, accepted in place of the full ones.
+const ROOT_ABBREVIATIONS: [(&str, &str); 5] = [
+    ("HKLM", "HKEY_LOCAL_MACHINE"),
+    ("HKCU", "HKEY_CURRENT_USER"),
+    ("HKCR", "HKEY_CLASSES_ROOT"),
+    ("HKU", "HKEY_USERS"),
+    ("HKCC", "HKEY_CURRENT_CONFIG"),
+];
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    name: String,
+    values: Vec<(String, RegValue)>,
+    subkeys: Vec<Node>,
+    /// Positions in `values` and `subkeys`, by folded name.
+    value_index: HashMap<String, usize>,
+    subkey_index: HashMap<String, usize>,
+}
+
+impl Node {
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.subkey_index.get(&fold_name(name)).map(|&index| &self.subkeys[index])
+    }
+
+    /// Returns the position of the child called `name`, adding it if there is none.
+    fn child_or_insert(&mut self, name: &str) -> usize {
+        let len = self.subkeys.len();
+        let index = *self.subkey_index.entry(fold_name(name)).or_insert(len);
+        if index == len {
+            self.subkeys.push(Node { name: name.to_string(), ..Node::default() });
+        }
+        index
+    }
+
+    fn remove_child(&mut self, name: &str) {
+        if let Some(index) = self.subkey_index.remove(&fold_name(name)) {
+            self.subkeys.remove(index);
+            self.subkey_index.values_mut().filter(|position| **position > index).for_each(|position| *position -= 1);
+        }
+    }
+
+    fn value(&self, name: &str) -> Option<&RegValue> {
+        self.value_index.get(&fold_name(name)).map(|&index| &self.values[index].1)
+    }
+
+    fn set(&mut self, name: String, value: Option<RegValue>) {
+        let folded = fold_name(&name);
+        match (self.value_index.get(&folded).copied(), value) {
+            (Some(index), Some(value)) => self.values[index].1 = value,
+            (None, Some(value)) => {
+                self.value_index.insert(folded, self.values.len());
+                self.values.push((name, value));
+            }
+            (Some(index), None) => {
+                self.value_index.remove(&folded);
+                self.values.remove(index);
+                self.value_index.values_mut().filter(|position| **position > index).for_each(|position| *position -= 1);
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// The keys and values left by applying a registry export to an empty registry.
+///
+/// Paths start with the predefined key, such as
+/// `HKEY_LOCAL_MACHINE\SOFTWARE\Classes`; the usual abbreviations such as
+/// `HKLM` are accepted too.
+#[derive(Clone, Debug, Default)]
+pub struct RegFile {
+    root: Node,
+}
+
+impl RegFile {
+    /// Parses an export or Wine registry file held in memory, in any encoding `regedit` writes.
+    ///
+    /// Lines that cannot be parsed are skipped, as `regedit` skips them on import.
+    pub fn parse(data: &[u8]) -> Result<RegFile, IconError> {
+        let text = decode_text(data);
+        let mut lines = logical_lines(&text);
+        let dialect = match lines.next().as_deref().map(str::trim) {
+            Some(REGEDIT4) => Dialect::Regedit4,
+            Some(REGEDIT5) => Dialect::Regedit5,
+            Some(WINE) => Dialect::Wine,
+            _ => return Err(IconError::Malformed { stage: Stage::RegistryFile }),
+        };
+
+        let mut file = RegFile::default();
+        let mut prefix = Vec::new();
+        // Positions of the nodes leading to the current key, so value lines need no lookups.
+        let mut key: Option<Vec<usize>> = None;
+        for line in lines {
+            let line = line.trim();
+            if dialect == Dialect::Wine {
+                if let Some(root) = line.strip_prefix(WINE_RELATIVE_TO) {
+                    prefix = wine_root(&unescape(root));
+                    continue;
+                }
+            }
+            if line.is_empty() || line.starts_with([';', '#']) {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let Some(end) = header.rfind(']') else { continue };
+                let (path, delete) = match header[..end].strip_prefix('-') {
+                    Some(path) => (path, true),
+                    None => (&header[..end], false),
+                };
+                let path = match dialect {
+                    Dialect::Wine => prefix.iter().cloned().chain(canonical_path(&unescape(path))).collect(),
+                    _ => canonical_path(path),
+                };
+                if delete {
+                    file.delete_key(&path);
+                    key = None;
+                } else {
+                    let mut node = &mut file.root;
+                    let mut positions = Vec::with_capacity(path.len());
+                    for name in &path {
+                        let index = node.child_or_insert(name);
+                        positions.push(index);
+                        node = &mut node.subkeys[index];
+                    }
+                    key = Some(positions);
+                }
+            } else if let (Some(positions), Some((name, value))) = (&key, parse_value_line(line, dialect)) {
+                positions.iter().fold(&mut file.root, |node, &index| &mut node.subkeys[index]).set(name, value);
+            }
+        }
+        Ok(file)
+    }
+
+    /// Reads and parses the export at `path`.
+    pub fn read(path: &Path) -> Result<RegFile, IconError> {
+        let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+        RegFile::parse(&data)
+    }
+
+    /// Returns the associations recorded in the export, with the user's
+    /// classes taking precedence over the machine's.
+    pub fn associations(&self) -> Associations<'_> {
+        let mut associations = Associations::new();
+        for root in [r"HKEY_CURRENT_USER\Software\Classes", r"HKEY_LOCAL_MACHINE\SOFTWARE\Classes", "HKEY_CLASSES_ROOT"] {
+            associations.push_classes(self, root);
+        }
+        associations.set_user(self, "HKEY_CURRENT_USER");
+        associations
+    }
+
+    fn node(&self, key: &str) -> Option<&Node> {
+        canonical_path(key).iter().try_fold(&self.root, |node, name| node.child(name))
+    }
+
+    fn delete_key(&mut self, path: &[String]) {
+        let Some((name, parents)) = path.split_last() else { return };
+        let mut node = &mut self.root;
+        for parent in parents {
+            match node.subkey_index.get(&fold_name(parent)) {
+                Some(&index) => node = &mut node.subkeys[index],
+                None => return,
+            }
+        }
+        node.remove_child(name);
+    }
+}
+
+impl Registry for RegFile {
+    fn subkey_names(&self, key: &str) -> Result<Option<Vec<String>>, IconError> {
+        Ok(self.node(key).map(|node| node.subkeys.iter().map(|child| child.name.clone()).collect()))
+    }
+
+    fn value_names(&self, key: &str) -> Result<Option<Vec<String>>, IconError> {
+        Ok(self.node(key).map(|node| node.values.iter().map(|(name, _)| name.clone()).collect()))
+    }
+
+    fn value(&self, key: &str, name: &str) -> Result<Option<RegValue>, IconError> {
+        Ok(self.node(key).and_then(|node| node.value(name)).cloned())
+    }
+}
+
+/// Splits a key path into components, expanding an abbreviated predefined key.
+fn canonical_path(path: &str) -> Vec<String> {
+    path_components(path)
+        .enumerate()
+        .map(|(index, component)| match ROOT_ABBREVIATIONS.iter().find(|(short, _)| index == 0 && short.eq_ignore_ascii_case(component)) {
+            Some((_, full)) => full.to_string(),
+            None => component.to_string(),
+        })
+        .collect()
+}
+
+/// Joins value lines ending in a backslash with the line after them.
+///
+/// Keys and comments may end in a backslash of their own, so only lines
+/// starting a value, with `"` or `@`, continue.
+fn logical_lines(text: &str) -> impl Iterator<Item = String> + '_ {
+    let mut lines = text.lines();
+    std::iter::from_fn(move || {
+        let mut line = lines.next()?.trim_end().to_string();
+        let value = line.trim_start().starts_with(['"', '@']);
+        while value && line.ends_with('\\') {
+            line.pop();
+            match lines.next() {
+                Some(next) => line.push_str(next.trim()),
+                None => break,
+            }
+        }
+        Some(line)
+    })
+}
+
+/// Maps the root named by a Wine file, such as `\Machine`, to the predefined key it stands for.
+fn wine_root(root: &str) -> Vec<String> {
+    let components: Vec<&str> = path_components(root.trim()).collect();
+    let root: &[&str] = match components.as_slice() {
+        [machine] if machine.eq_ignore_ascii_case("Machine") => &["HKEY_LOCAL_MACHINE"],
+        [user, default] if user.eq_ignore_ascii_case("User") && default.eq_ignore_ascii_case(".Default") => &["HKEY_USERS", ".DEFAULT"],
+        [user, ..] if user.eq_ignore_ascii_case("User") => &["HKEY_CURRENT_USER"],
+        _ => &[],
+    };
+    root.iter().map(|component| component.to_string()).collect()
+}
+
+/// Parses `"name"=data` or `@=data`, returning `None` as the value for a deletion.
+fn parse_value_line(line: &str, dialect: Dialect) -> Option<(String, Option<RegValue>)> {
+    let (name, rest) = match line.strip_prefix('@') {
+        Some(rest) => (String::new(), rest),
+        None => parse_quoted(line, dialect)?,
+    };
+    let data = rest.trim_start().strip_prefix('=')?.trim();
+    if data == "-" {
+        return Some((name, None));
+    }
+    Some((name, Some(parse_data(data, dialect)?)))
+}
+
+/// Parses the data of a value: a quoted string, `str(type):`, `dword:`, `hex:` or `hex(type):`.
+fn parse_data(data: &str, dialect: Dialect) -> Option<RegValue> {
+    let quoted = |data| match parse_quoted(data, dialect)? {
+        (text, rest) if rest.trim().is_empty() => Some(text),
+        _ => None,
+    };
+    if data.starts_with('"') {
+        return quoted(data).map(RegValue::String);
+    }
+    if let Some(digits) = data.strip_prefix("dword:") {
+        return u32::from_str_radix(digits.trim(), 16).ok().map(RegValue::Dword);
+    }
+    if let Some((kind, text)) = data.strip_prefix("str(").and_then(|data| data.split_once("):")) {
+        let bytes: Vec<u8> = quoted(text)?.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect();
+        return Some(RegValue::from_bytes(u32::from_str_radix(kind, 16).ok()?, &bytes));
+    }
+
+    let (kind, bytes) = if let Some(bytes) = data.strip_prefix("hex:") {
+        (REG_BINARY, bytes)
+    } else {
+        let (kind, bytes) = data.strip_prefix("hex(")?.split_once("):")?;
+        (u32::from_str_radix(kind, 16).ok()?, bytes)
+    };
+    let bytes = bytes
+        .split(',')
+        .map(str::trim)
+        .filter(|byte| !byte.is_empty())
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    // `REGEDIT4` stores string data in the ANSI code page.
+    let bytes = match kind {
+        REG_SZ | REG_EXPAND_SZ | REG_MULTI_SZ if dialect == Dialect::Regedit4 => decode_ansi(&bytes).encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        _ => bytes,
+    };
+    Some(RegValue::from_bytes(kind, &bytes))
+}
+
+/// Parses a quoted string, returning it and the text after it.
+///
+/// `regedit` escapes only `\\` and `\"`; Wine also uses C escapes.
+fn parse_quoted(text: &str, dialect: Dialect) -> Option<(String, &str)> {
+    let inner = text.strip_prefix('"')?;
+    let mut escaped = false;
+    let end = inner.char_indices().find_map(|(index, c)| {
+        let close = c == '"' && !escaped;
+        escaped = c == '\\' && !escaped;
+        close.then_some(index)
+    })?;
+    let string = match dialect {
+        Dialect::Wine => unescape(&inner[..end]),
+        _ => {
+            let mut string = String::new();
+            let mut chars = inner[..end].chars();
+            while let Some(c) = chars.next() {
+                string.push(if c == '\\' { chars.next().unwrap_or('\\') } else { c });
+            }
+            string
+        }
+    };
+    Some((string, &inner[end + 1..]))
+}
+
+/// Resolves the C escapes Wine writes: control characters, `\x` with up to
+/// four hex digits, and octal with up to three digits.
+fn unescape(text: &str) -> String {
+    let mut string = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            string.push('\\');
+            break;
+        };
+        let (radix, max_digits, first) = match escape {
+            'x' => (16, 4, None),
+            '0'..='7' => (8, 3, Some(escape)),
+            _ => {
+                string.push(match escape {
+                    'a' => '\x07',
+                    'b' => '\x08',
+                    'e' => '\x1b',
+                    'f' => '\x0c',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'v' => '\x0b',
+                    escape => escape,
+                });
+                continue;
+            }
+        };
+        let mut digits: String = first.into_iter().collect();
+        while digits.len() < max_digits {
+            match chars.peek() {
+                Some(&digit) if digit.is_digit(radix) => {
+                    digits.push(digit);
+                    chars.next();
+                }
+                _ => break,
+            }
+        }
+        match u32::from_str_radix(&digits, radix).ok().and_then(char::from_u32) {
+            Some(c) => string.push(c),
+            None => string.push(escape),
+        }
+    }
+    string
+}
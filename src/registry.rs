@@ -0,0 +1,125 @@
+//! Read access to registry data, whatever it was loaded from.
+//!
+//! Keys are addressed by backslash-separated paths relative to the root of
+//! the data, such as `Software\Classes\.txt`, and compare case-insensitively.
+//! The empty string names a key's default value.
+
+use crate::error::IconError;
+
+pub const REG_SZ: u32 = 1;
+pub const REG_EXPAND_SZ: u32 = 2;
+pub const REG_BINARY: u32 = 3;
+pub const REG_DWORD: u32 = 4;
+pub const REG_DWORD_BIG_ENDIAN: u32 = 5;
+pub const REG_MULTI_SZ: u32 = 7;
+pub const REG_QWORD: u32 = 11;
+
+/// A registry value, decoded according to its type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegValue {
+    String(String),
+    /// A string holding unexpanded `%VAR%` references.
+    ExpandString(String),
+    Binary(Vec<u8>),
+    Dword(u32),
+    Qword(u64),
+    MultiString(Vec<String>),
+    /// A value of any other type, or one whose data does not fit its type.
+    Other { kind: u32, data: Vec<u8> },
+}
+
+impl RegValue {
+    /// Decodes the raw data of a value of type `kind`, with strings in UTF-16LE.
+    pub fn from_bytes(kind: u32, data: &[u8]) -> RegValue {
+        let units = || data.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect::<Vec<u16>>();
+        let string = || {
+            let units = units();
+            let end = units.iter().position(|&unit| unit == 0).unwrap_or(units.len());
+            String::from_utf16_lossy(&units[..end])
+        };
+
+        match (kind, data.len()) {
+            (REG_SZ, _) => RegValue::String(string()),
+            (REG_EXPAND_SZ, _) => RegValue::ExpandString(string()),
+            (REG_BINARY, _) => RegValue::Binary(data.to_vec()),
+            (REG_DWORD, 4) => RegValue::Dword(u32::from_le_bytes(data.try_into().unwrap())),
+            (REG_DWORD_BIG_ENDIAN, 4) => RegValue::Dword(u32::from_be_bytes(data.try_into().unwrap())),
+            (REG_QWORD, 8) => RegValue::Qword(u64::from_le_bytes(data.try_into().unwrap())),
+            (REG_MULTI_SZ, _) => RegValue::MultiString(
+                String::from_utf16_lossy(&units())
+                    .split('\0')
+                    .take_while(|string| !string.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            _ => RegValue::Other { kind, data: data.to_vec() },
+        }
+    }
+
+    /// Returns the text of a string or expandable string value.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            RegValue::String(text) | RegValue::ExpandString(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// Read access to a tree of registry keys.
+pub trait Registry {
+    /// Returns the names of the subkeys of `key`, or `None` if there is no such key.
+    fn subkey_names(&self, key: &str) -> Result<Option<Vec<String>>, IconError>;
+
+    /// Returns the names of the values of `key`, or `None` if there is no such key.
+    fn value_names(&self, key: &str) -> Result<Option<Vec<String>>, IconError>;
+
+    /// Returns a value of `key`, or `None` if there is no such key or value.
+    fn value(&self, key: &str, name: &str) -> Result<Option<RegValue>, IconError>;
+
+    /// Returns true if `key` exists.
+    fn key_exists(&self, key: &str) -> Result<bool, IconError> {
+        Ok(self.value_names(key)?.is_some())
+    }
+
+    /// Returns a string value of `key`, ignoring values of other types.
+    fn string(&self, key: &str, name: &str) -> Result<Option<String>, IconError> {
+        Ok(self.value(key, name)?.and_then(|value| value.as_str().map(str::to_string)))
+    }
+}
+
+impl<R: Registry + ?Sized> Registry for &R {
+    fn subkey_names(&self, key: &str) -> Result<Option<Vec<String>>, IconError> {
+        (**self).subkey_names(key)
+    }
+
+    fn value_names(&self, key: &str) -> Result<Option<Vec<String>>, IconError> {
+        (**self).value_names(key)
+    }
+
+    fn value(&self, key: &str, name: &str) -> Result<Option<RegValue>, IconError> {
+        (**self).value(key, name)
+    }
+}
+
+/// Splits a key path into its components, ignoring empty ones.
+pub(crate) fn path_components(key: &str) -> impl Iterator<Item = &str> {
+    key.split('\\').filter(|component| !component.is_empty())
+}
+
+/// Compares key or value names as the registry does, ignoring case.
+pub(crate) fn names_match(a: &str, b: &str) -> bool {
+    if a.is_ascii() && b.is_ascii() {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        fold_name(a) == fold_name(b)
+    }
+}
+
+/// Returns the form of a name that [`names_match`] compares, for use as a lookup key.
+pub(crate) fn fold_name(name: &str) -> String {
+    if name.is_ascii() {
+        name.to_ascii_uppercase()
+    } else {
+        name.to_uppercase()
+    }
+}
@@ -0,0 +1,194 @@
+//! Icon resources embedded in executables and libraries.
+//!
+//! Executables store each icon as a `RT_GROUP_ICON` resource listing its
+//! images, with every image in its own `RT_ICON` resource. [`IconResources`]
+//! reassembles those into ordinary icon files, whatever the executable format.
+
+use crate::bytes::{slice_at, u16_at, u32_at};
+use crate::error::{IconError, Stage};
+use crate::ico::{self, EncodedEntry, IcoEntry, ICON_TYPE};
+use crate::ne::NeFile;
+use crate::pe::PeFile;
+use crate::size::IconSize;
+use image::RgbaImage;
+use std::fmt;
+use std::path::Path;
+
+/// `RT_ICON`: a single icon image.
+pub const RT_ICON: u16 = 3;
+/// `RT_GROUP_ICON`: the directory of an icon's images.
+pub const RT_GROUP_ICON: u16 = 14;
+
+/// The reserved word and resource type that open an icon file.
+pub(crate) const ICON_FILE_SIGNATURE: [u8; 4] = [0, 0, 1, 0];
+
+const GROUP_HEADER_SIZE: usize = 6;
+const GROUP_ENTRY_SIZE: usize = 14;
+
+/// Identifies a resource by number or by name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceId {
+    Id(u16),
+    Name(String),
+}
+
+impl ResourceId {
+    /// Compares as `FindResource` does, ignoring the case of names.
+    pub fn matches(&self, other: &ResourceId) -> bool {
+        match (self, other) {
+            (ResourceId::Id(a), ResourceId::Id(b)) => a == b,
+            (ResourceId::Name(a), ResourceId::Name(b)) => a.eq_ignore_ascii_case(b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceId::Id(id) => write!(f, "#{id}"),
+            ResourceId::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Read access to the resources of an executable image.
+pub trait IconResources {
+    /// Returns the names of every resource of `resource_type`, in directory order.
+    fn resource_names(&self, resource_type: u16) -> Result<Vec<ResourceId>, IconError>;
+
+    /// Returns the data of a resource, or `None` if there is no such resource.
+    fn resource(&self, resource_type: u16, name: &ResourceId) -> Result<Option<&[u8]>, IconError>;
+
+    /// Returns the names of every icon group, in the order `ExtractIconEx` counts them.
+    fn icon_groups(&self) -> Result<Vec<ResourceId>, IconError> {
+        self.resource_names(RT_GROUP_ICON)
+    }
+
+    /// Reassembles an icon group into the bytes of an icon file.
+    fn icon_group(&self, name: &ResourceId) -> Result<Vec<u8>, IconError> {
+        let group = self.resource(RT_GROUP_ICON, name)?.ok_or_else(|| IconError::MissingResource {
+            resource_type: RT_GROUP_ICON,
+            name: name.clone(),
+        })?;
+        assemble_group(group, |id| {
+            self.resource(RT_ICON, &ResourceId::Id(id))?
+                .ok_or(IconError::MissingResource { resource_type: RT_ICON, name: ResourceId::Id(id) })
+        })
+    }
+
+    /// Decodes every image of an icon group.
+    fn icon_group_images(&self, name: &ResourceId) -> Result<Vec<IcoEntry>, IconError> {
+        ico::parse(&self.icon_group(name)?)
+    }
+
+    /// Resolves an icon index as `ExtractIconEx` does.
+    ///
+    /// A non-negative index counts icon groups in directory order; a negative
+    /// one names the group whose integer ID is its magnitude.
+    fn icon_group_for_index(&self, index: i32) -> Result<ResourceId, IconError> {
+        if index < 0 {
+            let id = u16::try_from(index.unsigned_abs()).map_err(|_| IconError::NoIconAtIndex(index))?;
+            let id = ResourceId::Id(id);
+            return match self.resource(RT_GROUP_ICON, &id)? {
+                Some(_) => Ok(id),
+                None => Err(IconError::NoIconAtIndex(index)),
+            };
+        }
+
+        self.icon_groups()?.into_iter().nth(index as usize).ok_or(IconError::NoIconAtIndex(index))
+    }
+
+    /// Reassembles the icon at an `ExtractIconEx` index into the bytes of an icon file.
+    fn icon_at_index(&self, index: i32) -> Result<Vec<u8>, IconError> {
+        self.icon_group(&self.icon_group_for_index(index)?)
+    }
+}
+
+/// Builds an icon file from a `GRPICONDIR`, looking up each image by its `RT_ICON` ID.
+pub(crate) fn assemble_group<'a>(
+    group: &[u8],
+    icon: impl Fn(u16) -> Result<&'a [u8], IconError>,
+) -> Result<Vec<u8>, IconError> {
+    let truncated = || IconError::Truncated { stage: Stage::IconDirectory };
+
+    if u16_at(group, 0).ok_or_else(truncated)? != 0 || u16_at(group, 2).ok_or_else(truncated)? != ICON_TYPE {
+        return Err(IconError::Malformed { stage: Stage::IconDirectory });
+    }
+
+    let count = usize::from(u16_at(group, 4).ok_or_else(truncated)?);
+    let entries = (0..count)
+        .map(|index| {
+            let entry = slice_at(group, GROUP_HEADER_SIZE + index * GROUP_ENTRY_SIZE, GROUP_ENTRY_SIZE).ok_or_else(truncated)?;
+            let data = icon(u16_at(entry, 12).unwrap())?;
+            let declared_len = u32_at(entry, 8).unwrap() as usize;
+
+            Ok(EncodedEntry {
+                width: ico::declared_dimension(entry[0]),
+                height: ico::declared_dimension(entry[1]),
+                color_count: entry[2],
+                planes: u16_at(entry, 4).unwrap(),
+                bit_count: u16_at(entry, 6).unwrap(),
+                // Resource data may be padded beyond the size the group declares.
+                data: data[..declared_len.min(data.len())].to_vec(),
+            })
+        })
+        .collect::<Result<Vec<_>, IconError>>()?;
+
+    ico::write_directory(ICON_TYPE, &entries)
+}
+
+/// A PE or NE executable.
+pub enum Executable<'a> {
+    Pe(PeFile<'a>),
+    Ne(NeFile<'a>),
+}
+
+impl<'a> Executable<'a> {
+    /// Parses either format, telling them apart by the signature the DOS header points to.
+    pub fn parse(data: &'a [u8]) -> Result<Executable<'a>, IconError> {
+        let header = u32_at(data, 0x3c).ok_or(IconError::Truncated { stage: Stage::ExecutableHeader })? as usize;
+        match slice_at(data, header, 2) {
+            Some(b"NE") => Ok(Executable::Ne(NeFile::parse(data)?)),
+            _ => Ok(Executable::Pe(PeFile::parse(data)?)),
+        }
+    }
+
+    /// Returns the data of a resource, borrowed from the file rather than from `self`.
+    pub(crate) fn find(&self, resource_type: u16, name: &ResourceId) -> Result<Option<&'a [u8]>, IconError> {
+        match self {
+            Executable::Pe(file) => file.find(resource_type, name),
+            Executable::Ne(file) => file.find(resource_type, name),
+        }
+    }
+}
+
+impl IconResources for Executable<'_> {
+    fn resource_names(&self, resource_type: u16) -> Result<Vec<ResourceId>, IconError> {
+        match self {
+            Executable::Pe(file) => file.resource_names(resource_type),
+            Executable::Ne(file) => file.resource_names(resource_type),
+        }
+    }
+
+    fn resource(&self, resource_type: u16, name: &ResourceId) -> Result<Option<&[u8]>, IconError> {
+        self.find(resource_type, name)
+    }
+}
+
+/// Loads the icon at an `ExtractIconEx` index from the executable at `path`,
+/// as [`ico::load_icon_as_image`] does for icon files.
+///
+/// `path` may also name an icon file, which has only index 0.
+pub fn load_icon_as_image(path: &Path, index: i32, size: IconSize) -> Result<RgbaImage, IconError> {
+    let data = std::fs::read(path).map_err(|source| IconError::Io { path: path.to_path_buf(), source })?;
+    let entries = if data.starts_with(&ICON_FILE_SIGNATURE) {
+        if index != 0 {
+            return Err(IconError::NoIconAtIndex(index));
+        }
+        ico::parse(&data)?
+    } else {
+        ico::parse(&Executable::parse(&data)?.icon_at_index(index)?)?
+    };
+    ico::image_for_size(&entries, size).ok_or_else(|| IconError::NoIcon { path: path.to_path_buf() })
+}
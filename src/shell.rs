@@ -0,0 +1,354 @@
+use crate::alpha::{detect_alpha_mode, AlphaMode};
+use crate::error::{IconError, Stage};
+use crate::geometry::BitmapGeometry;
+use crate::mask::{apply_and_mask, decode_monochrome, mask_stride};
+use crate::metadata::IconMetadata;
+use crate::provider::{normalize_extension, IconProvider};
+use crate::size::{IconSize, DEFAULT_DPI};
+use crate::swizzle::bgra_to_rgba;
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, RgbaImage};
+use windows::core::{w, Error, PCWSTR};
+use windows::Win32::{
+    Foundation::ERROR_SUCCESS,
+    Graphics::Gdi::{CreateCompatibleDC, DeleteDC, DeleteObject, GetDIBits, GetDeviceCaps, GetObjectW, BITMAP, BITMAPINFO, BITMAPINFOHEADER, DIB_RGB_COLORS, HBITMAP, HDC, LOGPIXELSX, RGBQUAD},
+    Storage::FileSystem::{FILE_ATTRIBUTE_NORMAL, FILE_FLAGS_AND_ATTRIBUTES},
+    System::Registry::{RegGetValueW, HKEY_CLASSES_ROOT, RRF_RT_REG_SZ},
+    UI::{
+        Controls::{IImageList, ILD_TRANSPARENT},
+        Shell::{SHGetFileInfoW, SHGetImageList, SHFILEINFOW, SHGFI_FLAGS, SHGFI_SYSICONINDEX, SHGFI_USEFILEATTRIBUTES},
+        WindowsAndMessaging::{DestroyIcon, GetIconInfoExW, HICON, ICONINFOEXW},
+    },
+};
+
+use std::mem::ManuallyDrop;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+
+/// Fetches an icon as an image from a given file path at the requested size.
+///
+/// [`IconSize::Pixels`] sizes are resampled from the nearest native image list.
+pub fn fetch_icon_as_image(
+    path: &Path, 
+    size: IconSize
+) -> Result<RgbaImage, IconError> {
+    fetch_shell_icon(path, FILE_FLAGS_AND_ATTRIBUTES(0), SHGFI_FLAGS(0), size)
+}
+
+/// Looks up `path` in the shell with extra `SHGFI_*` flags and file attributes and fetches its icon.
+fn fetch_shell_icon(
+    path: &Path,
+    attributes: FILE_FLAGS_AND_ATTRIBUTES,
+    flags: SHGFI_FLAGS,
+    size: IconSize,
+) -> Result<RgbaImage, IconError> {
+    if size == IconSize::Pixels(0) {
+        return Err(IconError::InvalidDimensions { width: 0, height: 0 });
+    }
+
+    unsafe {
+        let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut file_info = SHFILEINFOW::default();
+
+        if SHGetFileInfoW(
+            PCWSTR(wide_path.as_ptr()),
+            attributes,
+            Some(&mut file_info),
+            std::mem::size_of::<SHFILEINFOW>() as u32,
+            SHGFI_SYSICONINDEX | flags,
+        ) == 0 || file_info.iIcon == 0
+        {
+            return Err(IconError::NoIcon { path: path.to_path_buf() });
+        }
+
+        let image_list: IImageList = SHGetImageList(size.shell_image_list(system_dpi()))
+            .map_err(|source| IconError::windows(Stage::ImageList, Some(path), source))?;
+        let icon = image_list
+            .GetIcon(file_info.iIcon, ILD_TRANSPARENT.0)
+            .map_err(|source| IconError::windows(Stage::GetIcon, Some(path), source))?;
+        // Destroy the icon before reporting a conversion error so it is never leaked.
+        let converted = hicon_to_image(&icon);
+        let destroyed = DestroyIcon(icon);
+        let mut image = converted.map_err(|error| error.with_path(path))?;
+        destroyed.map_err(|source| IconError::windows(Stage::Cleanup, Some(path), source))?;
+
+        if let IconSize::Pixels(pixels) = size {
+            if image.dimensions() != (pixels, pixels) {
+                image = imageops::resize(&image, pixels, pixels, FilterType::Lanczos3);
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+/// [`IconProvider`] backed by the live Windows shell.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShellIconProvider;
+
+impl IconProvider for ShellIconProvider {
+    fn icon_for_path(&self, path: &Path, size: IconSize) -> Result<RgbaImage, IconError> {
+        fetch_icon_as_image(path, size)
+    }
+
+    /// Asks the shell for the icon of a file with this extension without the file needing to exist.
+    fn icon_for_extension(&self, extension: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        let name = format!("file.{}", normalize_extension(extension));
+        fetch_shell_icon(Path::new(&name), FILE_ATTRIBUTE_NORMAL, SHGFI_USEFILEATTRIBUTES, size).map_err(|error| match error {
+            IconError::NoIcon { .. } => IconError::NoIconForExtension(extension.to_string()),
+            error => error,
+        })
+    }
+
+    /// Maps `mime` to an extension through `HKEY_CLASSES_ROOT\MIME\Database\Content Type`.
+    fn icon_for_mime(&self, mime: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        let extension = mime_extension(mime).ok_or_else(|| IconError::NoIconForMime(mime.to_string()))?;
+        self.icon_for_extension(&extension, size)
+    }
+}
+
+/// Returns the extension registered for a MIME type, including its leading dot.
+fn mime_extension(mime: &str) -> Option<String> {
+    let subkey: Vec<u16> = format!("MIME\\Database\\Content Type\\{mime}").encode_utf16().chain(Some(0)).collect();
+    let mut buffer = [0u16; 260];
+    let mut len = std::mem::size_of_val(&buffer) as u32;
+
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CLASSES_ROOT,
+            PCWSTR(subkey.as_ptr()),
+            w!("Extension"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buffer.as_mut_ptr() as *mut _),
+            Some(&mut len),
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+
+    let chars = (len as usize / 2).min(buffer.len());
+    let extension = String::from_utf16_lossy(&buffer[..chars]);
+    let extension = extension.trim_end_matches('\0');
+    (!extension.is_empty()).then(|| extension.to_string())
+}
+
+/// Returns the DPI of the screen, which the shell's image lists are scaled to.
+fn system_dpi() -> u32 {
+    unsafe {
+        let screen_dc = CreateCompatibleDC(None);
+        let dpi = GetDeviceCaps(screen_dc, LOGPIXELSX);
+        let _ = DeleteDC(screen_dc);
+        if dpi > 0 { dpi as u32 } else { DEFAULT_DPI }
+    }
+}
+
+/// Converts a handle to an icon (HICON) into an image buffer (RgbaImage) with straight alpha.
+pub fn hicon_to_image(hicon: &HICON) -> Result<RgbaImage, IconError> {
+    hicon_to_image_with_metadata(hicon).map(|(image, _)| image)
+}
+
+/// Converts a handle to an icon (HICON) into an image buffer (RgbaImage) with straight alpha,
+/// along with the icon's real dimensions, hotspot and source format.
+pub fn hicon_to_image_with_metadata(hicon: &HICON) -> Result<(RgbaImage, IconMetadata), IconError> {
+    unsafe {
+        let mut icon_info = ICONINFOEXW {
+            cbSize: std::mem::size_of::<ICONINFOEXW>() as u32,
+            ..Default::default()
+        };
+
+        if !GetIconInfoExW(*hicon, &mut icon_info).as_bool() {
+            return Err(IconError::windows(Stage::IconInfo, None, Error::from_win32()));
+        }
+        // The caller owns both bitmaps; the guards delete them on every early return.
+        let color_bitmap = OwnedBitmap(icon_info.hbmColor);
+        let mask_bitmap = OwnedBitmap(icon_info.hbmMask);
+
+        // Monochrome icons have no colour bitmap; the mask holds both halves.
+        let monochrome = icon_info.hbmColor.is_invalid();
+        let source = if monochrome { icon_info.hbmMask } else { icon_info.hbmColor };
+
+        let mut bitmap = BITMAP::default();
+        if GetObjectW(source, std::mem::size_of::<BITMAP>() as i32, Some(&mut bitmap as *mut BITMAP as *mut _)) == 0 {
+            return Err(IconError::windows(Stage::IconInfo, None, Error::from_win32()));
+        }
+
+        let (width, source_rows) = BitmapGeometry {
+            width: bitmap.bmWidth,
+            height: bitmap.bmHeight,
+            bits_per_pixel: bitmap.bmBitsPixel,
+            stride: bitmap.bmWidthBytes as usize,
+        }
+        .validate()?;
+        let height = if monochrome { source_rows / 2 } else { source_rows };
+
+        let mut metadata = IconMetadata {
+            width,
+            height,
+            hotspot: (icon_info.xHotspot, icon_info.yHotspot),
+            is_cursor: !icon_info.fIcon.as_bool(),
+            bit_depth: bitmap.bmBitsPixel,
+            alpha_mode: AlphaMode::Straight,
+            alpha_from_mask: false,
+            inverted: Vec::new(),
+        };
+
+        let screen_dc = OwnedDc(CreateCompatibleDC(None));
+        let mem_dc = OwnedDc(CreateCompatibleDC(screen_dc.0));
+
+        if monochrome {
+            let mask_data = read_mask_bits(mem_dc.0, icon_info.hbmMask, width, height * 2)?;
+
+            mem_dc.release()?;
+            screen_dc.release()?;
+            mask_bitmap.release()?;
+
+            let (image, outcome) = decode_monochrome(&mask_data, width, height, true)?;
+            metadata.alpha_from_mask = outcome.alpha_from_mask;
+            metadata.inverted = outcome.inverted;
+            return Ok((image, metadata));
+        }
+
+        let geometry = BitmapGeometry::top_down(width, height, 32);
+        let mut bmp_info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: geometry.width,
+                biHeight: geometry.height,
+                biPlanes: 1,
+                biBitCount: geometry.bits_per_pixel,
+                biCompression: DIB_RGB_COLORS.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut pixel_data = vec![0; geometry.data_len().unwrap_or_default()];
+        geometry.validate_data(pixel_data.len())?;
+
+        // Neither bitmap is selected into the DC, as GetDIBits requires.
+        if GetDIBits(
+            mem_dc.0,
+            icon_info.hbmColor,
+            0,
+            height,
+            Some(pixel_data.as_mut_ptr() as *mut _),
+            &mut bmp_info,
+            DIB_RGB_COLORS,
+        ) == 0 {
+            return Err(IconError::windows(Stage::ColorBits, None, Error::from_win32()));
+        }
+
+        let mask_data = read_mask_bits(mem_dc.0, icon_info.hbmMask, width, height)?;
+
+        mem_dc.release()?;
+        screen_dc.release()?;
+        color_bitmap.release()?;
+        mask_bitmap.release()?;
+
+        if bmp_info.bmiHeader.biBitCount != 32 {
+            return Err(IconError::UnsupportedBitDepth(bmp_info.bmiHeader.biBitCount));
+        }
+
+        bgra_to_rgba(&mut pixel_data);
+
+        // Icon colour bitmaps hold straight alpha; the guess is only reported.
+        metadata.alpha_mode = detect_alpha_mode(&pixel_data);
+
+        let actual = pixel_data.len();
+        let mut image = ImageBuffer::from_raw(width, height, pixel_data)
+            .ok_or(IconError::BufferSize { expected: width as usize * height as usize * 4, actual })?;
+
+        let outcome = apply_and_mask(&mut image, &mask_data, true)?;
+        metadata.alpha_from_mask = outcome.alpha_from_mask;
+        metadata.inverted = outcome.inverted;
+
+        Ok((image, metadata))
+    }
+}
+
+/// A BITMAPINFO with room for the two-entry colour table of a 1-bpp bitmap.
+#[repr(C)]
+struct MonochromeBitmapInfo {
+    header: BITMAPINFOHEADER,
+    colors: [RGBQUAD; 2],
+}
+
+/// Reads a 1-bpp mask bitmap as top-down rows.
+unsafe fn read_mask_bits(dc: HDC, mask: HBITMAP, width: u32, height: u32) -> Result<Vec<u8>, IconError> {
+    let mut bmp_info = MonochromeBitmapInfo {
+        header: BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width as i32,
+            biHeight: -(height as i32),
+            biPlanes: 1,
+            biBitCount: 1,
+            biCompression: DIB_RGB_COLORS.0,
+            ..Default::default()
+        },
+        colors: Default::default(),
+    };
+
+    let mut mask_data = vec![0; mask_stride(width) * height as usize];
+
+    if GetDIBits(
+        dc,
+        mask,
+        0,
+        height,
+        Some(mask_data.as_mut_ptr() as *mut _),
+        &mut bmp_info as *mut MonochromeBitmapInfo as *mut BITMAPINFO,
+        DIB_RGB_COLORS,
+    ) == 0 {
+        return Err(IconError::windows(Stage::MaskBits, None, Error::from_win32()));
+    }
+
+    Ok(mask_data)
+}
+
+/// A GDI bitmap that is deleted when dropped.
+struct OwnedBitmap(HBITMAP);
+
+impl OwnedBitmap {
+    /// Deletes the bitmap now, reporting failure.
+    fn release(self) -> Result<(), IconError> {
+        let bitmap = ManuallyDrop::new(self);
+        unsafe { DeleteObject(bitmap.0) }.ok().map_err(cleanup_error)
+    }
+}
+
+impl Drop for OwnedBitmap {
+    fn drop(&mut self) {
+        if !self.0.is_invalid() {
+            unsafe {
+                let _ = DeleteObject(self.0);
+            }
+        }
+    }
+}
+
+/// A memory device context that is deleted when dropped.
+struct OwnedDc(HDC);
+
+impl OwnedDc {
+    /// Deletes the device context now, reporting failure.
+    fn release(self) -> Result<(), IconError> {
+        let dc = ManuallyDrop::new(self);
+        unsafe { DeleteDC(dc.0) }.ok().map_err(cleanup_error)
+    }
+}
+
+impl Drop for OwnedDc {
+    fn drop(&mut self) {
+        if !self.0.is_invalid() {
+            unsafe {
+                let _ = DeleteDC(self.0);
+            }
+        }
+    }
+}
+
+fn cleanup_error(source: Error) -> IconError {
+    IconError::windows(Stage::Cleanup, None, source)
+}
@@ -0,0 +1,89 @@
+//! Icon sizes and their mapping to the shell's system image lists.
+
+/// The DPI at which the shell's nominal icon sizes are defined.
+pub const DEFAULT_DPI: u32 = 96;
+
+/// `SHIL_LARGE`: the large icon list, 32px at 96 DPI.
+pub const SHIL_LARGE: i32 = 0;
+/// `SHIL_SMALL`: the small icon list, 16px at 96 DPI.
+pub const SHIL_SMALL: i32 = 1;
+/// `SHIL_EXTRALARGE`: the extra large icon list, 48px at 96 DPI.
+pub const SHIL_EXTRALARGE: i32 = 2;
+/// `SHIL_SYSSMALL`: the list sized by `SM_CXSMICON`, 16px at 96 DPI.
+pub const SHIL_SYSSMALL: i32 = 3;
+/// `SHIL_JUMBO`: the jumbo icon list, always 256px.
+pub const SHIL_JUMBO: i32 = 4;
+
+/// A requested icon size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IconSize {
+    /// The small icon list.
+    Small,
+    /// The system small icon list.
+    SysSmall,
+    /// The large icon list.
+    Large,
+    /// The extra large icon list.
+    ExtraLarge,
+    /// The jumbo icon list.
+    Jumbo,
+    /// An arbitrary square size, resampled from the nearest native list.
+    Pixels(u32),
+}
+
+impl IconSize {
+    /// Native lists in increasing size order.
+    const NATIVE: [IconSize; 4] = [IconSize::Small, IconSize::Large, IconSize::ExtraLarge, IconSize::Jumbo];
+
+    /// Returns the `SHIL_*` constant of the image list this size is served from at `dpi`.
+    pub fn shell_image_list(self, dpi: u32) -> i32 {
+        match self.native(dpi) {
+            IconSize::Small => SHIL_SMALL,
+            IconSize::SysSmall => SHIL_SYSSMALL,
+            IconSize::Large => SHIL_LARGE,
+            IconSize::ExtraLarge => SHIL_EXTRALARGE,
+            _ => SHIL_JUMBO,
+        }
+    }
+
+    /// Returns the nominal edge length in pixels at `dpi`.
+    ///
+    /// The jumbo list does not scale with DPI.
+    pub fn pixels(self, dpi: u32) -> u32 {
+        let scale = |base: u32| (u64::from(base) * u64::from(dpi) / u64::from(DEFAULT_DPI)) as u32;
+        match self {
+            IconSize::Small | IconSize::SysSmall => scale(16),
+            IconSize::Large => scale(32),
+            IconSize::ExtraLarge => scale(48),
+            IconSize::Jumbo => 256,
+            IconSize::Pixels(pixels) => pixels,
+        }
+    }
+
+    /// Returns the native list to fetch from at `dpi`.
+    ///
+    /// For [`IconSize::Pixels`] this is the smallest list at least as large as
+    /// the request, so images are only ever scaled down, or the jumbo list if
+    /// none is.
+    pub fn native(self, dpi: u32) -> IconSize {
+        match self {
+            IconSize::Pixels(pixels) => Self::NATIVE
+                .into_iter()
+                .find(|size| size.pixels(dpi) >= pixels)
+                .unwrap_or(IconSize::Jumbo),
+            size => size,
+        }
+    }
+
+    /// Returns the size for a raw `SHIL_*` constant.
+    pub fn from_shell_image_list(list: i32) -> Option<IconSize> {
+        match list {
+            SHIL_LARGE => Some(IconSize::Large),
+            SHIL_SMALL => Some(IconSize::Small),
+            SHIL_EXTRALARGE => Some(IconSize::ExtraLarge),
+            SHIL_SYSSMALL => Some(IconSize::SysSmall),
+            SHIL_JUMBO => Some(IconSize::Jumbo),
+            _ => None,
+        }
+    }
+}
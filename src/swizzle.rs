@@ -0,0 +1,317 @@
+//! Channel swizzling for packed pixel buffers.
+//!
+//! On x86 the fastest implementation available on the running CPU is picked
+//! at call time. aarch64 always uses NEON and wasm32 uses `simd128` when the
+//! module is compiled with that feature; every other target falls back to a
+//! scalar loop. Trailing bytes that do not form a whole pixel are left
+//! untouched.
+
+/// Marks a destination byte that is filled with `0xFF` by [`expand`].
+pub(crate) const OPAQUE: u8 = 0xFF;
+
+const BGRA_TO_RGBA: [u8; 4] = [2, 1, 0, 3];
+
+/// Converts pixel data from BGRA format to RGBA format in place.
+pub fn bgra_to_rgba(data: &mut [u8]) {
+    permute(data, BGRA_TO_RGBA);
+}
+
+/// Scalar reference implementation of [`bgra_to_rgba`].
+#[cfg(any(test, feature = "internal-testing"))]
+pub fn bgra_to_rgba_scalar(data: &mut [u8]) {
+    permute_scalar(data, BGRA_TO_RGBA);
+}
+
+/// One implementation of the swizzles and the [alpha](crate::alpha)
+/// conversions, for checking the vector paths against the scalar one.
+///
+/// Only built for the crate's own tests; not part of the public API.
+#[cfg(any(test, feature = "internal-testing"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    Ssse3,
+    /// AVX2 permutes; expansion and alpha use the SSSE3 and SSE2 paths, which
+    /// every AVX2 CPU has.
+    Avx2,
+    Neon,
+    Simd128,
+}
+
+#[cfg(any(test, feature = "internal-testing"))]
+impl Backend {
+    /// Returns the backends the running CPU supports, slowest first.
+    pub fn available() -> Vec<Backend> {
+        let mut backends = vec![Backend::Scalar];
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("ssse3") {
+                backends.push(Backend::Ssse3);
+            }
+            if is_x86_feature_detected!("avx2") {
+                backends.push(Backend::Avx2);
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        backends.push(Backend::Neon);
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        backends.push(Backend::Simd128);
+        backends
+    }
+
+    /// Converts BGRA pixels to RGBA in place, as [`bgra_to_rgba`] does.
+    ///
+    /// Panics if the backend is not [available](Backend::available).
+    pub fn bgra_to_rgba(self, data: &mut [u8]) {
+        self.permute(data, BGRA_TO_RGBA);
+    }
+
+    /// Widens BGR pixels from `src` into opaque RGBA pixels in `dst`.
+    ///
+    /// Panics if the backend is not [available](Backend::available).
+    pub fn bgr_to_rgba(self, src: &[u8], dst: &mut [u8]) {
+        self.expand(src, dst, [2, 1, 0, OPAQUE]);
+    }
+
+    fn permute(self, data: &mut [u8], order: [u8; 4]) {
+        assert!(Backend::available().contains(&self), "{self:?} is not available");
+        match self {
+            Backend::Scalar => permute_scalar(data, order),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Ssse3 => unsafe { x86::permute_ssse3(data, order) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Avx2 => unsafe { x86::permute_avx2(data, order) },
+            #[cfg(target_arch = "aarch64")]
+            Backend::Neon => neon::permute(data, order),
+            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+            Backend::Simd128 => wasm::permute(data, order),
+            _ => unreachable!(),
+        }
+    }
+
+    fn expand(self, src: &[u8], dst: &mut [u8], order: [u8; 4]) {
+        assert!(Backend::available().contains(&self), "{self:?} is not available");
+        match self {
+            Backend::Scalar => expand_scalar(src, dst, order),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Ssse3 | Backend::Avx2 => unsafe { x86::expand_ssse3(src, dst, order) },
+            #[cfg(target_arch = "aarch64")]
+            Backend::Neon => neon::expand(src, dst, order),
+            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+            Backend::Simd128 => wasm::expand(src, dst, order),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Reorders the bytes of every 4-byte pixel so that `out[i] = in[order[i]]`.
+pub(crate) fn permute(data: &mut [u8], order: [u8; 4]) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { x86::permute_avx2(data, order) };
+        }
+        if is_x86_feature_detected!("ssse3") {
+            return unsafe { x86::permute_ssse3(data, order) };
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    neon::permute(data, order);
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    wasm::permute(data, order);
+
+    #[cfg(not(any(target_arch = "aarch64", all(target_arch = "wasm32", target_feature = "simd128"))))]
+    permute_scalar(data, order);
+}
+
+/// Widens 3-byte pixels from `src` into 4-byte pixels in `dst`.
+///
+/// Each `order` entry names the source byte for that destination byte, or
+/// [`OPAQUE`] to write `0xFF`. Conversion stops at whichever buffer runs out
+/// of whole pixels first.
+pub(crate) fn expand(src: &[u8], dst: &mut [u8], order: [u8; 4]) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("ssse3") {
+        return unsafe { x86::expand_ssse3(src, dst, order) };
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    neon::expand(src, dst, order);
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    wasm::expand(src, dst, order);
+
+    #[cfg(not(any(target_arch = "aarch64", all(target_arch = "wasm32", target_feature = "simd128"))))]
+    expand_scalar(src, dst, order);
+}
+
+fn permute_scalar(data: &mut [u8], order: [u8; 4]) {
+    for pixel in data.chunks_exact_mut(4) {
+        let source = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        for (byte, &index) in pixel.iter_mut().zip(&order) {
+            *byte = source[index as usize];
+        }
+    }
+}
+
+fn expand_scalar(src: &[u8], dst: &mut [u8], order: [u8; 4]) {
+    for (source, pixel) in src.chunks_exact(3).zip(dst.chunks_exact_mut(4)) {
+        for (byte, &index) in pixel.iter_mut().zip(&order) {
+            *byte = if index == OPAQUE { 0xFF } else { source[index as usize] };
+        }
+    }
+}
+
+/// Builds a 16-byte table shuffle that applies `order` to four 4-byte pixels.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64", all(target_arch = "wasm32", target_feature = "simd128")))]
+fn permute_mask(order: [u8; 4]) -> [u8; 16] {
+    let mut mask = [0; 16];
+    for (i, byte) in mask.iter_mut().enumerate() {
+        *byte = (i & !3) as u8 + order[i & 3];
+    }
+    mask
+}
+
+/// Builds a 16-byte table shuffle that widens four 3-byte pixels, along with
+/// the bytes to OR in afterwards for opaque alpha. Out-of-range indices zero
+/// the lane on every backend.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64", all(target_arch = "wasm32", target_feature = "simd128")))]
+fn expand_mask(order: [u8; 4]) -> ([u8; 16], [u8; 16]) {
+    let mut mask = [0; 16];
+    let mut fill = [0; 16];
+    for i in 0..16 {
+        let index = order[i & 3];
+        if index == OPAQUE {
+            mask[i] = 0x80;
+            fill[i] = 0xFF;
+        } else {
+            mask[i] = (i / 4 * 3) as u8 + index;
+        }
+    }
+    (mask, fill)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn permute_ssse3(data: &mut [u8], order: [u8; 4]) {
+        let mask = _mm_loadu_si128(super::permute_mask(order).as_ptr() as *const __m128i);
+
+        let mut chunks = data.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            let vector = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            let shuffled = _mm_shuffle_epi8(vector, mask);
+            _mm_storeu_si128(chunk.as_mut_ptr() as *mut __m128i, shuffled);
+        }
+        super::permute_scalar(chunks.into_remainder(), order);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn permute_avx2(data: &mut [u8], order: [u8; 4]) {
+        let half = _mm_loadu_si128(super::permute_mask(order).as_ptr() as *const __m128i);
+        let mask = _mm256_broadcastsi128_si256(half);
+
+        let mut chunks = data.chunks_exact_mut(32);
+        for chunk in &mut chunks {
+            let vector = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            let shuffled = _mm256_shuffle_epi8(vector, mask);
+            _mm256_storeu_si256(chunk.as_mut_ptr() as *mut __m256i, shuffled);
+        }
+        permute_ssse3(chunks.into_remainder(), order);
+    }
+
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn expand_ssse3(src: &[u8], dst: &mut [u8], order: [u8; 4]) {
+        let (mask, fill) = super::expand_mask(order);
+        let mask = _mm_loadu_si128(mask.as_ptr() as *const __m128i);
+        let fill = _mm_loadu_si128(fill.as_ptr() as *const __m128i);
+
+        // Each step consumes 12 source bytes but loads 16, so stop while a
+        // full load still fits.
+        let mut done = 0;
+        while (done + 1) * 12 + 4 <= src.len() && (done + 1) * 16 <= dst.len() {
+            let vector = _mm_loadu_si128(src[done * 12..].as_ptr() as *const __m128i);
+            let widened = _mm_or_si128(_mm_shuffle_epi8(vector, mask), fill);
+            _mm_storeu_si128(dst[done * 16..].as_mut_ptr() as *mut __m128i, widened);
+            done += 1;
+        }
+        super::expand_scalar(&src[done * 12..], &mut dst[done * 16..], order);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    pub(super) fn permute(data: &mut [u8], order: [u8; 4]) {
+        let mask = super::permute_mask(order);
+
+        let mut chunks = data.chunks_exact_mut(16);
+        unsafe {
+            let mask = vld1q_u8(mask.as_ptr());
+            for chunk in &mut chunks {
+                let vector = vld1q_u8(chunk.as_ptr());
+                vst1q_u8(chunk.as_mut_ptr(), vqtbl1q_u8(vector, mask));
+            }
+        }
+        super::permute_scalar(chunks.into_remainder(), order);
+    }
+
+    pub(super) fn expand(src: &[u8], dst: &mut [u8], order: [u8; 4]) {
+        let (mask, fill) = super::expand_mask(order);
+
+        let mut done = 0;
+        unsafe {
+            let mask = vld1q_u8(mask.as_ptr());
+            let fill = vld1q_u8(fill.as_ptr());
+            while (done + 1) * 12 + 4 <= src.len() && (done + 1) * 16 <= dst.len() {
+                let vector = vld1q_u8(src[done * 12..].as_ptr());
+                let widened = vorrq_u8(vqtbl1q_u8(vector, mask), fill);
+                vst1q_u8(dst[done * 16..].as_mut_ptr(), widened);
+                done += 1;
+            }
+        }
+        super::expand_scalar(&src[done * 12..], &mut dst[done * 16..], order);
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod wasm {
+    use std::arch::wasm32::*;
+
+    pub(super) fn permute(data: &mut [u8], order: [u8; 4]) {
+        let mask = unsafe { v128_load(super::permute_mask(order).as_ptr() as *const v128) };
+
+        let mut chunks = data.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            unsafe {
+                let vector = v128_load(chunk.as_ptr() as *const v128);
+                v128_store(chunk.as_mut_ptr() as *mut v128, i8x16_swizzle(vector, mask));
+            }
+        }
+        super::permute_scalar(chunks.into_remainder(), order);
+    }
+
+    pub(super) fn expand(src: &[u8], dst: &mut [u8], order: [u8; 4]) {
+        let (mask, fill) = super::expand_mask(order);
+        let (mask, fill) = unsafe { (v128_load(mask.as_ptr() as *const v128), v128_load(fill.as_ptr() as *const v128)) };
+
+        let mut done = 0;
+        while (done + 1) * 12 + 4 <= src.len() && (done + 1) * 16 <= dst.len() {
+            unsafe {
+                let vector = v128_load(src[done * 12..].as_ptr() as *const v128);
+                let widened = v128_or(i8x16_swizzle(vector, mask), fill);
+                v128_store(dst[done * 16..].as_mut_ptr() as *mut v128, widened);
+            }
+            done += 1;
+        }
+        super::expand_scalar(&src[done * 12..], &mut dst[done * 16..], order);
+    }
+}
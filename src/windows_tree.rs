@@ -0,0 +1,216 @@
+//! Icon resources of a Windows installation mounted on another system.
+//!
+//! Since Windows 10 the icons of system libraries such as `imageres.dll` live
+//! in resource-only `.mun` files under `SystemResources`, leaving the library
+//! itself a stub, and language-specific resources live in `.mui` satellites
+//! next to it. [`WindowsTree`] follows both redirections.
+
+use crate::error::IconError;
+use crate::ico;
+use crate::location::{expand_environment, IconLocation};
+use crate::resource::{Executable, IconResources, ResourceId, ICON_FILE_SIGNATURE, RT_GROUP_ICON};
+use crate::size::IconSize;
+use image::RgbaImage;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Directories `LoadLibrary` searches for a bare module name, relative to the volume root.
+const MODULE_SEARCH_PATH: [&str; 2] = ["Windows\\System32", "Windows"];
+const SYSTEM_RESOURCES: &str = "Windows\\SystemResources";
+/// Variables that point into the tree on a default installation.
+const DEFAULT_ENVIRONMENT: [(&str, &str); 11] = [
+    ("SystemDrive", "C:"),
+    ("SystemRoot", "C:\\Windows"),
+    ("windir", "C:\\Windows"),
+    ("ProgramFiles", "C:\\Program Files"),
+    ("ProgramFiles(x86)", "C:\\Program Files (x86)"),
+    ("ProgramW6432", "C:\\Program Files"),
+    ("CommonProgramFiles", "C:\\Program Files\\Common Files"),
+    ("CommonProgramFiles(x86)", "C:\\Program Files (x86)\\Common Files"),
+    ("ProgramData", "C:\\ProgramData"),
+    ("ALLUSERSPROFILE", "C:\\ProgramData"),
+    ("PUBLIC", "C:\\Users\\Public"),
+];
+
+/// A Windows system volume mounted at `root`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowsTree {
+    pub root: PathBuf,
+    /// UI languages whose MUI satellites are consulted, in order of preference.
+    pub languages: Vec<String>,
+    /// Host directories of the drives, by uppercase letter. When empty, every
+    /// drive letter is taken to name the volume at `root`.
+    pub drives: BTreeMap<char, PathBuf>,
+}
+
+impl WindowsTree {
+    /// Returns the tree mounted at `root`, preferring `en-US` resources.
+    pub fn new(root: impl Into<PathBuf>) -> WindowsTree {
+        WindowsTree { root: root.into(), languages: vec!["en-US".to_string()], drives: BTreeMap::new() }
+    }
+
+    /// Returns the environment variables that point into a default installation.
+    pub fn environment(&self) -> HashMap<String, String> {
+        DEFAULT_ENVIRONMENT.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    /// Maps a path on the mounted volume to the host, or returns `None` if
+    /// it is on a drive missing from [`drives`](WindowsTree::drives).
+    ///
+    /// Paths without a drive letter are on the volume at `root`, and each
+    /// component is matched without regard to case, as Windows would. `..`
+    /// stops at the drive root rather than leaving the mounted volume.
+    pub fn host_path(&self, windows_path: &str) -> Option<PathBuf> {
+        let (root, path) = match windows_path.as_bytes() {
+            [letter, b':', ..] if letter.is_ascii_alphabetic() && !self.drives.is_empty() => {
+                (self.drives.get(&char::from(letter.to_ascii_uppercase()))?, &windows_path[2..])
+            }
+            [letter, b':', ..] if letter.is_ascii_alphabetic() => (&self.root, &windows_path[2..]),
+            _ => (&self.root, windows_path),
+        };
+        let mut components = Vec::new();
+        for component in path.split(['\\', '/']) {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    components.pop();
+                }
+                _ => components.push(component),
+            }
+        }
+        Some(components.into_iter().fold(root.clone(), |dir, component| resolve_component(&dir, component)))
+    }
+
+    /// Finds a module by full path, or by bare name in the system and Windows directories.
+    pub fn find_module(&self, module: &str) -> Option<PathBuf> {
+        if module.contains(['\\', '/', ':']) {
+            return self.host_path(module).filter(|path| path.is_file());
+        }
+        MODULE_SEARCH_PATH
+            .iter()
+            .filter_map(|dir| self.host_path(&format!("{dir}\\{module}")))
+            .find(|path| path.is_file())
+    }
+
+    /// Gathers the resources of a module from its `.mun` file, the module
+    /// itself and its MUI satellites, in that order of precedence.
+    pub fn module_resources(&self, module: &str) -> Result<ModuleResources, IconError> {
+        let found = self.find_module(module);
+        let name = module.rsplit(['\\', '/']).next().unwrap_or(module);
+        let directory = match &found {
+            Some(path) => path.parent().map_or_else(|| self.root.clone(), Path::to_path_buf),
+            None => self.host_path(MODULE_SEARCH_PATH[0]).unwrap_or_else(|| self.root.clone()),
+        };
+
+        let mut candidates: Vec<PathBuf> = self.host_path(&format!("{SYSTEM_RESOURCES}\\{name}.mun")).into_iter().collect();
+        candidates.extend(found);
+        candidates.extend(self.languages.iter().map(|language| {
+            resolve_component(&resolve_component(&directory, language), &format!("{name}.mui"))
+        }));
+
+        let mut files = Vec::new();
+        for path in candidates.into_iter().filter(|path| path.is_file()) {
+            let data = std::fs::read(&path).map_err(|source| IconError::Io { path: path.clone(), source })?;
+            files.push((path, data));
+        }
+
+        if files.is_empty() {
+            let source = std::io::Error::from(std::io::ErrorKind::NotFound);
+            return Err(IconError::Io { path: directory.join(name), source });
+        }
+        Ok(ModuleResources { files })
+    }
+
+    /// Loads the icon at an `ExtractIconEx` index from a module, following
+    /// `.mun` and MUI redirection.
+    ///
+    /// `path` may also name an icon file, which has only index 0. Variables
+    /// in it are expanded from [`WindowsTree::environment`].
+    pub fn load_icon_as_image(&self, path: &str, index: i32, size: IconSize) -> Result<RgbaImage, IconError> {
+        let path = expand_environment(path, &self.environment());
+        let icon_file = self.find_module(&path).filter(|host| is_icon_file(host));
+        let entries = match icon_file {
+            Some(_) if index != 0 => return Err(IconError::NoIconAtIndex(index)),
+            Some(host) => ico::read(&host)?,
+            None => ico::parse(&self.module_resources(&path)?.icon_at_index(index)?)?,
+        };
+        ico::image_for_size(&entries, size).ok_or_else(|| IconError::NoIcon { path: self.host_path(&path).unwrap_or_else(|| PathBuf::from(&path)) })
+    }
+
+    /// Loads the icon an [`IconLocation`] points at, with `document` standing in for `%1`.
+    pub fn load_icon_location(&self, location: &IconLocation, document: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        self.load_icon_as_image(location.path(document), location.index, size)
+    }
+}
+
+/// Returns true if the file at `path` starts with an icon directory header.
+fn is_icon_file(path: &Path) -> bool {
+    let mut header = [0; 4];
+    std::fs::File::open(path).and_then(|mut file| file.read_exact(&mut header)).is_ok() && header == ICON_FILE_SIGNATURE
+}
+
+/// Returns `dir/name`, or the entry of `dir` whose name matches `name` case-insensitively.
+pub(crate) fn resolve_component(dir: &Path, name: &str) -> PathBuf {
+    let exact = dir.join(name);
+    if exact.exists() {
+        return exact;
+    }
+
+    std::fs::read_dir(dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .flatten()
+                .find(|entry| entry.file_name().to_string_lossy().eq_ignore_ascii_case(name))
+                .map(|entry| entry.path())
+        })
+        .unwrap_or(exact)
+}
+
+/// The files a module's resources are spread across, in order of precedence.
+#[derive(Clone, Debug)]
+pub struct ModuleResources {
+    files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl ModuleResources {
+    /// Returns the host paths of the files found, in order of precedence.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+}
+
+impl IconResources for ModuleResources {
+    /// Returns the names from the first file that has any resources of the type.
+    fn resource_names(&self, resource_type: u16) -> Result<Vec<ResourceId>, IconError> {
+        for (_, data) in &self.files {
+            let names = Executable::parse(data)?.resource_names(resource_type)?;
+            if !names.is_empty() {
+                return Ok(names);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn resource(&self, resource_type: u16, name: &ResourceId) -> Result<Option<&[u8]>, IconError> {
+        for (_, data) in &self.files {
+            if let Some(found) = Executable::parse(data)?.find(resource_type, name)? {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reassembles a group from the images of the file that holds it, as
+    /// image IDs are only unique within one file.
+    fn icon_group(&self, name: &ResourceId) -> Result<Vec<u8>, IconError> {
+        for (_, data) in &self.files {
+            let file = Executable::parse(data)?;
+            if file.find(RT_GROUP_ICON, name)?.is_some() {
+                return file.icon_group(name);
+            }
+        }
+        Err(IconError::MissingResource { resource_type: RT_GROUP_ICON, name: name.clone() })
+    }
+}
@@ -0,0 +1,114 @@
+//! Icons of a Wine prefix, resolved from its registry files and the
+//! executables installed under `drive_c`.
+//!
+//! Wine keeps the machine's registry in `system.reg` and the user's in
+//! `user.reg`, both in its text format. Its drives are the `x:` links in
+//! `dosdevices`, where `C:` leads to `drive_c` and usually `Z:` to the host's root.
+
+use crate::association::Associations;
+use crate::error::IconError;
+use crate::location::{IconFile, IconLocation};
+use crate::provider::{normalize_extension, IconProvider};
+use crate::reg_file::RegFile;
+use crate::resource;
+use crate::size::IconSize;
+use crate::windows_tree::{resolve_component, WindowsTree};
+use image::RgbaImage;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const SYSTEM_REG: &str = "system.reg";
+const USER_REG: &str = "user.reg";
+const DRIVE_C: &str = "drive_c";
+const DOSDEVICES: &str = "dosdevices";
+/// The generic application icon, shown for executables without icons of their own.
+const DEFAULT_APPLICATION_ICON: &str = r"%SystemRoot%\system32\shell32.dll,2";
+
+/// Serves the icons Windows programs running in a Wine prefix would see.
+pub struct WineIconProvider {
+    system: RegFile,
+    user: RegFile,
+    tree: WindowsTree,
+}
+
+impl WineIconProvider {
+    /// Loads the registry of the prefix at `prefix`, such as `~/.wine`.
+    pub fn open(prefix: &Path) -> Result<WineIconProvider, IconError> {
+        let mut tree = WindowsTree::new(resolve_component(prefix, DRIVE_C));
+        tree.drives = drives(prefix);
+        Ok(WineIconProvider {
+            system: RegFile::read(&resolve_component(prefix, SYSTEM_REG))?,
+            user: RegFile::read(&resolve_component(prefix, USER_REG))?,
+            tree,
+        })
+    }
+
+    /// Returns the prefix's drives: every letter in `dosdevices`, with `C:`
+    /// falling back to `drive_c` and serving as the [root](WindowsTree::root).
+    pub fn tree(&self) -> &WindowsTree {
+        &self.tree
+    }
+
+    /// Returns the prefix's associations, with the user's classes taking precedence over the machine's.
+    pub fn associations(&self) -> Associations<'_> {
+        let mut associations = Associations::new();
+        associations.push_classes(&self.user, r"HKEY_CURRENT_USER\Software\Classes");
+        associations.push_classes(&self.system, r"HKEY_LOCAL_MACHINE\Software\Classes");
+        associations.set_user(&self.user, "HKEY_CURRENT_USER");
+        associations
+    }
+
+    fn load_default_application_icon(&self, size: IconSize) -> Result<RgbaImage, IconError> {
+        let location = IconLocation::parse(DEFAULT_APPLICATION_ICON, &self.tree.environment())?;
+        self.tree.load_icon_location(&location, "", size)
+    }
+}
+
+impl IconProvider for WineIconProvider {
+    /// Returns the icon of the host file at `path`.
+    ///
+    /// Types whose icon is `%1`, and executables, take the icon from the file
+    /// itself; executables without icons get the generic application icon.
+    fn icon_for_path(&self, path: &Path, size: IconSize) -> Result<RgbaImage, IconError> {
+        let extension = normalize_extension(&path.extension().unwrap_or_default().to_string_lossy());
+        let executable = extension == "exe";
+        let location = match self.associations().icon_location(&extension, &self.tree.environment())? {
+            Some(location) => location,
+            None if executable => IconLocation { file: IconFile::Itself, index: 0 },
+            None => return Err(IconError::NoIcon { path: path.to_path_buf() }),
+        };
+
+        match location.file {
+            IconFile::Path(icon) => self.tree.load_icon_as_image(&icon, location.index, size),
+            IconFile::Itself => match resource::load_icon_as_image(path, location.index, size) {
+                Err(IconError::NoIconAtIndex(_)) if executable => self.load_default_application_icon(size),
+                result => result,
+            },
+        }
+    }
+
+    fn icon_for_extension(&self, extension: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        self.associations().load_icon_for_extension(extension, &self.tree, size)
+    }
+
+    /// Returns the icon of the extension registered for `mime` under `MIME\Database\Content Type`.
+    fn icon_for_mime(&self, mime: &str, size: IconSize) -> Result<RgbaImage, IconError> {
+        match self.associations().extension_for_mime(mime)? {
+            Some(extension) => self.icon_for_extension(&extension, size),
+            None => Err(IconError::NoIconForMime(mime.to_string())),
+        }
+    }
+}
+
+/// Returns the prefix's drives from the `x:` entries of `dosdevices`, with `C:` at `drive_c` if it has none.
+fn drives(prefix: &Path) -> BTreeMap<char, PathBuf> {
+    let entries = std::fs::read_dir(prefix.join(DOSDEVICES)).into_iter().flatten().flatten();
+    let mut drives: BTreeMap<char, PathBuf> = entries
+        .filter_map(|entry| match entry.file_name().as_encoded_bytes() {
+            [letter, b':'] if letter.is_ascii_alphabetic() => Some((char::from(letter.to_ascii_uppercase()), entry.path())),
+            _ => None,
+        })
+        .collect();
+    drives.entry('C').or_insert_with(|| resolve_component(prefix, DRIVE_C));
+    drives
+}
@@ -0,0 +1,85 @@
+use image::RgbaImage;
+use windows_ext_icons::swizzle::Backend;
+use windows_ext_icons::{detect_alpha_mode, premultiply, premultiply_image, unpremultiply, AlphaMode};
+
+/// Every (colour, alpha) pair plus a few trailing pixels for the scalar tail.
+fn all_pairs() -> Vec<u8> {
+    let mut data = Vec::new();
+    for alpha in 0..=255u8 {
+        for colour in 0..=255u8 {
+            data.extend_from_slice(&[colour, 255 - colour, colour / 2, alpha]);
+        }
+    }
+    data.extend_from_slice(&[10, 20, 30, 40, 50, 60, 70, 80]);
+    data
+}
+
+#[test]
+fn premultiply_matches_scalar_reference() {
+    let mut fast = all_pairs();
+    let mut reference = fast.clone();
+    premultiply(&mut fast);
+    Backend::Scalar.premultiply(&mut reference);
+    assert_eq!(fast, reference);
+}
+
+#[test]
+fn unpremultiply_matches_scalar_reference() {
+    let mut fast = all_pairs();
+    let mut reference = fast.clone();
+    unpremultiply(&mut fast);
+    Backend::Scalar.unpremultiply(&mut reference);
+    assert_eq!(fast, reference);
+}
+
+#[test]
+fn every_backend_converts_like_scalar() {
+    let pairs = all_pairs();
+    // Lengths short of a whole vector, with partial pixels, leave work for the scalar tails.
+    for len in (0..=70).chain([pairs.len()]) {
+        let input = &pairs[pairs.len() - len..];
+        let mut premultiplied = input.to_vec();
+        let mut unpremultiplied = input.to_vec();
+        Backend::Scalar.premultiply(&mut premultiplied);
+        Backend::Scalar.unpremultiply(&mut unpremultiplied);
+
+        for backend in Backend::available() {
+            let mut data = input.to_vec();
+            backend.premultiply(&mut data);
+            assert_eq!(data, premultiplied, "{backend:?} premultiply mismatch for length {len}");
+
+            let mut data = input.to_vec();
+            backend.unpremultiply(&mut data);
+            assert_eq!(data, unpremultiplied, "{backend:?} unpremultiply mismatch for length {len}");
+        }
+    }
+}
+
+#[test]
+fn premultiplies_with_rounding() {
+    let mut data = [255, 128, 0, 128, 200, 100, 50, 0, 7, 8, 9, 255];
+    premultiply(&mut data);
+    assert_eq!(data, [128, 64, 0, 128, 0, 0, 0, 0, 7, 8, 9, 255]);
+}
+
+#[test]
+fn round_trips_opaque_and_half_transparent() {
+    let mut data = [255, 128, 0, 128, 7, 8, 9, 255];
+    premultiply(&mut data);
+    unpremultiply(&mut data);
+    assert_eq!(data, [255, 128, 0, 128, 7, 8, 9, 255]);
+}
+
+#[test]
+fn detects_alpha_convention() {
+    assert_eq!(detect_alpha_mode(&[64, 32, 0, 128, 1, 2, 3, 255]), AlphaMode::Premultiplied);
+    assert_eq!(detect_alpha_mode(&[200, 32, 0, 128]), AlphaMode::Straight);
+    assert_eq!(detect_alpha_mode(&[1, 2, 3, 255, 0, 0, 0, 0]), AlphaMode::Straight);
+}
+
+#[test]
+fn premultiplies_images() {
+    let mut image = RgbaImage::from_raw(1, 1, vec![255, 255, 255, 51]).unwrap();
+    premultiply_image(&mut image);
+    assert_eq!(image.into_raw(), [51, 51, 51, 51]);
+}
@@ -0,0 +1,133 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, Rgba, RgbaImage};
+use std::io::Cursor as Reader;
+use std::time::Duration;
+use windows_ext_icons::ico::{self, EncodeOptions};
+use windows_ext_icons::{ani, cur, Cursor, IconError, Stage};
+
+fn solid(color: [u8; 4]) -> RgbaImage {
+    RgbaImage::from_pixel(8, 8, Rgba(color))
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn anih(frames: u32, steps: u32, rate: u32, flags: u32) -> Vec<u8> {
+    chunk(b"anih", &words(&[36, frames, steps, 0, 0, 0, 0, rate, flags]))
+}
+
+fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body = [b"ACON".to_vec(), chunks.concat()].concat();
+    [b"RIFF".to_vec(), (body.len() as u32).to_le_bytes().to_vec(), body].concat()
+}
+
+fn frames(icons: &[Vec<u8>]) -> Vec<u8> {
+    let list: Vec<u8> = [b"fram".to_vec()].into_iter().chain(icons.iter().map(|icon| chunk(b"icon", icon))).flatten().collect();
+    chunk(b"LIST", &list)
+}
+
+fn cursor_file(color: [u8; 4], hotspot: (u32, u32)) -> Vec<u8> {
+    cur::encode(&Cursor { images: vec![solid(color)], hotspots: vec![hotspot] }).unwrap()
+}
+
+fn sample() -> Vec<u8> {
+    riff(&[
+        anih(2, 3, 6, 0x3),
+        chunk(b"rate", &words(&[6, 12, 30])),
+        chunk(b"seq ", &words(&[0, 1, 0])),
+        frames(&[cursor_file([255, 0, 0, 255], (1, 2)), cursor_file([0, 0, 255, 255], (3, 4))]),
+    ])
+}
+
+#[test]
+fn follows_sequence_and_rates() {
+    let cursor = ani::parse(&sample()).unwrap();
+    let steps: Vec<_> = cursor.frames.iter().map(|frame| (frame.image.get_pixel(0, 0).0, frame.hotspot, frame.duration)).collect();
+    assert_eq!(
+        steps,
+        [
+            ([255, 0, 0, 255], (1, 2), Duration::from_millis(100)),
+            ([0, 0, 255, 255], (3, 4), Duration::from_millis(200)),
+            ([255, 0, 0, 255], (1, 2), Duration::from_millis(500)),
+        ]
+    );
+}
+
+#[test]
+fn defaults_to_frame_order_and_display_rate() {
+    let icon = ico::encode(&[solid([0, 255, 0, 255])], &EncodeOptions::default()).unwrap();
+    let data = riff(&[anih(2, 2, 3, 0x1), chunk(b"INAM", b"busy"), frames(&[icon.clone(), icon])]);
+    let cursor = ani::parse(&data).unwrap();
+
+    assert_eq!(cursor.frames.len(), 2);
+    assert!(cursor.frames.iter().all(|frame| frame.duration == Duration::from_millis(50)));
+    assert_eq!(cursor.frames[0].hotspot, (4, 4));
+}
+
+#[test]
+fn rejects_bad_containers() {
+    assert!(matches!(ani::parse(b"RIFF\x04\0\0\0WAVE"), Err(IconError::Malformed { stage: Stage::RiffChunk })));
+
+    let missing_header = riff(&[frames(&[cursor_file([0; 4], (0, 0))])]);
+    assert!(matches!(ani::parse(&missing_header), Err(IconError::Malformed { stage: Stage::AniHeader })));
+
+    let bad_step = riff(&[anih(1, 1, 1, 0x3), chunk(b"seq ", &words(&[4])), frames(&[cursor_file([0; 4], (0, 0))])]);
+    assert!(matches!(ani::parse(&bad_step), Err(IconError::Malformed { stage: Stage::AniHeader })));
+
+    let mut truncated = sample();
+    truncated.truncate(truncated.len() - 20);
+    assert!(matches!(ani::parse(&truncated), Err(IconError::Truncated { stage: Stage::RiffChunk })));
+}
+
+#[test]
+fn exports_apng() {
+    let cursor = ani::parse(&sample()).unwrap();
+    let decoder = PngDecoder::new(Reader::new(ani::encode_apng(&cursor).unwrap())).unwrap();
+    let frames = decoder.apng().unwrap().into_frames().collect_frames().unwrap();
+
+    assert_eq!(frames.len(), 3);
+    for (frame, source) in frames.iter().zip(&cursor.frames) {
+        assert_eq!(frame.buffer(), &source.image);
+        assert_eq!(Duration::from(frame.delay()), source.duration);
+    }
+}
+
+#[test]
+fn exports_gif() {
+    let cursor = ani::parse(&sample()).unwrap();
+    let decoder = GifDecoder::new(Reader::new(ani::encode_gif(&cursor).unwrap())).unwrap();
+    let frames = decoder.into_frames().collect_frames().unwrap();
+
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[1].buffer().get_pixel(0, 0).0, [0, 0, 255, 255]);
+    assert_eq!(Duration::from(frames[2].delay()), Duration::from_millis(500));
+}
+
+#[test]
+fn clamps_long_gif_delays() {
+    let data = riff(&[anih(1, 1, 257_698_042, 0x3), frames(&[cursor_file([0, 255, 0, 255], (0, 0))])]);
+    let cursor = ani::parse(&data).unwrap();
+    let decoder = GifDecoder::new(Reader::new(ani::encode_gif(&cursor).unwrap())).unwrap();
+    let frames = decoder.into_frames().collect_frames().unwrap();
+
+    assert_eq!(Duration::from(frames[0].delay()), Duration::from_millis(655_350));
+}
+
+#[test]
+fn rejects_exporting_empty_animations() {
+    let cursor = ani::AnimatedCursor { frames: Vec::new() };
+    assert!(matches!(ani::encode_apng(&cursor), Err(IconError::Malformed { stage: Stage::AniHeader })));
+    assert!(matches!(ani::encode_gif(&cursor), Err(IconError::Malformed { stage: Stage::AniHeader })));
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::{hive_file, icon_resources, pe_file, reg_key, reg_sz, solid, RegKey, TempDir};
+use windows_ext_icons::registry::{REG_EXPAND_SZ, REG_SZ};
+use windows_ext_icons::{Associations, Hive, IconError, IconFile, IconLocation, IconSize, ResourceId, WindowsTree};
+
+fn class(name: &str, default: Option<&str>, icon: Option<&str>, subkeys: Vec<RegKey>) -> RegKey {
+    let values = default.map(|value| vec![("", REG_SZ, reg_sz(value))]).unwrap_or_default();
+    let mut subkeys = subkeys;
+    if let Some(icon) = icon {
+        subkeys.push(reg_key("DefaultIcon", vec![("", REG_EXPAND_SZ, reg_sz(icon))], vec![]));
+    }
+    reg_key(name, values, subkeys)
+}
+
+fn open_with(prog_ids: &[&str]) -> RegKey {
+    reg_key("OpenWithProgids", prog_ids.iter().map(|prog_id| (*prog_id, REG_SZ, vec![])).collect(), vec![])
+}
+
+fn software() -> Vec<u8> {
+    let mut jpg = class(".jpg", None, None, vec![]);
+    jpg.values.push(("PerceivedType".into(), REG_SZ, reg_sz("image")));
+    hive_file(&reg_key(
+        "ROOT",
+        vec![],
+        vec![reg_key(
+            "Classes",
+            vec![],
+            vec![
+                class(".txt", Some("txtfile"), None, vec![]),
+                class("txtfile", None, Some("%SystemRoot%\\system32\\imageres.dll,-102"), vec![]),
+                class(".pdf", None, None, vec![open_with(&["Unregistered.pdf", "AcroExch.Document.DC"])]),
+                class("AcroExch.Document.DC", None, Some("\"C:\\Program Files\\Adobe\\Acrobat.exe\",1"), vec![]),
+                class(".ini", Some("inifile"), None, vec![]),
+                class("inifile", None, Some("%SystemRoot%\\system32\\imageres.dll,-69"), vec![]),
+                class(".exe", Some("exefile"), None, vec![]),
+                class("exefile", None, Some("%1"), vec![]),
+                class(".log", None, Some("log.ico"), vec![]),
+                jpg,
+                class(
+                    "SystemFileAssociations",
+                    None,
+                    None,
+                    vec![
+                        class(".xyz", None, Some("xyz.dll,3"), vec![]),
+                        class("image", None, Some("%SystemRoot%\\system32\\imageres.dll,-72"), vec![]),
+                    ],
+                ),
+            ],
+        )],
+    ))
+}
+
+fn usr_class() -> Vec<u8> {
+    hive_file(&reg_key(
+        "ROOT",
+        vec![],
+        vec![
+            class("Notepad++.txt", None, Some("C:\\Tools\\notepad++.exe,0"), vec![]),
+            class("inifile", None, Some("%SystemRoot%\\system32\\user.dll,0"), vec![]),
+        ],
+    ))
+}
+
+fn ntuser() -> Vec<u8> {
+    let ext = |name: &str, subkeys| reg_key(name, vec![], subkeys);
+    let choice = |prog_id: &str| reg_key("UserChoice", vec![("ProgId", REG_SZ, reg_sz(prog_id)), ("Hash", REG_SZ, reg_sz("abc="))], vec![]);
+    let file_exts = reg_key(
+        "FileExts",
+        vec![],
+        vec![ext(".txt", vec![choice("Notepad++.txt")]), ext(".log", vec![open_with(&["txtfile"])])],
+    );
+    let path = ["Software", "Microsoft", "Windows", "CurrentVersion", "Explorer"];
+    let explorer = path.iter().rev().fold(file_exts, |key, name| reg_key(name, vec![], vec![key]));
+    hive_file(&reg_key("ROOT", vec![], vec![explorer]))
+}
+
+#[test]
+fn follows_the_resolution_chain() {
+    let (software, usr_class, ntuser) = (software(), usr_class(), ntuser());
+    let mut associations = Associations::new();
+    associations.push_classes(Hive::parse(&usr_class).unwrap(), "");
+    associations.push_classes(Hive::parse(&software).unwrap(), "Classes");
+
+    let icon = |associations: &Associations, extension| associations.default_icon(extension).unwrap();
+    assert_eq!(icon(&associations, ".txt").as_deref(), Some("%SystemRoot%\\system32\\imageres.dll,-102"));
+    assert_eq!(icon(&associations, "PDF").as_deref(), Some("\"C:\\Program Files\\Adobe\\Acrobat.exe\",1"));
+    assert_eq!(icon(&associations, "ini").as_deref(), Some("%SystemRoot%\\system32\\user.dll,0"));
+    assert_eq!(icon(&associations, "log").as_deref(), Some("log.ico"));
+    assert_eq!(icon(&associations, "xyz").as_deref(), Some("xyz.dll,3"));
+    assert_eq!(icon(&associations, "jpg").as_deref(), Some("%SystemRoot%\\system32\\imageres.dll,-72"));
+    assert_eq!(icon(&associations, "none"), None);
+
+    associations.set_user(Hive::parse(&ntuser).unwrap(), "");
+    assert_eq!(associations.prog_ids("txt").unwrap(), ["Notepad++.txt", "txtfile", ".txt"]);
+    assert_eq!(icon(&associations, ".txt").as_deref(), Some("C:\\Tools\\notepad++.exe,0"));
+    assert_eq!(associations.prog_ids(".log").unwrap(), ["txtfile", ".log"]);
+    assert_eq!(icon(&associations, "log").as_deref(), Some("%SystemRoot%\\system32\\imageres.dll,-102"));
+    assert_eq!(
+        associations.prog_ids(".pdf").unwrap(),
+        ["Unregistered.pdf", "AcroExch.Document.DC", ".pdf"],
+    );
+}
+
+#[test]
+fn loads_extension_icons_from_a_windows_tree() {
+    let dir = TempDir::new("association");
+    dir.write(
+        "Windows/System32/imageres.dll",
+        &pe_file(&icon_resources(&[(ResourceId::Id(72), vec![solid(32, [1, 0, 0, 255])]), (ResourceId::Id(102), vec![solid(32, [2, 0, 0, 255])])]), true),
+    );
+    let tree = WindowsTree::new(&dir.0);
+    let software = software();
+    let mut associations = Associations::new();
+    associations.push_classes(Hive::parse(&software).unwrap(), "Classes");
+
+    assert_eq!(
+        associations.icon_location("txt", &tree.environment()).unwrap(),
+        Some(IconLocation { file: IconFile::Path("C:\\Windows\\system32\\imageres.dll".into()), index: -102 })
+    );
+    let color = |extension| associations.load_icon_for_extension(extension, &tree, IconSize::Large).map(|image| image.get_pixel(0, 0).0);
+    assert_eq!(color("txt").unwrap(), [2, 0, 0, 255]);
+    assert_eq!(color("jpg").unwrap(), [1, 0, 0, 255]);
+    assert!(matches!(color("exe"), Err(IconError::NoIconForExtension(extension)) if extension == "exe"));
+    assert!(matches!(color("none"), Err(IconError::NoIconForExtension(_))));
+}
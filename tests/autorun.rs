@@ -0,0 +1,60 @@
+mod common;
+
+use common::{icon_resources, pe_file, solid, TempDir};
+use windows_ext_icons::ico::{self, EncodeOptions};
+use windows_ext_icons::{drive_icon, Autorun, AutorunIconProvider, IconFile, IconLocation, IconProvider, IconSize, MemoryIconProvider, ResourceId};
+
+const GENERIC: [u8; 4] = [128, 128, 0, 255];
+const MEDIA: [u8; 4] = [0, 64, 255, 255];
+const SETUP: [u8; 4] = [200, 0, 200, 255];
+
+fn location(path: &str, index: i32) -> Option<IconLocation> {
+    Some(IconLocation { file: IconFile::Path(path.into()), index })
+}
+
+#[test]
+fn reads_icon_with_index() {
+    let autorun = Autorun::parse(b"[AutoRun]\r\nopen=setup.exe\r\nicon=\"\\Setup\\setup.exe\",2\r\nlabel=Install\r\n");
+    assert_eq!(autorun.icon_location(false).unwrap(), location("Setup\\setup.exe", 2));
+    assert_eq!(autorun.icon_location(true).unwrap(), location("Setup\\setup.exe", 2));
+    assert_eq!(Autorun::parse(b"[autorun]\nicon=disc.ico\n").icon_location(false).unwrap(), location("disc.ico", 0));
+}
+
+#[test]
+fn prefers_amd64_section_on_64_bit() {
+    let autorun = Autorun::parse(b"[autorun.amd64]\nicon=x64\\setup.exe,1\n[autorun]\nicon=x86\\setup.exe\n");
+    assert_eq!(autorun.icon_location(true).unwrap(), location("x64\\setup.exe", 1));
+    assert_eq!(autorun.icon_location(false).unwrap(), location("x86\\setup.exe", 0));
+    assert_eq!(Autorun::parse(b"[autorun.amd64]\nicon=x64.ico\n").icon_location(false).unwrap(), None);
+    assert_eq!(Autorun::parse(b"[autorun]\nopen=setup.exe\n").icon_location(true).unwrap(), None);
+}
+
+#[test]
+fn drive_root_lookup_returns_media_icon() {
+    let dir = TempDir::new("autorun");
+    dir.write("disc/AUTORUN.INF", b"[autorun]\r\nicon=\\SETUP\\Setup.exe,1\r\n");
+    dir.write(
+        "disc/setup/setup.exe",
+        &pe_file(&icon_resources(&[(ResourceId::Id(1), vec![solid(32, MEDIA)]), (ResourceId::Id(2), vec![solid(32, SETUP)])]), false),
+    );
+    dir.write("stick/autorun.inf", b"[autorun]\nicon=stick.ico\n");
+    dir.write("stick/stick.ico", &ico::encode(&[solid(16, MEDIA)], &EncodeOptions::default()).unwrap());
+    dir.write("plain/readme.txt", b"");
+    dir.write("broken/autorun.inf", b"[autorun]\nicon=missing.ico\n");
+
+    let color = |name: &str| drive_icon(&dir.0.join(name), IconSize::Large).map(|image| image.map(|image| image.get_pixel(0, 0).0));
+    assert_eq!(color("disc").unwrap(), Some(SETUP));
+    assert_eq!(color("stick").unwrap(), Some(MEDIA));
+    assert_eq!(color("plain").unwrap(), None);
+    assert!(color("broken").is_err());
+
+    let mut generic = MemoryIconProvider::new();
+    for name in ["disc", "plain", "broken"] {
+        generic.insert_path(dir.0.join(name), solid(32, GENERIC));
+    }
+    let provider = AutorunIconProvider::new(generic, vec![dir.0.join("disc"), dir.0.join("broken"), dir.0.join("plain")]);
+    let color = |name: &str| provider.icon_for_path(&dir.0.join(name), IconSize::Large).unwrap().get_pixel(0, 0).0;
+    assert_eq!(color("disc"), SETUP);
+    assert_eq!(color("plain"), GENERIC);
+    assert_eq!(color("broken"), GENERIC);
+}
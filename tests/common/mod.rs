@@ -0,0 +1,404 @@
+//! Builders for synthetic executables shared by the integration tests.
+#![allow(dead_code)]
+
+use image::{Rgba, RgbaImage};
+use windows_ext_icons::ico::{self, EncodeOptions};
+use windows_ext_icons::resource::{RT_GROUP_ICON, RT_ICON};
+use windows_ext_icons::ResourceId;
+
+pub type Resources = Vec<(u16, Vec<(ResourceId, Vec<u8>)>)>;
+
+pub fn solid(edge: u32, color: [u8; 4]) -> RgbaImage {
+    RgbaImage::from_pixel(edge, edge, Rgba(color))
+}
+
+/// Splits an icon file into a `GRPICONDIR` and its `RT_ICON` images, numbered from `first_id`.
+pub fn split_icon(file: &[u8], first_id: u16) -> (Vec<u8>, Vec<(ResourceId, Vec<u8>)>) {
+    let count = u16::from_le_bytes([file[4], file[5]]);
+    let mut group = file[..6].to_vec();
+    let mut icons = Vec::new();
+    for index in 0..count {
+        let entry = &file[6 + usize::from(index) * 16..][..16];
+        let len = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as usize;
+        let offset = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as usize;
+        group.extend_from_slice(&entry[..12]);
+        group.extend_from_slice(&(first_id + index).to_le_bytes());
+        icons.push((ResourceId::Id(first_id + index), file[offset..offset + len].to_vec()));
+    }
+    (group, icons)
+}
+
+/// Builds the resources for icon groups, each holding the given images.
+///
+/// Named groups must come before numbered ones, as in a real directory.
+pub fn icon_resources(groups: &[(ResourceId, Vec<RgbaImage>)]) -> Resources {
+    let mut group_entries = Vec::new();
+    let mut icon_entries = Vec::new();
+    let mut next_id = 1;
+    for (name, images) in groups {
+        let file = ico::encode(images, &EncodeOptions::default()).unwrap();
+        let (group, icons) = split_icon(&file, next_id);
+        next_id += icons.len() as u16;
+        group_entries.push((name.clone(), group));
+        icon_entries.extend(icons);
+    }
+    vec![(RT_ICON, icon_entries), (RT_GROUP_ICON, group_entries)]
+}
+
+fn directory_size(entries: usize) -> usize {
+    16 + entries * 8
+}
+
+fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_directory(data: &mut [u8], offset: usize, names: &[&ResourceId]) {
+    let named = names.iter().filter(|name| matches!(name, ResourceId::Name(_))).count();
+    put_u16(data, offset + 12, named as u16);
+    put_u16(data, offset + 14, (names.len() - named) as u16);
+}
+
+/// Lays out a `.rsrc` section loaded at `base_rva`, with one language per resource.
+pub fn resource_section(types: &Resources, base_rva: u32) -> Vec<u8> {
+    let resources: Vec<&(ResourceId, Vec<u8>)> = types.iter().flat_map(|(_, entries)| entries).collect();
+
+    let mut offset = directory_size(types.len());
+    let mut type_directories = Vec::new();
+    for (_, entries) in types {
+        type_directories.push(offset);
+        offset += directory_size(entries.len());
+    }
+    let language_directories: Vec<usize> = (0..resources.len()).map(|index| offset + index * directory_size(1)).collect();
+    offset += resources.len() * directory_size(1);
+    let data_entries: Vec<usize> = (0..resources.len()).map(|index| offset + index * 16).collect();
+    offset += resources.len() * 16;
+    let mut strings = Vec::new();
+    for (name, _) in &resources {
+        if let ResourceId::Name(name) = name {
+            strings.push(offset);
+            offset += 2 + name.encode_utf16().count() * 2;
+        } else {
+            strings.push(0);
+        }
+    }
+    let mut data_offsets = Vec::new();
+    for (_, data) in &resources {
+        offset = offset.next_multiple_of(4);
+        data_offsets.push(offset);
+        offset += data.len();
+    }
+
+    let mut section = vec![0u8; offset.next_multiple_of(4)];
+    let type_names: Vec<ResourceId> = types.iter().map(|(id, _)| ResourceId::Id(*id)).collect();
+    put_directory(&mut section, 0, &type_names.iter().collect::<Vec<_>>());
+
+    let mut resource = 0;
+    for (type_index, (resource_type, entries)) in types.iter().enumerate() {
+        put_u32(&mut section, 16 + type_index * 8, u32::from(*resource_type));
+        put_u32(&mut section, 16 + type_index * 8 + 4, 0x8000_0000 | type_directories[type_index] as u32);
+
+        let directory = type_directories[type_index];
+        put_directory(&mut section, directory, &entries.iter().map(|(name, _)| name).collect::<Vec<_>>());
+        for (entry_index, (name, data)) in entries.iter().enumerate() {
+            let entry = directory + 16 + entry_index * 8;
+            match name {
+                ResourceId::Id(id) => put_u32(&mut section, entry, u32::from(*id)),
+                ResourceId::Name(text) => {
+                    put_u32(&mut section, entry, 0x8000_0000 | strings[resource] as u32);
+                    let units: Vec<u16> = text.encode_utf16().collect();
+                    put_u16(&mut section, strings[resource], units.len() as u16);
+                    for (unit_index, unit) in units.iter().enumerate() {
+                        put_u16(&mut section, strings[resource] + 2 + unit_index * 2, *unit);
+                    }
+                }
+            }
+            put_u32(&mut section, entry + 4, 0x8000_0000 | language_directories[resource] as u32);
+
+            let language = language_directories[resource];
+            put_u16(&mut section, language + 14, 1);
+            put_u32(&mut section, language + 16, 0x0409);
+            put_u32(&mut section, language + 20, data_entries[resource] as u32);
+
+            put_u32(&mut section, data_entries[resource], base_rva + data_offsets[resource] as u32);
+            put_u32(&mut section, data_entries[resource] + 4, data.len() as u32);
+            section[data_offsets[resource]..][..data.len()].copy_from_slice(data);
+            resource += 1;
+        }
+    }
+    section
+}
+
+/// Builds a minimal PE32 or PE32+ image whose only section holds `resources`.
+pub fn pe_file(resources: &Resources, pe32_plus: bool) -> Vec<u8> {
+    const RVA: u32 = 0x1000;
+    const RAW_OFFSET: usize = 0x200;
+
+    let section = resource_section(resources, RVA);
+    let optional_size: usize = if pe32_plus { 240 } else { 224 };
+    let (count_offset, directories_offset) = if pe32_plus { (108, 112) } else { (92, 96) };
+
+    let mut file = vec![0u8; RAW_OFFSET];
+    file[..2].copy_from_slice(b"MZ");
+    put_u32(&mut file, 0x3c, 0x40);
+    file[0x40..0x44].copy_from_slice(b"PE\0\0");
+    let coff = 0x44;
+    put_u16(&mut file, coff, if pe32_plus { 0x8664 } else { 0x14c });
+    put_u16(&mut file, coff + 2, 1);
+    put_u16(&mut file, coff + 16, optional_size as u16);
+
+    let optional = coff + 20;
+    put_u16(&mut file, optional, if pe32_plus { 0x20b } else { 0x10b });
+    put_u32(&mut file, optional + count_offset, 16);
+    put_u32(&mut file, optional + directories_offset + 2 * 8, RVA);
+    put_u32(&mut file, optional + directories_offset + 2 * 8 + 4, section.len() as u32);
+
+    let header = optional + optional_size;
+    file[header..header + 5].copy_from_slice(b".rsrc");
+    put_u32(&mut file, header + 8, section.len() as u32);
+    put_u32(&mut file, header + 12, RVA);
+    put_u32(&mut file, header + 16, section.len() as u32);
+    put_u32(&mut file, header + 20, RAW_OFFSET as u32);
+
+    file.extend_from_slice(&section);
+    file
+}
+
+/// Builds a minimal NE image holding `resources`, with offsets stored in units of `1 << shift`.
+pub fn ne_file(resources: &Resources, shift: u16) -> Vec<u8> {
+    const HEADER: usize = 0x40;
+    const TABLE: usize = 0x80;
+    let alignment = 1usize << shift;
+
+    let mut table = shift.to_le_bytes().to_vec();
+    let mut strings = Vec::new();
+    let names_start = 2 + resources.iter().map(|(_, entries)| 8 + entries.len() * 12).sum::<usize>() + 2;
+    let mut data_offset = (TABLE + names_start + 64).next_multiple_of(alignment);
+    let mut data = Vec::new();
+
+    for (resource_type, entries) in resources {
+        table.extend_from_slice(&(0x8000 | resource_type).to_le_bytes());
+        table.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        table.extend_from_slice(&[0; 4]);
+        for (name, bytes) in entries {
+            let id = match name {
+                ResourceId::Id(id) => 0x8000 | id,
+                ResourceId::Name(text) => {
+                    let offset = names_start + strings.len();
+                    strings.push(text.len() as u8);
+                    strings.extend_from_slice(text.as_bytes());
+                    offset as u16
+                }
+            };
+            let len = bytes.len().next_multiple_of(alignment);
+            table.extend_from_slice(&((data_offset >> shift) as u16).to_le_bytes());
+            table.extend_from_slice(&((len >> shift) as u16).to_le_bytes());
+            table.extend_from_slice(&0x30u16.to_le_bytes());
+            table.extend_from_slice(&id.to_le_bytes());
+            table.extend_from_slice(&[0; 4]);
+
+            data.push((data_offset, bytes));
+            data_offset += len;
+        }
+    }
+    table.extend_from_slice(&[0, 0]);
+    table.extend_from_slice(&strings);
+    table.push(0);
+
+    let mut file = vec![0u8; TABLE];
+    file[..2].copy_from_slice(b"MZ");
+    put_u32(&mut file, 0x3c, HEADER as u32);
+    file[HEADER..HEADER + 2].copy_from_slice(b"NE");
+    put_u16(&mut file, HEADER + 0x24, (TABLE - HEADER) as u16);
+    put_u16(&mut file, HEADER + 0x26, (TABLE - HEADER + table.len()) as u16);
+    file.extend_from_slice(&table);
+
+    for (offset, bytes) in data {
+        file.resize(offset, 0);
+        file.extend_from_slice(bytes);
+    }
+    file
+}
+
+/// A directory under the system temporary directory, removed when dropped.
+pub struct TempDir(pub std::path::PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("windows-ext-icons-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    /// Writes `data` to `relative`, creating parent directories.
+    pub fn write(&self, relative: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = self.0.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A key to lay out in a synthetic registry hive.
+pub struct RegKey {
+    pub name: String,
+    pub values: Vec<(String, u32, Vec<u8>)>,
+    pub subkeys: Vec<RegKey>,
+}
+
+pub fn reg_key(name: &str, values: Vec<(&str, u32, Vec<u8>)>, subkeys: Vec<RegKey>) -> RegKey {
+    let values = values.into_iter().map(|(name, kind, data)| (name.to_string(), kind, data)).collect();
+    RegKey { name: name.to_string(), values, subkeys }
+}
+
+/// Encodes a `REG_SZ` or `REG_EXPAND_SZ` value as UTF-16LE with its terminator.
+pub fn reg_sz(text: &str) -> Vec<u8> {
+    text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+}
+
+const NO_CELL: u32 = 0xffff_ffff;
+const BIG_DATA_SEGMENT: usize = 16344;
+
+/// The cells of a single hive bin, addressed from the start of the bin.
+struct Cells(Vec<u8>);
+
+impl Cells {
+    fn push(&mut self, data: &[u8]) -> u32 {
+        let offset = self.0.len();
+        let size = (4 + data.len()).next_multiple_of(8);
+        self.0.extend_from_slice(&(-(size as i32)).to_le_bytes());
+        self.0.extend_from_slice(data);
+        self.0.resize(offset + size, 0);
+        offset as u32
+    }
+
+    /// Pushes a subkey list; `lf` and `lh` lists take the names of their keys for the hints.
+    fn push_list(&mut self, signature: &[u8; 2], entries: &[u32], names: &[&str]) -> u32 {
+        let mut list = signature.to_vec();
+        list.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (index, entry) in entries.iter().enumerate() {
+            list.extend_from_slice(&entry.to_le_bytes());
+            if let Some(name) = names.get(index) {
+                list.extend_from_slice(&subkey_hint(signature, name).to_le_bytes());
+            }
+        }
+        self.push(&list)
+    }
+}
+
+/// Returns the first four characters of a name for `lf` lists, or the hash of its uppercase for `lh` lists.
+pub fn subkey_hint(signature: &[u8; 2], name: &str) -> u32 {
+    let units = name.encode_utf16();
+    if signature == b"lh" {
+        let upper = |unit: u16| char::from_u32(u32::from(unit)).and_then(|c| c.to_uppercase().next()).map_or(u32::from(unit), u32::from);
+        return units.fold(0u32, |hash, unit| hash.wrapping_mul(37).wrapping_add(upper(unit)));
+    }
+    let mut prefix = [0; 4];
+    for (byte, unit) in prefix.iter_mut().zip(units) {
+        *byte = unit as u8;
+    }
+    u32::from_le_bytes(prefix)
+}
+
+/// Encodes a name in Latin-1 if it fits, returning the bytes and whether they are compressed.
+fn hive_name(name: &str) -> (Vec<u8>, bool) {
+    match name.chars().map(|c| u8::try_from(u32::from(c)).ok()).collect::<Option<Vec<u8>>>() {
+        Some(latin1) => (latin1, true),
+        None => (name.encode_utf16().flat_map(u16::to_le_bytes).collect(), false),
+    }
+}
+
+fn write_value(cells: &mut Cells, (name, kind, data): &(String, u32, Vec<u8>)) -> u32 {
+    let (size, offset) = if data.len() <= 4 {
+        let mut inline = [0; 4];
+        inline[..data.len()].copy_from_slice(data);
+        (0x8000_0000 | data.len() as u32, u32::from_le_bytes(inline))
+    } else if data.len() > BIG_DATA_SEGMENT {
+        let segments: Vec<u32> = data.chunks(BIG_DATA_SEGMENT).map(|chunk| cells.push(chunk)).collect();
+        let list = cells.push(&segments.iter().flat_map(|offset| offset.to_le_bytes()).collect::<Vec<u8>>());
+        let mut db = b"db".to_vec();
+        db.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+        db.extend_from_slice(&list.to_le_bytes());
+        (data.len() as u32, cells.push(&db))
+    } else {
+        (data.len() as u32, cells.push(data))
+    };
+
+    let (name, compressed) = hive_name(name);
+    let mut vk = vec![0u8; 0x14];
+    vk[..2].copy_from_slice(b"vk");
+    put_u16(&mut vk, 2, name.len() as u16);
+    put_u32(&mut vk, 4, size);
+    put_u32(&mut vk, 8, offset);
+    put_u32(&mut vk, 0xc, *kind);
+    put_u16(&mut vk, 0x10, u16::from(compressed));
+    vk.extend_from_slice(&name);
+    cells.push(&vk)
+}
+
+fn write_key(cells: &mut Cells, key: &RegKey, depth: usize) -> u32 {
+    let children: Vec<u32> = key.subkeys.iter().map(|subkey| write_key(cells, subkey, depth + 1)).collect();
+    let subkey_list = match children.len() {
+        0 => NO_CELL,
+        // Split long lists into an index of `li` lists, as hives do for large keys.
+        5.. => {
+            let lists: Vec<u32> = children.chunks(2).map(|chunk| cells.push_list(b"li", chunk, &[])).collect();
+            cells.push_list(b"ri", &lists, &[])
+        }
+        _ => {
+            let names: Vec<&str> = key.subkeys.iter().map(|subkey| subkey.name.as_str()).collect();
+            cells.push_list(if depth.is_multiple_of(2) { b"lf" } else { b"lh" }, &children, &names)
+        }
+    };
+
+    let values: Vec<u32> = key.values.iter().map(|value| write_value(cells, value)).collect();
+    let value_list = match values.len() {
+        0 => NO_CELL,
+        _ => cells.push(&values.iter().flat_map(|offset| offset.to_le_bytes()).collect::<Vec<u8>>()),
+    };
+
+    let (name, compressed) = hive_name(&key.name);
+    let mut nk = vec![0u8; 0x4c];
+    nk[..2].copy_from_slice(b"nk");
+    put_u16(&mut nk, 2, if compressed { 0x20 } else { 0 });
+    put_u32(&mut nk, 0x14, children.len() as u32);
+    put_u32(&mut nk, 0x1c, subkey_list);
+    put_u32(&mut nk, 0x20, NO_CELL);
+    put_u32(&mut nk, 0x24, values.len() as u32);
+    put_u32(&mut nk, 0x28, value_list);
+    put_u16(&mut nk, 0x48, name.len() as u16);
+    nk.extend_from_slice(&name);
+    cells.push(&nk)
+}
+
+/// Builds a `regf` hive holding `root` in a single bin.
+///
+/// Subkey lists alternate between `lf` and `lh` by depth, and keys with more
+/// than four subkeys get an `ri` index of `li` lists.
+pub fn hive_file(root: &RegKey) -> Vec<u8> {
+    let mut cells = Cells(vec![0; 0x20]);
+    let root = write_key(&mut cells, root, 0);
+    let mut bin = cells.0;
+    bin.resize(bin.len().next_multiple_of(4096), 0);
+    bin[..4].copy_from_slice(b"hbin");
+    let size = bin.len() as u32;
+    put_u32(&mut bin, 8, size);
+
+    let mut file = vec![0u8; 4096];
+    file[..4].copy_from_slice(b"regf");
+    put_u32(&mut file, 0x24, root);
+    put_u32(&mut file, 0x28, size);
+    file.extend_from_slice(&bin);
+    file
+}
@@ -0,0 +1,81 @@
+use windows_ext_icons::{convert, convert_in_place, IconError, PixelLayout};
+
+const FOUR_BYTE: [PixelLayout; 4] = [PixelLayout::Rgba, PixelLayout::Bgra, PixelLayout::Argb, PixelLayout::Abgr];
+
+fn encode(layout: PixelLayout, pixels: &[[u8; 4]]) -> Vec<u8> {
+    let mut out = vec![0; pixels.len() * layout.bytes_per_pixel()];
+    convert(pixels.concat().as_slice(), PixelLayout::Rgba, &mut out, layout).unwrap();
+    out
+}
+
+fn sample(count: usize) -> Vec<[u8; 4]> {
+    (0..count).map(|i| [i as u8, (i * 7) as u8, (i * 13) as u8, (i * 29) as u8]).collect()
+}
+
+#[test]
+fn reorders_channels() {
+    let rgba = [[1, 2, 3, 4]];
+    assert_eq!(encode(PixelLayout::Bgra, &rgba), [3, 2, 1, 4]);
+    assert_eq!(encode(PixelLayout::Argb, &rgba), [4, 1, 2, 3]);
+    assert_eq!(encode(PixelLayout::Abgr, &rgba), [4, 3, 2, 1]);
+    assert_eq!(encode(PixelLayout::Bgr, &rgba), [3, 2, 1]);
+}
+
+#[test]
+fn round_trips_every_four_byte_pair() {
+    // 37 pixels exercise the SIMD blocks and the scalar tail.
+    let pixels = sample(37);
+    for from in FOUR_BYTE {
+        for to in FOUR_BYTE {
+            let src = encode(from, &pixels);
+            let mut dst = vec![0; src.len()];
+            convert(&src, from, &mut dst, to).unwrap();
+            assert_eq!(dst, encode(to, &pixels), "{from:?} -> {to:?}");
+
+            let mut in_place = src.clone();
+            convert_in_place(&mut in_place, from, to).unwrap();
+            assert_eq!(in_place, dst, "{from:?} -> {to:?} in place");
+        }
+    }
+}
+
+#[test]
+fn widens_24_bit_with_opaque_alpha() {
+    let pixels: Vec<[u8; 4]> = sample(37).into_iter().map(|[r, g, b, _]| [r, g, b, 0xFF]).collect();
+    for from in [PixelLayout::Rgb, PixelLayout::Bgr] {
+        let src = encode(from, &pixels);
+        for to in FOUR_BYTE {
+            let mut dst = vec![0; pixels.len() * 4];
+            convert(&src, from, &mut dst, to).unwrap();
+            assert_eq!(dst, encode(to, &pixels), "{from:?} -> {to:?}");
+        }
+    }
+}
+
+#[test]
+fn expands_16_bit_layouts() {
+    let mut dst = [0; 8];
+    convert(&[0x00, 0xF8, 0xE0, 0x07], PixelLayout::Rgb565, &mut dst, PixelLayout::Rgba).unwrap();
+    assert_eq!(dst, [0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0xFF]);
+
+    let mut dst = [0; 4];
+    convert(&0x7C00u16.to_le_bytes(), PixelLayout::Rgb555, &mut dst, PixelLayout::Bgra).unwrap();
+    assert_eq!(dst, [0, 0, 0xFF, 0xFF]);
+}
+
+#[test]
+fn rejects_mismatched_buffers() {
+    let mut dst = [0; 4];
+    assert!(matches!(
+        convert(&[0; 6], PixelLayout::Rgba, &mut dst, PixelLayout::Bgra),
+        Err(IconError::BufferSize { expected: 4, actual: 6 })
+    ));
+    assert!(matches!(
+        convert(&[0; 8], PixelLayout::Rgba, &mut dst, PixelLayout::Bgra),
+        Err(IconError::BufferSize { expected: 8, actual: 4 })
+    ));
+    assert!(matches!(
+        convert_in_place(&mut [0; 4], PixelLayout::Rgba, PixelLayout::Rgb),
+        Err(IconError::IncompatibleLayouts { .. })
+    ));
+}
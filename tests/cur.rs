@@ -0,0 +1,43 @@
+use image::{Rgba, RgbaImage};
+use windows_ext_icons::{cur, ico, Cursor, IconError, Stage};
+
+fn arrow(edge: u32) -> RgbaImage {
+    RgbaImage::from_fn(edge, edge, |x, y| if x <= y { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+}
+
+#[test]
+fn round_trips_hotspots() {
+    let cursor = Cursor { images: vec![arrow(32), arrow(48), arrow(256)], hotspots: vec![(0, 0), (5, 7), (255, 128)] };
+    let parsed = cur::parse(&cur::encode(&cursor).unwrap()).unwrap();
+    assert_eq!(parsed, cursor);
+}
+
+#[test]
+fn writes_cursor_resource_type() {
+    let file = cur::encode(&Cursor { images: vec![arrow(16)], hotspots: vec![(3, 4)] }).unwrap();
+    assert_eq!(file[..6], [0, 0, 2, 0, 1, 0]);
+    assert_eq!(file[10..14], [3, 0, 4, 0]);
+    assert!(matches!(ico::parse(&file), Err(IconError::Malformed { stage: Stage::IconDirectory })));
+}
+
+#[test]
+fn rejects_inconsistent_hotspots() {
+    let missing = Cursor { images: vec![arrow(16), arrow(32)], hotspots: vec![(0, 0)] };
+    assert!(matches!(cur::encode(&missing), Err(IconError::HotspotCount { images: 2, hotspots: 1 })));
+
+    let outside = Cursor { images: vec![arrow(16)], hotspots: vec![(16, 0)] };
+    assert!(matches!(
+        cur::encode(&outside),
+        Err(IconError::InvalidHotspot { hotspot: (16, 0), width: 16, height: 16 })
+    ));
+}
+
+#[test]
+fn round_trips_through_disk() {
+    let path = std::env::temp_dir().join(format!("windows-ext-icons-{}.cur", std::process::id()));
+    let cursor = Cursor { images: vec![arrow(24)], hotspots: vec![(12, 2)] };
+    cur::write(&path, &cursor).unwrap();
+    let read = cur::read(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.unwrap(), cursor);
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{icon_resources, pe_file, solid, TempDir};
+use std::collections::HashMap;
+use windows_ext_icons::ico::{self, EncodeOptions};
+use windows_ext_icons::{
+    DesktopIni, FolderIconProvider, IconError, IconFile, IconLocation, IconProvider, IconSize, MemoryIconProvider, ResourceId, Stage,
+    WindowsTree,
+};
+
+const GENERIC: [u8; 4] = [128, 128, 0, 255];
+const CUSTOM: [u8; 4] = [0, 64, 255, 255];
+const SYSTEM: [u8; 4] = [200, 0, 200, 255];
+
+fn utf16_with_bom(text: &str) -> Vec<u8> {
+    [0xff, 0xfe].into_iter().chain(text.encode_utf16().flat_map(u16::to_le_bytes)).collect()
+}
+
+fn location(ini: &DesktopIni) -> Option<IconLocation> {
+    let environment = HashMap::from([("SystemRoot".to_string(), "C:\\Windows".to_string())]);
+    ini.icon_location(&environment).unwrap()
+}
+
+#[test]
+fn prefers_icon_resource() {
+    let ini = DesktopIni::parse(
+        b"[.ShellClassInfo]\r\nIconFile=old.ico\r\nIconIndex=2\r\nIconResource=%SystemRoot%\\System32\\imageres.dll,-3\r\n",
+    );
+    assert_eq!(
+        location(&ini),
+        Some(IconLocation { file: IconFile::Path("C:\\Windows\\System32\\imageres.dll".into()), index: -3 })
+    );
+}
+
+#[test]
+fn reads_icon_file_and_index() {
+    let ini = DesktopIni::parse(b"; comment\n[ViewState]\nIconFile=wrong.ico\n[.shellclassinfo]\n iconfile = \"folder icon.ico\" \nIconIndex=4\n");
+    assert_eq!(ini.icon_file.as_deref(), Some("folder icon.ico"));
+    assert_eq!(location(&ini), Some(IconLocation { file: IconFile::Path("folder icon.ico".into()), index: 4 }));
+}
+
+#[test]
+fn reads_utf16_files() {
+    let ini = DesktopIni::parse(&utf16_with_bom("[.ShellClassInfo]\r\nIconResource=Ícones\\pasta.ico,0\r\n"));
+    assert_eq!(ini.icon_resource.as_deref(), Some("Ícones\\pasta.ico,0"));
+}
+
+#[test]
+fn yields_nothing_without_icon_settings() {
+    assert_eq!(location(&DesktopIni::parse(b"[.ShellClassInfo]\nConfirmFileOp=0\n")), None);
+    assert_eq!(location(&DesktopIni::parse(b"IconResource=stray.ico\n")), None);
+    assert_eq!(DesktopIni::parse(b"[.ShellClassInfo]\nIconFile=a.ico\nIconIndex=x\n").icon_index, 0);
+}
+
+#[test]
+fn provider_consults_desktop_ini_before_generic_icon() {
+    let dir = TempDir::new("desktop-ini");
+    dir.write("Windows/System32/shell32.dll", &pe_file(&icon_resources(&[(ResourceId::Id(4), vec![solid(32, SYSTEM)])]), true));
+    dir.write("data/custom/Icons/Folder.ico", &ico::encode(&[solid(32, CUSTOM)], &EncodeOptions::default()).unwrap());
+    dir.write("data/custom/Desktop.ini", &utf16_with_bom("[.ShellClassInfo]\r\nIconResource=icons\\folder.ico,0\r\n"));
+    dir.write("data/system/desktop.ini", b"[.ShellClassInfo]\r\nIconFile=%SystemRoot%\\System32\\shell32.dll\r\nIconIndex=-4\r\n");
+    dir.write("data/broken/desktop.ini", b"[.ShellClassInfo]\r\nIconResource=missing.ico\r\n");
+    std::fs::create_dir_all(dir.0.join("data/plain")).unwrap();
+
+    let mut generic = MemoryIconProvider::new();
+    for folder in ["custom", "system", "broken", "plain"] {
+        generic.insert_path(dir.0.join("data").join(folder), solid(32, GENERIC));
+    }
+    let provider = FolderIconProvider::new(generic, Some(WindowsTree::new(&dir.0)));
+
+    let color = |folder: &str| provider.icon_for_path(&dir.0.join("data").join(folder), IconSize::Large).unwrap().get_pixel(0, 0).0;
+    assert_eq!(color("custom"), CUSTOM);
+    assert_eq!(color("system"), SYSTEM);
+    assert_eq!(color("broken"), GENERIC);
+    assert_eq!(color("plain"), GENERIC);
+
+    assert!(provider.folder_icon(&dir.0.join("data/plain"), IconSize::Large).unwrap().is_none());
+}
+
+#[test]
+fn refuses_icons_outside_the_folder() {
+    let dir = TempDir::new("desktop-ini-escape");
+    dir.write("outside.ico", &ico::encode(&[solid(32, CUSTOM)], &EncodeOptions::default()).unwrap());
+    dir.write("data/escape/desktop.ini", b"[.ShellClassInfo]\r\nIconResource=..\\..\\outside.ico,0\r\n");
+
+    let mut generic = MemoryIconProvider::new();
+    generic.insert_path(dir.0.join("data/escape"), solid(32, GENERIC));
+    let provider = FolderIconProvider::new(generic, Some(WindowsTree::new(&dir.0)));
+
+    assert!(matches!(
+        provider.folder_icon(&dir.0.join("data/escape"), IconSize::Large),
+        Err(IconError::Malformed { stage: Stage::IconLocation })
+    ));
+    assert_eq!(provider.icon_for_path(&dir.0.join("data/escape"), IconSize::Large).unwrap().get_pixel(0, 0).0, GENERIC);
+}
@@ -0,0 +1,153 @@
+use windows_ext_icons::dib::{decode, decode_packed, DibHeader, BI_BITFIELDS, BI_RGB};
+use windows_ext_icons::{IconError, Stage};
+
+fn info_header(size: u32, width: i32, height: i32, bit_count: u16, compression: u32) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&size.to_le_bytes());
+    header.extend_from_slice(&width.to_le_bytes());
+    header.extend_from_slice(&height.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&bit_count.to_le_bytes());
+    header.extend_from_slice(&compression.to_le_bytes());
+    header.resize(size as usize, 0);
+    header
+}
+
+fn pixels(image: &image::RgbaImage) -> Vec<[u8; 4]> {
+    image.pixels().map(|pixel| pixel.0).collect()
+}
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+#[test]
+fn decodes_bottom_up_1bpp() {
+    let mut header = info_header(40, 3, 2, 1, BI_RGB);
+    header.extend_from_slice(&[0, 0, 0, 0, 255, 255, 255, 0]);
+    // Bottom row first: 1,0,1 then 0,1,0, each row padded to 4 bytes.
+    let bits = [0b1010_0000, 0, 0, 0, 0b0100_0000, 0, 0, 0];
+
+    let image = decode(&header, &bits).unwrap();
+    assert_eq!(image.dimensions(), (3, 2));
+    assert_eq!(pixels(&image), [BLACK, WHITE, BLACK, WHITE, BLACK, WHITE]);
+}
+
+#[test]
+fn decodes_4bpp_and_8bpp_palettes() {
+    let palette = [0, 0, 255, 0, 0, 255, 0, 0, 255, 0, 0, 0];
+
+    let mut header = info_header(40, 3, -1, 4, BI_RGB);
+    header[32..36].copy_from_slice(&3u32.to_le_bytes());
+    header.extend_from_slice(&palette);
+    let image = decode(&header, &[0x01, 0x20, 0, 0]).unwrap();
+    assert_eq!(pixels(&image), [RED, GREEN, BLUE]);
+
+    let mut header = info_header(40, 3, -1, 8, BI_RGB);
+    header[32..36].copy_from_slice(&3u32.to_le_bytes());
+    header.extend_from_slice(&palette);
+    let image = decode(&header, &[2, 1, 0, 0]).unwrap();
+    assert_eq!(pixels(&image), [BLUE, GREEN, RED]);
+}
+
+#[test]
+fn decodes_core_header_palette() {
+    let mut header = vec![12, 0, 0, 0, 2, 0, 1, 0, 1, 0, 1, 0];
+    header.extend_from_slice(&[0, 0, 255, 255, 0, 0]);
+    let image = decode(&header, &[0b0100_0000, 0, 0, 0]).unwrap();
+    assert_eq!(pixels(&image), [RED, BLUE]);
+}
+
+#[test]
+fn decodes_16bpp_default_and_bitfields() {
+    let header = info_header(40, 2, -1, 16, BI_RGB);
+    let bits = [0x00, 0x7C, 0x1F, 0x00];
+    assert_eq!(pixels(&decode(&header, &bits).unwrap()), [RED, BLUE]);
+
+    let mut header = info_header(40, 2, -1, 16, BI_BITFIELDS);
+    for mask in [0xF800u32, 0x07E0, 0x001F] {
+        header.extend_from_slice(&mask.to_le_bytes());
+    }
+    let bits = [0xE0, 0x07, 0x00, 0xF8];
+    assert_eq!(pixels(&decode(&header, &bits).unwrap()), [GREEN, RED]);
+
+    // 4-4-4-4 masks exercise the generic mask path.
+    let mut header = info_header(56, 1, -1, 16, BI_BITFIELDS);
+    for (i, mask) in [0x0F00u32, 0x00F0, 0x000F, 0xF000].iter().enumerate() {
+        header[40 + i * 4..44 + i * 4].copy_from_slice(&mask.to_le_bytes());
+    }
+    let bits = [0x0F, 0x8F, 0, 0];
+    assert_eq!(pixels(&decode(&header, &bits).unwrap()), [[255, 0, 255, 136]]);
+}
+
+#[test]
+fn decodes_24bpp_with_row_padding() {
+    let header = info_header(40, 1, 2, 24, BI_RGB);
+    let bits = [255, 0, 0, 0, 0, 0, 255, 0];
+    assert_eq!(pixels(&decode(&header, &bits).unwrap()), [RED, BLUE]);
+}
+
+#[test]
+fn decodes_32bpp_alpha_top_down() {
+    let header = info_header(40, 2, -1, 32, BI_RGB);
+    let bits = [255, 0, 0, 128, 0, 0, 255, 64];
+    assert_eq!(pixels(&decode(&header, &bits).unwrap()), [[0, 0, 255, 128], [255, 0, 0, 64]]);
+}
+
+#[test]
+fn decodes_v5_header_with_masks() {
+    let mut header = info_header(124, 1, 1, 32, BI_BITFIELDS);
+    for (i, mask) in [0x0000_00FFu32, 0x0000_FF00, 0x00FF_0000, 0xFF00_0000].iter().enumerate() {
+        header[40 + i * 4..44 + i * 4].copy_from_slice(&mask.to_le_bytes());
+    }
+    let image = decode(&header, &[10, 20, 30, 40]).unwrap();
+    assert_eq!(pixels(&image), [[10, 20, 30, 40]]);
+}
+
+#[test]
+fn decodes_packed_dib() {
+    let mut data = info_header(40, 1, 1, 8, BI_RGB);
+    data[32..36].copy_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 255, 0, 0]);
+    data.extend_from_slice(&[1, 0, 0, 0]);
+
+    let info = DibHeader::parse(&data).unwrap();
+    assert_eq!(info.bits_offset(), Some(48));
+    assert_eq!(pixels(&decode_packed(&data).unwrap()), [GREEN]);
+}
+
+#[test]
+fn rejects_truncated_and_unsupported_data() {
+    let header = info_header(40, 4, 4, 32, BI_RGB);
+    assert!(matches!(
+        decode(&header, &[0; 60]),
+        Err(IconError::Truncated { stage: Stage::PixelData })
+    ));
+    assert!(matches!(
+        decode(&header[..20], &[0; 64]),
+        Err(IconError::Truncated { stage: Stage::BitmapHeader })
+    ));
+    assert!(matches!(
+        decode(&info_header(40, 1, 1, 8, 1), &[0; 4]),
+        Err(IconError::UnsupportedCompression(1))
+    ));
+    assert!(matches!(
+        decode(&info_header(40, 1, 1, 2, BI_RGB), &[0; 4]),
+        Err(IconError::UnsupportedBitDepth(2))
+    ));
+    assert!(matches!(
+        decode(&info_header(40, 0, 1, 32, BI_RGB), &[0; 4]),
+        Err(IconError::InvalidDimensions { width: 0, height: 1 })
+    ));
+
+    // A colour table far larger than the data, as a hostile biClrUsed claims.
+    let mut huge_palette = info_header(40, 1, 1, 32, BI_RGB);
+    huge_palette[32..36].copy_from_slice(&0x4000_0000u32.to_le_bytes());
+    huge_palette.extend_from_slice(&[0; 4]);
+    assert!(matches!(
+        decode_packed(&huge_palette),
+        Err(IconError::Truncated { stage: Stage::PixelData })
+    ));
+}
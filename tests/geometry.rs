@@ -0,0 +1,59 @@
+use windows_ext_icons::dib::DibHeader;
+use windows_ext_icons::{BitmapGeometry, IconError, Stage};
+
+fn geometry(width: i32, height: i32, bits_per_pixel: u16, stride: usize) -> BitmapGeometry {
+    BitmapGeometry { width, height, bits_per_pixel, stride }
+}
+
+#[test]
+fn accepts_consistent_geometry() {
+    assert_eq!(geometry(48, 48, 32, 192).validate_data(192 * 48).unwrap(), (48, 48));
+    assert_eq!(geometry(33, -7, 1, 8).validate_data(56).unwrap(), (33, 7));
+    // DDBs pad rows to two bytes, so a tight 24-bpp stride is valid.
+    assert_eq!(geometry(3, 2, 24, 10).validate().unwrap(), (3, 2));
+}
+
+#[test]
+fn takes_size_from_header_rather_than_hotspot() {
+    let mut header = vec![0; 40];
+    header[0..4].copy_from_slice(&40u32.to_le_bytes());
+    header[4..8].copy_from_slice(&37i32.to_le_bytes());
+    header[8..12].copy_from_slice(&(-21i32).to_le_bytes());
+    header[14..16].copy_from_slice(&4u16.to_le_bytes());
+
+    let geometry = BitmapGeometry::from_header(&DibHeader::parse(&header).unwrap());
+    assert_eq!(geometry.stride, 20);
+    assert_eq!(geometry.validate_data(20 * 21).unwrap(), (37, 21));
+}
+
+#[test]
+fn builds_tight_top_down_geometry() {
+    let geometry = BitmapGeometry::top_down(5, 3, 32);
+    assert_eq!(geometry, BitmapGeometry { width: 5, height: -3, bits_per_pixel: 32, stride: 20 });
+    assert_eq!(geometry.data_len(), Some(60));
+}
+
+#[test]
+fn rejects_inconsistent_geometry() {
+    assert!(matches!(
+        geometry(0, 16, 32, 0).validate(),
+        Err(IconError::InvalidDimensions { width: 0, height: 16 })
+    ));
+    assert!(matches!(
+        geometry(16, 0, 32, 64).validate(),
+        Err(IconError::InvalidDimensions { width: 16, height: 0 })
+    ));
+    assert!(matches!(geometry(16, 16, 12, 64).validate(), Err(IconError::UnsupportedBitDepth(12))));
+    assert!(matches!(
+        geometry(16, 16, 32, 60).validate(),
+        Err(IconError::InvalidStride { stride: 60, minimum: 64 })
+    ));
+    assert!(matches!(
+        geometry(16, 16, 32, 64).validate_data(64 * 15),
+        Err(IconError::Truncated { stage: Stage::PixelData })
+    ));
+    assert!(matches!(
+        geometry(i32::MAX, i32::MAX, 32, usize::MAX).validate(),
+        Err(IconError::InvalidDimensions { .. })
+    ));
+}
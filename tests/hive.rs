@@ -0,0 +1,109 @@
+mod common;
+
+use common::{hive_file, reg_key, reg_sz, subkey_hint};
+use windows_ext_icons::registry::{REG_BINARY, REG_DWORD, REG_EXPAND_SZ, REG_MULTI_SZ, REG_QWORD, REG_SZ};
+use windows_ext_icons::{Hive, IconError, RegValue, Registry, Stage};
+
+fn sample() -> Vec<u8> {
+    let many = (0..7).map(|index| reg_key(&format!("Sub{index}"), vec![("", REG_SZ, reg_sz(&index.to_string()))], vec![])).collect();
+    hive_file(&reg_key(
+        "ROOT",
+        vec![],
+        vec![
+            reg_key(
+                "Software",
+                vec![],
+                vec![reg_key(
+                    "Vendor",
+                    vec![
+                        ("", REG_SZ, reg_sz("default")),
+                        ("Path", REG_EXPAND_SZ, reg_sz("%ProgramFiles%\\Vendor")),
+                        ("Count", REG_DWORD, 7u32.to_le_bytes().to_vec()),
+                        ("Big", REG_QWORD, u64::MAX.to_le_bytes().to_vec()),
+                        ("List", REG_MULTI_SZ, reg_sz("one\0two\0")),
+                        ("Tiny", REG_BINARY, vec![1, 2]),
+                        ("Blob", REG_BINARY, (0..40000u32).map(|index| index as u8).collect()),
+                        ("Ünïcode ☃", REG_SZ, reg_sz("snowman")),
+                    ],
+                    vec![reg_key("Ключ", vec![], vec![])],
+                )],
+            ),
+            reg_key("Many", vec![], many),
+        ],
+    ))
+}
+
+#[test]
+fn reads_values_of_each_type() {
+    let data = sample();
+    let hive = Hive::parse(&data).unwrap();
+    let value = |name| hive.value("software\\VENDOR", name).unwrap();
+
+    assert_eq!(value(""), Some(RegValue::String("default".into())));
+    assert_eq!(value("path"), Some(RegValue::ExpandString("%ProgramFiles%\\Vendor".into())));
+    assert_eq!(value("Count"), Some(RegValue::Dword(7)));
+    assert_eq!(value("Big"), Some(RegValue::Qword(u64::MAX)));
+    assert_eq!(value("List"), Some(RegValue::MultiString(vec!["one".into(), "two".into()])));
+    assert_eq!(value("Tiny"), Some(RegValue::Binary(vec![1, 2])));
+    assert_eq!(value("Blob"), Some(RegValue::Binary((0..40000u32).map(|index| index as u8).collect())));
+    assert_eq!(value("ÜNÏCODE ☃"), Some(RegValue::String("snowman".into())));
+    assert_eq!(value("Missing"), None);
+    assert_eq!(hive.string("Software\\Vendor", "Count").unwrap(), None);
+}
+
+#[test]
+fn walks_keys() {
+    let data = sample();
+    let hive = Hive::parse(&data).unwrap();
+
+    assert_eq!(hive.root().unwrap().name(), "ROOT");
+    assert_eq!(hive.subkey_names("").unwrap(), Some(vec!["Software".into(), "Many".into()]));
+    assert_eq!(hive.subkey_names("Software\\Vendor").unwrap(), Some(vec!["Ключ".into()]));
+    assert!(hive.key_exists("\\software\\vendor\\ключ\\").unwrap());
+    assert_eq!(hive.subkey_names("Software\\Other").unwrap(), None);
+    assert_eq!(hive.value("Software\\Other", "").unwrap(), None);
+
+    let names: Vec<String> = (0..7).map(|index| format!("Sub{index}")).collect();
+    assert_eq!(hive.subkey_names("Many").unwrap(), Some(names));
+    assert_eq!(hive.string("Many\\sub6", "").unwrap().as_deref(), Some("6"));
+    assert_eq!(hive.key("Many").unwrap().unwrap().value_names().unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn skips_subkeys_whose_hints_differ() {
+    let data = sample();
+    let hash = subkey_hint(b"lh", "Vendor").to_le_bytes();
+    let at = data.windows(4).position(|window| window == hash).unwrap();
+
+    let mut data = data;
+    data[at..at + 4].copy_from_slice(&subkey_hint(b"lh", "Other").to_le_bytes());
+    let hive = Hive::parse(&data).unwrap();
+    assert!(!hive.key_exists("Software\\Vendor").unwrap());
+    assert_eq!(hive.subkey_names("Software").unwrap(), Some(vec!["Vendor".into()]));
+}
+
+#[test]
+fn rejects_damaged_hives() {
+    let mut data = sample();
+    assert!(matches!(Hive::parse(b"regg"), Err(IconError::Malformed { stage: Stage::RegistryHive })));
+    assert!(matches!(Hive::parse(&data[..0x20]), Err(IconError::Truncated { stage: Stage::RegistryHive })));
+
+    let mut wrapping = data.clone();
+    wrapping[0x24..0x28].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+    assert!(matches!(Hive::parse(&wrapping), Err(IconError::Truncated { stage: Stage::RegistryHive })));
+
+    // Value sizes larger than their segments or inline field could hold.
+    let vk_size = |name: &[u8]| data.windows(name.len()).position(|window| window == name).unwrap() - 0x14 + 4;
+    for (name, size) in [(&b"Blob"[..], 0x7fff_fff0u32), (b"Tiny", 0x8000_0010)] {
+        let mut oversized = data.clone();
+        let at = vk_size(name);
+        oversized[at..at + 4].copy_from_slice(&size.to_le_bytes());
+        let hive = Hive::parse(&oversized).unwrap();
+        let name = std::str::from_utf8(name).unwrap();
+        assert!(matches!(hive.value("Software\\Vendor", name), Err(IconError::Malformed { stage: Stage::RegistryHive })));
+    }
+
+    let root = u32::from_le_bytes(data[0x24..0x28].try_into().unwrap()) as usize;
+    data[4096 + root + 4..][..2].copy_from_slice(b"xx");
+    assert!(matches!(Hive::parse(&data), Err(IconError::Malformed { stage: Stage::RegistryHive })));
+}
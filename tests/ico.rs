@@ -0,0 +1,230 @@
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+use windows_ext_icons::ico::{self, EncodeOptions, EntryFormat};
+use windows_ext_icons::{IconError, IconSize, Stage};
+
+fn dib_header(width: i32, height: i32, bit_count: u16) -> Vec<u8> {
+    let mut header = vec![0; 40];
+    header[0..4].copy_from_slice(&40u32.to_le_bytes());
+    header[4..8].copy_from_slice(&width.to_le_bytes());
+    header[8..12].copy_from_slice(&(height * 2).to_le_bytes());
+    header[12..14].copy_from_slice(&1u16.to_le_bytes());
+    header[14..16].copy_from_slice(&bit_count.to_le_bytes());
+    header
+}
+
+/// 2x2, 4-bpp: red/blue over green/green, with the top-right pixel masked out.
+fn paletted_entry() -> Vec<u8> {
+    let mut data = dib_header(2, 2, 4);
+    let mut palette = vec![0; 64];
+    palette[0..4].copy_from_slice(&[0, 0, 255, 0]);
+    palette[4..8].copy_from_slice(&[0, 255, 0, 0]);
+    palette[8..12].copy_from_slice(&[255, 0, 0, 0]);
+    data.extend_from_slice(&palette);
+    // Bottom-up colour rows, then bottom-up mask rows.
+    data.extend_from_slice(&[0x11, 0, 0, 0, 0x02, 0, 0, 0]);
+    data.extend_from_slice(&[0, 0, 0, 0, 0b0100_0000, 0, 0, 0]);
+    data
+}
+
+/// 1x1, 32-bpp with its own alpha and no mask.
+fn alpha_entry() -> Vec<u8> {
+    let mut data = dib_header(1, 1, 32);
+    data.extend_from_slice(&[30, 20, 10, 128]);
+    data
+}
+
+fn png_entry() -> Vec<u8> {
+    png_file(RgbaImage::from_pixel(3, 3, Rgba([1, 2, 3, 4])).into())
+}
+
+fn png_file(image: DynamicImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+    png
+}
+
+fn build_ico(entries: &[(u8, u16, Vec<u8>)]) -> Vec<u8> {
+    let mut file = vec![0, 0, 1, 0];
+    file.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    let mut offset = 6 + entries.len() * 16;
+    for (dimension, bit_count, data) in entries {
+        file.extend_from_slice(&[*dimension, *dimension, 0, 0]);
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&bit_count.to_le_bytes());
+        file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += data.len();
+    }
+    for (_, _, data) in entries {
+        file.extend_from_slice(data);
+    }
+    file
+}
+
+fn sample_ico() -> Vec<u8> {
+    build_ico(&[(2, 4, paletted_entry()), (1, 0, alpha_entry()), (3, 32, png_entry())])
+}
+
+#[test]
+fn decodes_every_entry() {
+    let entries = ico::parse(&sample_ico()).unwrap();
+    assert_eq!(entries.len(), 3);
+
+    let paletted = &entries[0];
+    assert_eq!((paletted.width, paletted.height, paletted.bit_depth), (2, 2, 4));
+    assert_eq!(paletted.format, EntryFormat::Bmp);
+    let pixels: Vec<[u8; 4]> = paletted.image.pixels().map(|pixel| pixel.0).collect();
+    assert_eq!(pixels, [[255, 0, 0, 255], [0, 0, 255, 0], [0, 255, 0, 255], [0, 255, 0, 255]]);
+
+    let alpha = &entries[1];
+    assert_eq!(alpha.bit_depth, 32);
+    assert_eq!(alpha.image.get_pixel(0, 0).0, [10, 20, 30, 128]);
+
+    let png = &entries[2];
+    assert_eq!(png.format, EntryFormat::Png);
+    assert_eq!(png.image.dimensions(), (3, 3));
+    assert_eq!(png.image.get_pixel(2, 2).0, [1, 2, 3, 4]);
+}
+
+#[test]
+fn takes_png_bit_depth_from_its_header() {
+    let rgb = png_file(DynamicImage::ImageRgb8(image::RgbImage::new(2, 2)));
+    let grey = png_file(DynamicImage::ImageLuma8(image::GrayImage::new(2, 2)));
+    let entries = ico::parse(&build_ico(&[(2, 0, rgb), (2, 0, grey), (3, 0, png_entry())])).unwrap();
+
+    let depths: Vec<u16> = entries.iter().map(|entry| entry.bit_depth).collect();
+    assert_eq!(depths, [24, 8, 32]);
+    assert_eq!(ico::select_entry(&entries, 2).unwrap().bit_depth, 24);
+}
+
+#[test]
+fn reads_zero_dimension_as_256() {
+    let entries = ico::parse(&build_ico(&[(0, 32, png_entry())])).unwrap();
+    assert_eq!((entries[0].width, entries[0].height), (256, 256));
+}
+
+#[test]
+fn selects_nearest_entry_at_or_above_size() {
+    let entries = ico::parse(&sample_ico()).unwrap();
+    assert_eq!(ico::select_entry(&entries, 2).unwrap().bit_depth, 4);
+    assert_eq!(ico::select_entry(&entries, 1).unwrap().image.width(), 1);
+    assert_eq!(ico::select_entry(&entries, 64).unwrap().format, EntryFormat::Png);
+}
+
+#[test]
+fn loads_from_disk_at_requested_size() {
+    let path = std::env::temp_dir().join(format!("windows-ext-icons-{}.ico", std::process::id()));
+    std::fs::write(&path, sample_ico()).unwrap();
+
+    let image = ico::load_icon_as_image(&path, IconSize::Pixels(6));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(image.unwrap().dimensions(), (6, 6));
+
+    assert!(matches!(
+        ico::load_icon_as_image(&path, IconSize::Large),
+        Err(IconError::Io { .. })
+    ));
+}
+
+#[test]
+fn rejects_malformed_files() {
+    assert!(matches!(
+        ico::parse(&[0, 0, 2, 0, 0, 0]),
+        Err(IconError::Malformed { stage: Stage::IconDirectory })
+    ));
+    assert!(matches!(
+        ico::parse(&[0, 0, 1, 0, 1, 0]),
+        Err(IconError::Truncated { stage: Stage::IconDirectory })
+    ));
+
+    let mut file = sample_ico();
+    file.truncate(file.len() - 10);
+    assert!(matches!(ico::parse(&file), Err(IconError::Truncated { stage: Stage::IconEntry })));
+}
+
+fn gradient(edge: u32) -> RgbaImage {
+    RgbaImage::from_fn(edge, edge, |x, y| {
+        let alpha = if x == 0 { 0 } else { 255 };
+        Rgba([(x * 255 / edge) as u8, (y * 255 / edge) as u8, 0x40, alpha])
+    })
+}
+
+#[test]
+fn round_trips_png_and_bmp_entries() {
+    let images = [gradient(16), gradient(256)];
+    let entries = ico::parse(&ico::encode(&images, &EncodeOptions::default()).unwrap()).unwrap();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!((entries[0].format, entries[0].bit_depth), (EntryFormat::Bmp, 32));
+    assert_eq!((entries[1].format, entries[1].width), (EntryFormat::Png, 256));
+    assert_eq!(entries[0].image, images[0]);
+    assert_eq!(entries[1].image, images[1]);
+}
+
+#[test]
+fn formats_select_each_entry() {
+    let options = EncodeOptions { formats: vec![EntryFormat::Png, EntryFormat::Bmp], ..EncodeOptions::default() };
+    let images = [gradient(8), gradient(256), gradient(16), gradient(256)];
+    let entries = ico::parse(&ico::encode(&images, &options).unwrap()).unwrap();
+
+    let formats: Vec<EntryFormat> = entries.iter().map(|entry| entry.format).collect();
+    assert_eq!(formats, [EntryFormat::Png, EntryFormat::Bmp, EntryFormat::Bmp, EntryFormat::Png]);
+    for (entry, image) in entries.iter().zip(&images) {
+        assert_eq!(&entry.image, image);
+    }
+}
+
+#[test]
+fn writes_and_mask_for_32bpp_entries() {
+    let file = ico::encode(&[gradient(8)], &EncodeOptions::default()).unwrap();
+    let data = &file[6 + 16..];
+    let mask = &data[40 + 8 * 8 * 4..];
+    assert_eq!(mask.len(), 8 * 4);
+    assert!(mask.chunks(4).all(|row| row == [0x80, 0, 0, 0]));
+}
+
+#[test]
+fn emits_paletted_variants() {
+    let options = EncodeOptions { paletted_8bpp: true, paletted_4bpp: true, ..EncodeOptions::default() };
+    let image = RgbaImage::from_fn(4, 4, |x, _| match x {
+        0 => Rgba([0, 0, 0, 0]),
+        1 => Rgba([255, 0, 0, 255]),
+        2 => Rgba([0, 255, 0, 200]),
+        _ => Rgba([0, 0, 255, 255]),
+    });
+    let entries = ico::parse(&ico::encode(&[image], &options).unwrap()).unwrap();
+
+    let depths: Vec<u16> = entries.iter().map(|entry| entry.bit_depth).collect();
+    assert_eq!(depths, [32, 8, 4]);
+    for entry in &entries[1..] {
+        assert_eq!(entry.format, EntryFormat::Bmp);
+        let row: Vec<[u8; 4]> = (0..4).map(|x| entry.image.get_pixel(x, 1).0).collect();
+        assert_eq!(row, [[0, 0, 0, 0], [255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]);
+    }
+}
+
+#[test]
+fn reduces_many_colours_to_palette() {
+    let options = EncodeOptions { paletted_4bpp: true, ..EncodeOptions::default() };
+    let entries = ico::parse(&ico::encode(&[gradient(32)], &options).unwrap()).unwrap();
+    let paletted = &entries[1];
+
+    assert_eq!(paletted.bit_depth, 4);
+    let mut colors: Vec<[u8; 4]> = paletted.image.pixels().map(|pixel| pixel.0).collect();
+    colors.sort_unstable();
+    colors.dedup();
+    assert!(colors.len() <= 16);
+    // Colours stay close to the source despite the reduction.
+    for (source, reduced) in gradient(32).pixels().zip(paletted.image.pixels()).filter(|(source, _)| source[3] != 0) {
+        assert!(source.0.iter().zip(reduced.0).all(|(&a, b)| a.abs_diff(b) <= 64), "{source:?} -> {reduced:?}");
+    }
+}
+
+#[test]
+fn rejects_oversized_images() {
+    assert!(matches!(
+        ico::encode(&[RgbaImage::new(257, 16)], &EncodeOptions::default()),
+        Err(IconError::InvalidDimensions { width: 257, height: 16 })
+    ));
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{icon_resources, pe_file, solid, TempDir};
+use std::collections::HashMap;
+use windows_ext_icons::ico::{self, EncodeOptions};
+use windows_ext_icons::{IconError, IconFile, IconLocation, IconSize, InternetShortcut, InternetShortcutMetadata, ResourceId, WindowsTree};
+
+fn location(shortcut: &InternetShortcut) -> Option<IconLocation> {
+    shortcut.icon_location(&HashMap::from([("SystemRoot".to_string(), "C:\\Windows".to_string())]))
+}
+
+#[test]
+fn reads_url_and_icon() {
+    let shortcut = InternetShortcut::parse(
+        b"[{000214A0-0000-0000-C000-000000000046}]\r\nProp3=19,11\r\n[InternetShortcut]\r\nIDList=\r\nURL=https://example.com/\r\nIconFile=C:\\Users\\me\\AppData\\favicon.ico\r\nIconIndex=1\r\n",
+    );
+    assert_eq!(shortcut.url.as_deref(), Some("https://example.com/"));
+    assert_eq!(
+        location(&shortcut),
+        Some(IconLocation { file: IconFile::Path("C:\\Users\\me\\AppData\\favicon.ico".into()), index: 1 })
+    );
+}
+
+#[test]
+fn converts_icon_urls() {
+    let cases = [
+        ("file:///C:/Icons/My%20Site.ico", Some("C:\\Icons\\My Site.ico")),
+        ("FILE://server/share/site.ico", Some("\\\\server\\share\\site.ico")),
+        ("file:///C:/100%25/bad%zz.ico", Some("C:\\100%\\bad%zz.ico")),
+        ("%SystemRoot%\\System32\\url.dll", Some("C:\\Windows\\System32\\url.dll")),
+        ("https://example.com/favicon.ico", None),
+    ];
+    for (icon_file, expected) in cases {
+        let shortcut = InternetShortcut { icon_file: Some(icon_file.into()), ..InternetShortcut::default() };
+        let path = location(&shortcut).map(|location| location.path("").to_string());
+        assert_eq!(path.as_deref(), expected, "{icon_file}");
+    }
+    assert_eq!(location(&InternetShortcut::parse(b"[InternetShortcut]\nURL=https://example.com/\n")), None);
+}
+
+#[test]
+fn decodes_local_icons() {
+    let dir = TempDir::new("url");
+    dir.write("Windows/System32/url.dll", &pe_file(&icon_resources(&[
+        (ResourceId::Id(1), vec![solid(32, [1, 0, 0, 255])]),
+        (ResourceId::Id(2), vec![solid(32, [2, 0, 0, 255])]),
+    ]), false));
+    dir.write("links/favicon.ico", &ico::encode(&[solid(16, [3, 0, 0, 255])], &EncodeOptions::default()).unwrap());
+    let tree = WindowsTree::new(&dir.0);
+    let links = dir.0.join("links");
+
+    let color = |text: &[u8]| {
+        InternetShortcut::parse(text).load_icon_as_image(&links, Some(&tree), IconSize::Large).map(|image| image.get_pixel(0, 0).0)
+    };
+    assert_eq!(color(b"[InternetShortcut]\nIconFile=favicon.ico\n").unwrap(), [3, 0, 0, 255]);
+    assert_eq!(color(b"[InternetShortcut]\nIconFile=%SystemRoot%\\System32\\url.dll\nIconIndex=1\n").unwrap(), [2, 0, 0, 255]);
+    assert_eq!(color(b"[InternetShortcut]\nIconFile=C:\\Windows\\System32\\url.dll\nIconIndex=-1\n").unwrap(), [1, 0, 0, 255]);
+    assert!(matches!(color(b"[InternetShortcut]\nIconFile=https://example.com/favicon.ico\n"), Err(IconError::NoIcon { .. })));
+    assert!(matches!(color(b"[InternetShortcut]\nIconFile=favicon.ico\nIconIndex=1\n"), Err(IconError::NoIconAtIndex(1))));
+
+    let (_, metadata) = InternetShortcut::parse(b"[InternetShortcut]\nURL=https://example.com/\nIconFile=file:///C:/Windows/System32/url.dll\nIconIndex=1\n")
+        .load_icon_with_metadata(&links, Some(&tree), IconSize::Large)
+        .unwrap();
+    assert_eq!(
+        metadata,
+        InternetShortcutMetadata {
+            url: Some("https://example.com/".into()),
+            icon_location: IconLocation { file: IconFile::Path("C:\\Windows\\System32\\url.dll".into()), index: 1 },
+        }
+    );
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use common::{icon_resources, pe_file, solid, TempDir};
+use windows_ext_icons::ico::{self, EncodeOptions};
+use windows_ext_icons::lnk::KnownFolder;
+use windows_ext_icons::{IconError, IconSize, ResourceId, ShellLink, Stage, WindowsTree};
+
+const CLSID: [u8; 16] = [0x01, 0x14, 0x02, 0, 0, 0, 0, 0, 0xc0, 0, 0, 0, 0, 0, 0, 0x46];
+/// FOLDERID_ProgramFiles.
+const PROGRAM_FILES: [u8; 16] = [0xb6, 0x63, 0x5e, 0x90, 0xbf, 0xc1, 0x4e, 0x49, 0xb2, 0x9c, 0x65, 0xb7, 0x32, 0xd3, 0xd2, 0x1a];
+
+#[derive(Default)]
+struct Link {
+    unicode: bool,
+    id_list: Option<Vec<u8>>,
+    local_path: Option<(&'static str, &'static str)>,
+    strings: [Option<&'static str>; 5],
+    icon_index: i32,
+    blocks: Vec<Vec<u8>>,
+}
+
+fn utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn environment_block(signature: u32, path: &str) -> Vec<u8> {
+    let mut block = vec![0u8; 0x314];
+    block[..4].copy_from_slice(&0x314u32.to_le_bytes());
+    block[4..8].copy_from_slice(&signature.to_le_bytes());
+    block[8..8 + path.len()].copy_from_slice(path.as_bytes());
+    let unicode = utf16(path);
+    block[268..268 + unicode.len()].copy_from_slice(&unicode);
+    block
+}
+
+fn known_folder_block() -> Vec<u8> {
+    let mut block = 0x1cu32.to_le_bytes().to_vec();
+    block.extend_from_slice(&0xa000_000bu32.to_le_bytes());
+    block.extend_from_slice(&PROGRAM_FILES);
+    block.extend_from_slice(&20u32.to_le_bytes());
+    block
+}
+
+impl Link {
+    fn build(&self) -> Vec<u8> {
+        let mut flags = 0u32;
+        let mut body = Vec::new();
+        if let Some(id_list) = &self.id_list {
+            flags |= 0x1;
+            body.extend_from_slice(&(id_list.len() as u16).to_le_bytes());
+            body.extend_from_slice(id_list);
+        }
+        if let Some((base, suffix)) = self.local_path {
+            flags |= 0x2;
+            let base_offset = 0x1c;
+            let suffix_offset = base_offset + base.len() + 1;
+            let size = suffix_offset + suffix.len() + 1;
+            let mut info = Vec::new();
+            for value in [size, 0x1c, 1, 0, base_offset, 0, suffix_offset] {
+                info.extend_from_slice(&(value as u32).to_le_bytes());
+            }
+            info.extend_from_slice(base.as_bytes());
+            info.push(0);
+            info.extend_from_slice(suffix.as_bytes());
+            info.push(0);
+            body.extend_from_slice(&info);
+        }
+        if self.unicode {
+            flags |= 0x80;
+        }
+        for (string, flag) in self.strings.iter().zip([0x4, 0x8, 0x10, 0x20, 0x40]) {
+            if let Some(string) = string {
+                flags |= flag;
+                body.extend_from_slice(&(string.encode_utf16().count() as u16).to_le_bytes());
+                body.extend_from_slice(&if self.unicode { utf16(string) } else { string.as_bytes().to_vec() });
+            }
+        }
+        for block in &self.blocks {
+            body.extend_from_slice(block);
+        }
+        body.extend_from_slice(&[0; 4]);
+
+        let mut header = vec![0u8; 0x4c];
+        header[..4].copy_from_slice(&0x4cu32.to_le_bytes());
+        header[4..20].copy_from_slice(&CLSID);
+        header[20..24].copy_from_slice(&flags.to_le_bytes());
+        header[56..60].copy_from_slice(&self.icon_index.to_le_bytes());
+        [header, body].concat()
+    }
+}
+
+#[test]
+fn reads_target_and_strings() {
+    let data = Link {
+        unicode: true,
+        id_list: Some(vec![0; 20]),
+        local_path: Some(("C:\\Program Files\\", "Tool\\tool.exe")),
+        strings: [Some("Tool"), Some("..\\Tool\\tool.exe"), Some("C:\\Work"), Some("--fast"), Some("C:\\Icons\\tool.ico")],
+        icon_index: 3,
+        ..Link::default()
+    }
+    .build();
+
+    let link = ShellLink::parse(&data).unwrap();
+    assert_eq!(link.target_path.as_deref(), Some("C:\\Program Files\\Tool\\tool.exe"));
+    assert_eq!(link.name.as_deref(), Some("Tool"));
+    assert_eq!(link.relative_path.as_deref(), Some("..\\Tool\\tool.exe"));
+    assert_eq!(link.working_dir.as_deref(), Some("C:\\Work"));
+    assert_eq!(link.arguments.as_deref(), Some("--fast"));
+    assert_eq!(link.icon_source(), Some(("C:\\Icons\\tool.ico", 3)));
+}
+
+#[test]
+fn reads_ansi_strings_and_extra_blocks() {
+    let data = Link {
+        strings: [None, None, None, None, Some("shell32.dll")],
+        icon_index: -4,
+        blocks: vec![
+            environment_block(0xa000_0001, "%ProgramFiles%\\Tool\\tool.exe"),
+            known_folder_block(),
+            environment_block(0xa000_0007, "%SystemRoot%\\System32\\shell32.dll"),
+        ],
+        ..Link::default()
+    }
+    .build();
+
+    let link = ShellLink::parse(&data).unwrap();
+    assert_eq!(link.icon_location.as_deref(), Some("shell32.dll"));
+    assert_eq!(link.environment_target.as_deref(), Some("%ProgramFiles%\\Tool\\tool.exe"));
+    assert_eq!(
+        link.known_folder,
+        Some(KnownFolder { id: "{905E63B6-C1BF-494E-B29C-65B732D3D21A}".into(), offset: 20 })
+    );
+    assert_eq!(link.icon_source(), Some(("%SystemRoot%\\System32\\shell32.dll", -4)));
+}
+
+#[test]
+fn decodes_ansi_strings_as_windows_1252() {
+    let mut data = Link {
+        local_path: Some(("C:\\#\\", "tool.exe")),
+        strings: [Some("#"), None, None, None, None],
+        blocks: vec![environment_block(0xa000_0001, "")],
+        ..Link::default()
+    }
+    .build();
+    let ansi = data.len() - 4 - 0x314 + 8;
+    data[ansi..ansi + 3].copy_from_slice(b"C:#");
+    data.iter_mut().filter(|byte| **byte == b'#').for_each(|byte| *byte = 0x80);
+
+    let link = ShellLink::parse(&data).unwrap();
+    assert_eq!(link.name.as_deref(), Some("\u{20ac}"));
+    assert_eq!(link.target_path.as_deref(), Some("C:\\\u{20ac}\\tool.exe"));
+    assert_eq!(link.environment_target.as_deref(), Some("C:\u{20ac}"));
+}
+
+#[test]
+fn falls_back_to_the_target_icon() {
+    let data = Link { local_path: Some(("C:\\Apps\\", "app.exe")), ..Link::default() }.build();
+    assert_eq!(ShellLink::parse(&data).unwrap().icon_source(), Some(("C:\\Apps\\app.exe", 0)));
+    assert_eq!(ShellLink::parse(&Link::default().build()).unwrap().icon_source(), None);
+}
+
+#[test]
+fn decodes_icons_from_a_windows_tree() {
+    let dir = TempDir::new("lnk");
+    dir.write(
+        "Windows/System32/shell32.dll",
+        &pe_file(&icon_resources(&[(ResourceId::Id(4), vec![solid(32, [1, 2, 3, 255])])]), true),
+    );
+    dir.write("Icons/tool.ico", &ico::encode(&[solid(16, [4, 5, 6, 255])], &EncodeOptions::default()).unwrap());
+    let tree = WindowsTree::new(&dir.0);
+
+    let from_module = Link {
+        blocks: vec![environment_block(0xa000_0007, "%SystemRoot%\\System32\\shell32.dll")],
+        icon_index: -4,
+        ..Link::default()
+    };
+    let image = ShellLink::parse(&from_module.build()).unwrap().load_icon_as_image(&tree, IconSize::Large).unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [1, 2, 3, 255]);
+
+    let from_icon = Link { unicode: true, strings: [None, None, None, None, Some("C:\\icons\\TOOL.ico")], ..Link::default() };
+    let image = ShellLink::parse(&from_icon.build()).unwrap().load_icon_as_image(&tree, IconSize::Small).unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [4, 5, 6, 255]);
+}
+
+#[test]
+fn rejects_other_files() {
+    assert!(matches!(ShellLink::parse(&[0x4c, 0, 0, 0]), Err(IconError::Truncated { stage: Stage::ShellLink })));
+
+    let mut data = Link::default().build();
+    data[4] = 0xff;
+    assert!(matches!(ShellLink::parse(&data), Err(IconError::Malformed { stage: Stage::ShellLink })));
+
+    let data = Link { strings: [Some("name"), None, None, None, None], ..Link::default() }.build();
+    assert!(matches!(ShellLink::parse(&data[..0x4c + 3]), Err(IconError::Truncated { stage: Stage::ShellLink })));
+
+    // A network share whose offset wraps around the address space.
+    let mut data = Link { local_path: Some(("C:\\", "tool.exe")), ..Link::default() }.build();
+    data[0x4c + 8..0x4c + 12].copy_from_slice(&2u32.to_le_bytes());
+    data[0x4c + 20..0x4c + 24].copy_from_slice(&0xffff_fffcu32.to_le_bytes());
+    assert!(matches!(ShellLink::parse(&data), Err(IconError::Truncated { stage: Stage::LinkInfo })));
+}
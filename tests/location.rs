@@ -0,0 +1,105 @@
+mod common;
+
+use common::{icon_resources, pe_file, solid, TempDir};
+use std::collections::HashMap;
+use windows_ext_icons::location::expand_environment;
+use windows_ext_icons::{IconError, IconFile, IconLocation, IconSize, ResourceId, Stage, WindowsTree};
+
+fn environment() -> HashMap<String, String> {
+    [
+        ("SystemRoot", "C:\\Windows"),
+        ("ProgramFiles", "C:\\Program Files"),
+        ("ProgramFiles(x86)", "C:\\Program Files (x86)"),
+        ("EMPTY", ""),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+fn path(path: &str) -> IconFile {
+    IconFile::Path(path.to_string())
+}
+
+#[test]
+fn parses_location_table() {
+    let cases: &[(&str, IconFile, i32)] = &[
+        ("shell32.dll", path("shell32.dll"), 0),
+        ("shell32.dll,3", path("shell32.dll"), 3),
+        ("shell32.dll,-4", path("shell32.dll"), -4),
+        ("@shell32.dll,-4", path("shell32.dll"), -4),
+        ("  shell32.dll , 7  ", path("shell32.dll"), 7),
+        ("shell32.dll,", path("shell32.dll"), 0),
+        ("\"%SystemRoot%\\System32\\imageres.dll\",-102", path("C:\\Windows\\System32\\imageres.dll"), -102),
+        ("\"C:\\Program Files\\App\\app.exe\", 2", path("C:\\Program Files\\App\\app.exe"), 2),
+        ("\"C:\\Program Files\\App\\app.exe\"", path("C:\\Program Files\\App\\app.exe"), 0),
+        ("\"C:\\Program Files\\App\\app.exe,5\"", path("C:\\Program Files\\App\\app.exe"), 5),
+        ("\"C:\\a,b\\icon.ico\",1", path("C:\\a,b\\icon.ico"), 1),
+        ("C:\\a,b\\icon.ico", path("C:\\a,b\\icon.ico"), 0),
+        ("C:\\a,b\\icon.ico,2", path("C:\\a,b\\icon.ico"), 2),
+        ("%ProgramFiles(x86)%\\App\\app.exe,1", path("C:\\Program Files (x86)\\App\\app.exe"), 1),
+        ("%programfiles%\\App\\app.exe", path("C:\\Program Files\\App\\app.exe"), 0),
+        ("%UNKNOWN%\\icon.ico", path("%UNKNOWN%\\icon.ico"), 0),
+        ("%EMPTY%icon.ico", path("icon.ico"), 0),
+        ("100%\\%SystemRoot%\\icon.ico", path("100%\\C:\\Windows\\icon.ico"), 0),
+        ("%1", IconFile::Itself, 0),
+        ("%1,0", IconFile::Itself, 0),
+        ("\"%1\",-1", IconFile::Itself, -1),
+        ("shell32.dll,-2147483648", path("shell32.dll"), i32::MIN),
+    ];
+
+    for (text, file, index) in cases {
+        let location = IconLocation::parse(text, &environment()).unwrap_or_else(|error| panic!("{text}: {error}"));
+        assert_eq!((&location.file, location.index), (file, *index), "{text}");
+    }
+}
+
+#[test]
+fn rejects_malformed_locations() {
+    for text in ["", "   ", ",3", "\"\",1", "\"unterminated.dll,1", "\"app.exe\",x", "\"app.exe\" trailing", "@"] {
+        assert!(
+            matches!(IconLocation::parse(text, &environment()), Err(IconError::Malformed { stage: Stage::IconLocation })),
+            "{text:?}"
+        );
+    }
+}
+
+#[test]
+fn keeps_non_numeric_suffixes_in_unquoted_paths() {
+    let location = IconLocation::parse("C:\\icons\\a,b", &HashMap::new()).unwrap();
+    assert_eq!(location, IconLocation { file: path("C:\\icons\\a,b"), index: 0 });
+}
+
+#[test]
+fn substitutes_document_for_placeholder() {
+    let location = IconLocation::parse("%1", &HashMap::new()).unwrap();
+    assert_eq!(location.path("C:\\app.exe"), "C:\\app.exe");
+
+    let location = IconLocation::parse("shell32.dll,1", &HashMap::new()).unwrap();
+    assert_eq!(location.path("C:\\app.exe"), "shell32.dll");
+}
+
+#[test]
+fn expands_unterminated_references_literally() {
+    assert_eq!(expand_environment("50%", &environment()), "50%");
+    assert_eq!(expand_environment("%%SystemRoot%", &environment()), "%C:\\Windows");
+}
+
+#[test]
+fn loads_locations_from_a_windows_tree() {
+    let dir = TempDir::new("location");
+    dir.write(
+        "Windows/System32/imageres.dll",
+        &pe_file(&icon_resources(&[(ResourceId::Id(102), vec![solid(32, [9, 8, 7, 255])])]), true),
+    );
+    dir.write("Apps/app.exe", &pe_file(&icon_resources(&[(ResourceId::Id(1), vec![solid(32, [1, 1, 1, 255])])]), false));
+    let tree = WindowsTree::new(&dir.0);
+
+    let location = IconLocation::parse("\"%SystemRoot%\\System32\\imageres.dll\",-102", &tree.environment()).unwrap();
+    let image = tree.load_icon_location(&location, "C:\\unused.txt", IconSize::Large).unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [9, 8, 7, 255]);
+
+    let location = IconLocation::parse("%1", &tree.environment()).unwrap();
+    let image = tree.load_icon_location(&location, "C:\\Apps\\app.exe", IconSize::Large).unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [1, 1, 1, 255]);
+}
@@ -0,0 +1,50 @@
+use image::{Rgba, RgbaImage};
+use windows_ext_icons::mask::{apply_and_mask, decode_monochrome, mask_stride};
+
+#[test]
+fn pads_mask_rows_to_four_bytes() {
+    assert_eq!(mask_stride(1), 4);
+    assert_eq!(mask_stride(32), 4);
+    assert_eq!(mask_stride(33), 8);
+}
+
+#[test]
+fn builds_alpha_from_mask_when_colour_has_none() {
+    let mut image = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 0, 0]));
+    image.put_pixel(1, 0, Rgba([10, 20, 30, 0]));
+    // Bottom-up: bottom row fully transparent, top row transparent on the right.
+    let mask = [0b1100_0000, 0, 0, 0, 0b0100_0000, 0, 0, 0];
+
+    let outcome = apply_and_mask(&mut image, &mask, false).unwrap();
+
+    assert!(outcome.alpha_from_mask);
+    assert_eq!(outcome.inverted, [(1, 0)]);
+    let alpha: Vec<u8> = image.pixels().map(|pixel| pixel[3]).collect();
+    assert_eq!(alpha, [255, 0, 0, 0]);
+}
+
+#[test]
+fn keeps_existing_alpha() {
+    let mut image = RgbaImage::from_pixel(1, 1, Rgba([1, 2, 3, 100]));
+    let outcome = apply_and_mask(&mut image, &[0x80, 0, 0, 0], true).unwrap();
+    assert!(!outcome.alpha_from_mask);
+    assert_eq!(image.get_pixel(0, 0).0, [1, 2, 3, 100]);
+}
+
+#[test]
+fn decodes_monochrome_icons() {
+    // Top-down, 4x1: AND row then XOR row.
+    let mask = [0b0011_0000, 0, 0, 0, 0b0101_0000, 0, 0, 0];
+    let (image, outcome) = decode_monochrome(&mask, 4, 1, true).unwrap();
+
+    let pixels: Vec<[u8; 4]> = image.pixels().map(|pixel| pixel.0).collect();
+    assert_eq!(pixels, [[0, 0, 0, 255], [255, 255, 255, 255], [0, 0, 0, 0], [255, 255, 255, 0]]);
+    assert_eq!(outcome.inverted, [(3, 0)]);
+}
+
+#[test]
+fn rejects_short_masks() {
+    let mut image = RgbaImage::new(2, 2);
+    assert!(apply_and_mask(&mut image, &[0; 4], true).is_err());
+    assert!(decode_monochrome(&[0; 4], 1, 1, true).is_err());
+}
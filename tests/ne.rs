@@ -0,0 +1,63 @@
+mod common;
+
+use common::{icon_resources, ne_file, pe_file, solid};
+use windows_ext_icons::ne::NeFile;
+use windows_ext_icons::resource::{self, Executable, RT_GROUP_ICON};
+use windows_ext_icons::{ico, IconError, IconResources, IconSize, ResourceId, Stage};
+
+fn groups() -> Vec<(ResourceId, Vec<image::RgbaImage>)> {
+    vec![
+        (ResourceId::Name("APPICON".into()), vec![solid(32, [10, 20, 30, 255])]),
+        (ResourceId::Id(2), vec![solid(16, [40, 50, 60, 255]), solid(32, [40, 50, 60, 255])]),
+    ]
+}
+
+#[test]
+fn reads_resource_table_at_each_alignment() {
+    for shift in [0, 4, 9] {
+        let data = ne_file(&icon_resources(&groups()), shift);
+        let file = NeFile::parse(&data).unwrap();
+
+        assert_eq!(file.icon_groups().unwrap(), [ResourceId::Name("APPICON".into()), ResourceId::Id(2)]);
+        let entries = file.icon_group_images(&ResourceId::Name("appicon".into())).unwrap();
+        assert_eq!(entries[0].image.get_pixel(5, 5).0, [10, 20, 30, 255]);
+    }
+}
+
+#[test]
+fn decodes_like_the_same_icons_in_a_pe_image() {
+    let resources = icon_resources(&groups());
+    let ne = ne_file(&resources, 4);
+    let pe = pe_file(&resources, false);
+
+    for index in [0, 1, -2] {
+        let from_ne = ico::parse(&Executable::parse(&ne).unwrap().icon_at_index(index).unwrap()).unwrap();
+        let from_pe = ico::parse(&Executable::parse(&pe).unwrap().icon_at_index(index).unwrap()).unwrap();
+        assert_eq!(from_ne, from_pe);
+    }
+}
+
+#[test]
+fn handles_missing_and_truncated_tables() {
+    let empty = ne_file(&Vec::new(), 0);
+    assert!(NeFile::parse(&empty).unwrap().icon_groups().unwrap().is_empty());
+
+    let data = ne_file(&icon_resources(&groups()), 4);
+    assert!(matches!(
+        NeFile::parse(&data[..0x90]),
+        Err(IconError::Truncated { stage: Stage::ResourceDirectory })
+    ));
+    assert!(matches!(
+        NeFile::parse(&data).unwrap().icon_group(&ResourceId::Id(9)),
+        Err(IconError::MissingResource { resource_type: RT_GROUP_ICON, .. })
+    ));
+}
+
+#[test]
+fn loads_icon_from_disk() {
+    let path = std::env::temp_dir().join(format!("windows-ext-icons-{}-ne.exe", std::process::id()));
+    std::fs::write(&path, ne_file(&icon_resources(&groups()), 4)).unwrap();
+    let image = resource::load_icon_as_image(&path, 1, IconSize::Small);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(image.unwrap().get_pixel(0, 0).0, [40, 50, 60, 255]);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::{icon_resources, pe_file, solid};
+use windows_ext_icons::pe::PeFile;
+use windows_ext_icons::resource;
+use windows_ext_icons::{ico, IconError, IconResources, IconSize, ResourceId, Stage};
+
+fn sample(pe32_plus: bool) -> Vec<u8> {
+    pe_file(
+        &icon_resources(&[
+            (ResourceId::Name("MAINICON".into()), vec![solid(16, [255, 0, 0, 255]), solid(32, [255, 0, 0, 255])]),
+            (ResourceId::Id(101), vec![solid(16, [0, 255, 0, 255])]),
+            (ResourceId::Id(205), vec![solid(48, [0, 0, 255, 255])]),
+        ]),
+        pe32_plus,
+    )
+}
+
+fn color(file: &PeFile, index: i32) -> [u8; 4] {
+    ico::parse(&file.icon_at_index(index).unwrap()).unwrap()[0].image.get_pixel(0, 0).0
+}
+
+#[test]
+fn lists_icon_groups_in_directory_order() {
+    for pe32_plus in [false, true] {
+        let data = sample(pe32_plus);
+        let file = PeFile::parse(&data).unwrap();
+        assert_eq!(
+            file.icon_groups().unwrap(),
+            [ResourceId::Name("MAINICON".into()), ResourceId::Id(101), ResourceId::Id(205)]
+        );
+    }
+}
+
+#[test]
+fn reassembles_groups_into_icon_files() {
+    let data = sample(false);
+    let file = PeFile::parse(&data).unwrap();
+
+    let entries = file.icon_group_images(&ResourceId::Name("mainicon".into())).unwrap();
+    let sizes: Vec<u32> = entries.iter().map(|entry| entry.width).collect();
+    assert_eq!(sizes, [16, 32]);
+    assert!(entries.iter().all(|entry| entry.image.pixels().all(|pixel| pixel.0 == [255, 0, 0, 255])));
+}
+
+#[test]
+fn follows_extract_icon_ex_indices() {
+    let data = sample(true);
+    let file = PeFile::parse(&data).unwrap();
+
+    assert_eq!(color(&file, 0), [255, 0, 0, 255]);
+    assert_eq!(color(&file, 2), [0, 0, 255, 255]);
+    assert_eq!(color(&file, -101), [0, 255, 0, 255]);
+    assert_eq!(file.icon_group_for_index(-205).unwrap(), ResourceId::Id(205));
+
+    assert!(matches!(file.icon_at_index(3), Err(IconError::NoIconAtIndex(3))));
+    assert!(matches!(file.icon_at_index(-1), Err(IconError::NoIconAtIndex(-1))));
+    assert!(matches!(file.icon_at_index(i32::MIN), Err(IconError::NoIconAtIndex(i32::MIN))));
+}
+
+#[test]
+fn reports_missing_resources() {
+    let data = sample(false);
+    let file = PeFile::parse(&data).unwrap();
+    assert!(matches!(
+        file.icon_group(&ResourceId::Id(7)),
+        Err(IconError::MissingResource { resource_type: 14, name: ResourceId::Id(7) })
+    ));
+
+    let empty = pe_file(&Vec::new(), false);
+    assert!(PeFile::parse(&empty).unwrap().icon_groups().unwrap().is_empty());
+}
+
+#[test]
+fn rejects_malformed_images() {
+    assert!(matches!(PeFile::parse(b"ZM"), Err(IconError::Malformed { stage: Stage::ExecutableHeader })));
+    assert!(matches!(PeFile::parse(b"MZ\0\0"), Err(IconError::Truncated { stage: Stage::ExecutableHeader })));
+
+    let mut data = sample(false);
+    data.truncate(0x220);
+    let file = PeFile::parse(&data).unwrap();
+    assert!(matches!(file.icon_groups(), Err(IconError::Truncated { stage: Stage::ResourceDirectory })));
+
+    // Type subdirectories at the very end of the 31-bit offset range.
+    let mut data = sample(false);
+    let types = usize::from(u16::from_le_bytes([data[0x20e], data[0x20f]]));
+    for index in 0..types {
+        let target = 0x200 + 16 + index * 8 + 4;
+        data[target..target + 4].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+    }
+    let file = PeFile::parse(&data).unwrap();
+    assert!(matches!(file.icon_groups(), Err(IconError::Truncated { stage: Stage::ResourceDirectory })));
+}
+
+#[test]
+fn loads_icon_from_disk() {
+    let path = std::env::temp_dir().join(format!("windows-ext-icons-{}.exe", std::process::id()));
+    std::fs::write(&path, sample(false)).unwrap();
+    let image = resource::load_icon_as_image(&path, 0, IconSize::Large);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(image.unwrap().dimensions(), (32, 32));
+}
@@ -0,0 +1,72 @@
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+use windows_ext_icons::{IconError, IconProvider, IconSize, MemoryIconProvider, ProviderChain};
+
+fn solid(value: u8) -> RgbaImage {
+    RgbaImage::from_pixel(2, 2, Rgba([value, value, value, 255]))
+}
+
+#[test]
+fn memory_provider_serves_registered_icons() {
+    let mut provider = MemoryIconProvider::new();
+    provider.insert_path("C:/tools/app.exe", solid(1));
+    provider.insert_extension(".TXT", solid(2));
+    provider.insert_mime("Image/PNG", solid(3));
+
+    assert_eq!(provider.icon_for_path(Path::new("C:/tools/app.exe"), IconSize::Large).unwrap(), solid(1));
+    assert_eq!(provider.icon_for_extension("txt", IconSize::Small).unwrap(), solid(2));
+    assert_eq!(provider.icon_for_mime("image/png", IconSize::Jumbo).unwrap(), solid(3));
+}
+
+#[test]
+fn memory_provider_falls_back_to_extension_for_paths() {
+    let mut provider = MemoryIconProvider::new();
+    provider.insert_extension("md", solid(4));
+
+    assert_eq!(provider.icon_for_path(Path::new("docs/README.MD"), IconSize::Large).unwrap(), solid(4));
+    assert!(matches!(
+        provider.icon_for_path(Path::new("docs/notes"), IconSize::Large),
+        Err(IconError::NoIcon { .. })
+    ));
+}
+
+#[test]
+fn memory_provider_reports_missing_keys() {
+    let provider = MemoryIconProvider::new();
+    assert!(matches!(
+        provider.icon_for_extension("pdf", IconSize::Large),
+        Err(IconError::NoIconForExtension(extension)) if extension == "pdf"
+    ));
+    assert!(matches!(
+        provider.icon_for_mime("text/plain", IconSize::Large),
+        Err(IconError::NoIconForMime(mime)) if mime == "text/plain"
+    ));
+}
+
+#[test]
+fn chain_returns_first_success() {
+    let mut specific = MemoryIconProvider::new();
+    specific.insert_extension("rs", solid(5));
+    let mut fallback = MemoryIconProvider::new();
+    fallback.insert_extension("rs", solid(6));
+    fallback.insert_extension("toml", solid(7));
+
+    let mut chain = ProviderChain::new(vec![Box::new(specific)]);
+    chain.push(fallback);
+
+    assert_eq!(chain.icon_for_extension("rs", IconSize::Large).unwrap(), solid(5));
+    assert_eq!(chain.icon_for_extension("toml", IconSize::Large).unwrap(), solid(7));
+    assert!(matches!(
+        chain.icon_for_extension("lock", IconSize::Large),
+        Err(IconError::NoIconForExtension(_))
+    ));
+}
+
+#[test]
+fn empty_chain_reports_not_found() {
+    let chain = ProviderChain::default();
+    assert!(matches!(
+        chain.icon_for_path(Path::new("a.txt"), IconSize::Large),
+        Err(IconError::NoIcon { .. })
+    ));
+}
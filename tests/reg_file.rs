@@ -0,0 +1,110 @@
+use windows_ext_icons::{IconError, RegFile, RegValue, Registry, Stage};
+
+fn utf16_with_bom(text: &str) -> Vec<u8> {
+    [0xff, 0xfe].into_iter().chain(text.encode_utf16().flat_map(u16::to_le_bytes)).collect()
+}
+
+fn hex_utf16(text: &str) -> String {
+    let bytes: Vec<String> = text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).map(|byte| format!("{byte:02x}")).collect();
+    // Wrap the bytes over several lines as regedit does.
+    bytes.chunks(20).map(|chunk| chunk.join(",")).collect::<Vec<_>>().join(",\\\r\n  ")
+}
+
+const PDF_EXPORT: &str = r#"Windows Registry Editor Version 5.00
+
+; Acrobat association
+[HKEY_LOCAL_MACHINE\SOFTWARE\Classes\.pdf]
+@="AcroExch.Document.DC"
+"Content Type"="application/pdf"
+
+[HKEY_LOCAL_MACHINE\SOFTWARE\Classes\.pdf\OpenWithProgids]
+"AcroExch.Document.DC"=hex(0):
+
+[HKEY_LOCAL_MACHINE\SOFTWARE\Classes\AcroExch.Document.DC\DefaultIcon]
+@="\"C:\\Program Files\\Adobe\\Acrobat DC\\Acrobat\\Acrobat.exe\",1"
+"#;
+
+#[test]
+fn resolves_pdf_icon_from_utf16_export() {
+    let file = RegFile::parse(&utf16_with_bom(&PDF_EXPORT.replace('\n', "\r\n"))).unwrap();
+    let associations = file.associations();
+    assert_eq!(associations.prog_ids("pdf").unwrap(), ["AcroExch.Document.DC", ".pdf"]);
+    assert_eq!(
+        associations.default_icon(".pdf").unwrap().as_deref(),
+        Some(r#""C:\Program Files\Adobe\Acrobat DC\Acrobat\Acrobat.exe",1"#)
+    );
+    assert_eq!(file.string(r"HKLM\Software\Classes\.PDF", "content type").unwrap().as_deref(), Some("application/pdf"));
+}
+
+#[test]
+fn decodes_value_types() {
+    let text = format!(
+        "Windows Registry Editor Version 5.00\r\n\r\n[HKEY_CLASSES_ROOT\\txtfile\\DefaultIcon]\r\n@=hex(2):{}\r\n\"Count\"=dword:0000002a\r\n\"Names\"=hex(7):{}\r\n\"Raw\"=hex:de,ad,\\\r\n  be,ef\r\n\"Quote\"=\"say \\\"hi\\\" = [ok]\"\r\n",
+        hex_utf16("%SystemRoot%\\system32\\imageres.dll,-102"),
+        hex_utf16("one\0two\0"),
+    );
+    let file = RegFile::parse(text.as_bytes()).unwrap();
+    let value = |name| file.value(r"HKCR\txtfile\DefaultIcon", name).unwrap();
+
+    assert_eq!(value(""), Some(RegValue::ExpandString("%SystemRoot%\\system32\\imageres.dll,-102".into())));
+    assert_eq!(value("count"), Some(RegValue::Dword(42)));
+    assert_eq!(value("Names"), Some(RegValue::MultiString(vec!["one".into(), "two".into()])));
+    assert_eq!(value("Raw"), Some(RegValue::Binary(vec![0xde, 0xad, 0xbe, 0xef])));
+    assert_eq!(value("Quote"), Some(RegValue::String("say \"hi\" = [ok]".into())));
+}
+
+#[test]
+fn reads_regedit4_ansi_strings() {
+    let file = RegFile::parse(b"REGEDIT4\n\n[HKEY_CURRENT_USER\\Software\\Classes\\.log]\n@=\"logfile\"\n\n[HKEY_CURRENT_USER\\Software\\Classes\\logfile\\DefaultIcon]\n@=hex(2):25,53,79,73,74,65,6d,52,6f,6f,74,25,5c,6c,6f,67,2e,69,63,6f,00\n").unwrap();
+    assert_eq!(file.associations().default_icon("log").unwrap().as_deref(), Some("%SystemRoot%\\log.ico"));
+}
+
+#[test]
+fn reads_regedit4_strings_as_windows_1252() {
+    let file = RegFile::parse(b"REGEDIT4\n\n[HKEY_CURRENT_USER\\Test]\n\"Price\"=hex(2):80,e9,99,00\n\"Names\"=hex(7):93,61,94,00,62,00,00\n").unwrap();
+    assert_eq!(file.string(r"HKEY_CURRENT_USER\Test", "Price").unwrap().as_deref(), Some("\u{20ac}\u{e9}\u{2122}"));
+    assert_eq!(file.value(r"HKEY_CURRENT_USER\Test", "Names").unwrap(), Some(RegValue::MultiString(vec!["\u{201c}a\u{201d}".to_string(), "b".to_string()])));
+}
+
+#[test]
+fn continues_only_value_lines() {
+    let file = RegFile::parse(b"REGEDIT4\n\n[HKEY_CURRENT_USER\\Test]\n; ends in C:\\\n\"Kept\"=\"yes\"\n\"Raw\"=hex:01,\\\n  02\n").unwrap();
+    assert_eq!(file.string(r"HKEY_CURRENT_USER\Test", "Kept").unwrap().as_deref(), Some("yes"));
+    assert_eq!(file.value(r"HKEY_CURRENT_USER\Test", "Raw").unwrap(), Some(RegValue::Binary(vec![1, 2])));
+}
+
+#[test]
+fn applies_deletions_in_order() {
+    let file = RegFile::parse(
+        br#"Windows Registry Editor Version 5.00
+
+[HKEY_CLASSES_ROOT\.old\Sub]
+"Keep"="no"
+
+[HKEY_CLASSES_ROOT\.txt]
+@="txtfile"
+"PerceivedType"="text"
+"Stale"="yes"
+
+[-HKEY_CLASSES_ROOT\.old]
+
+[HKEY_CLASSES_ROOT\.txt]
+"Stale"=-
+@="notepad"
+
+[-HKEY_CLASSES_ROOT\.missing\Key]
+"#,
+    )
+    .unwrap();
+
+    assert!(!file.key_exists(r"HKEY_CLASSES_ROOT\.old").unwrap());
+    assert_eq!(file.subkey_names("HKEY_CLASSES_ROOT").unwrap(), Some(vec![".txt".into()]));
+    assert_eq!(file.value_names(r"HKCR\.txt").unwrap(), Some(vec!["".into(), "PerceivedType".into()]));
+    assert_eq!(file.string(r"HKCR\.txt", "").unwrap().as_deref(), Some("notepad"));
+}
+
+#[test]
+fn rejects_other_files() {
+    assert!(matches!(RegFile::parse(b"[HKEY_CLASSES_ROOT\\.txt]\n"), Err(IconError::Malformed { stage: Stage::RegistryFile })));
+    assert!(matches!(RegFile::parse(b""), Err(IconError::Malformed { stage: Stage::RegistryFile })));
+}
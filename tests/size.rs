@@ -0,0 +1,32 @@
+use windows_ext_icons::size::{DEFAULT_DPI, SHIL_EXTRALARGE, SHIL_JUMBO, SHIL_LARGE, SHIL_SMALL, SHIL_SYSSMALL};
+use windows_ext_icons::IconSize;
+
+#[test]
+fn maps_to_shell_image_lists() {
+    assert_eq!(IconSize::Small.shell_image_list(DEFAULT_DPI), SHIL_SMALL);
+    assert_eq!(IconSize::SysSmall.shell_image_list(DEFAULT_DPI), SHIL_SYSSMALL);
+    assert_eq!(IconSize::Large.shell_image_list(DEFAULT_DPI), SHIL_LARGE);
+    assert_eq!(IconSize::ExtraLarge.shell_image_list(DEFAULT_DPI), SHIL_EXTRALARGE);
+    assert_eq!(IconSize::Jumbo.shell_image_list(DEFAULT_DPI), SHIL_JUMBO);
+    assert_eq!(IconSize::from_shell_image_list(SHIL_EXTRALARGE), Some(IconSize::ExtraLarge));
+    assert_eq!(IconSize::from_shell_image_list(7), None);
+}
+
+#[test]
+fn scales_nominal_sizes_with_dpi() {
+    assert_eq!(IconSize::Small.pixels(96), 16);
+    assert_eq!(IconSize::Large.pixels(144), 48);
+    assert_eq!(IconSize::ExtraLarge.pixels(192), 96);
+    assert_eq!(IconSize::Jumbo.pixels(192), 256);
+    assert_eq!(IconSize::Pixels(20).pixels(192), 20);
+}
+
+#[test]
+fn picks_nearest_native_list_at_or_above_request() {
+    assert_eq!(IconSize::Pixels(16).native(96), IconSize::Small);
+    assert_eq!(IconSize::Pixels(24).native(96), IconSize::Large);
+    assert_eq!(IconSize::Pixels(24).native(144), IconSize::Small);
+    assert_eq!(IconSize::Pixels(64).native(96), IconSize::Jumbo);
+    assert_eq!(IconSize::Pixels(512).native(96), IconSize::Jumbo);
+    assert_eq!(IconSize::Pixels(40).shell_image_list(96), SHIL_EXTRALARGE);
+}
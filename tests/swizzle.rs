@@ -0,0 +1,131 @@
+use windows_ext_icons::swizzle::{bgra_to_rgba, bgra_to_rgba_scalar, Backend};
+
+/// Small xorshift generator so the property checks stay dependency-free and reproducible.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+#[test]
+fn swaps_red_and_blue() {
+    let mut data = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    bgra_to_rgba(&mut data);
+    assert_eq!(data, [3, 2, 1, 4, 7, 6, 5, 8]);
+}
+
+#[test]
+fn converts_tail_pixels() {
+    // 5 pixels: one full SSE block plus a single trailing pixel.
+    let mut data: Vec<u8> = (0..20).collect();
+    bgra_to_rgba(&mut data);
+    assert_eq!(&data[16..], [18, 17, 16, 19]);
+}
+
+#[test]
+fn leaves_partial_pixel_untouched() {
+    let mut data = vec![1, 2, 3, 4, 5, 6];
+    bgra_to_rgba(&mut data);
+    assert_eq!(data, [3, 2, 1, 4, 5, 6]);
+}
+
+#[test]
+fn matches_scalar_reference_for_random_buffers() {
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..500 {
+        let len = (rng.next() % 300) as usize;
+        let input = rng.bytes(len);
+
+        let mut fast = input.clone();
+        let mut reference = input;
+        bgra_to_rgba(&mut fast);
+        bgra_to_rgba_scalar(&mut reference);
+
+        assert_eq!(fast, reference, "mismatch for length {len}");
+    }
+}
+
+#[test]
+fn matches_scalar_reference_around_block_boundaries() {
+    // Covers the 16-byte NEON/SSE/simd128 block and the 32-byte AVX2 block.
+    let mut rng = XorShift(0x0123_4567_89ab_cdef);
+    for len in (0..=100).step_by(4) {
+        let input = rng.bytes(len);
+
+        let mut fast = input.clone();
+        let mut reference = input;
+        bgra_to_rgba(&mut fast);
+        bgra_to_rgba_scalar(&mut reference);
+
+        assert_eq!(fast, reference, "mismatch for length {len}");
+    }
+}
+
+#[test]
+fn matches_scalar_reference_at_unaligned_offsets() {
+    let mut rng = XorShift(0xdead_beef_cafe_f00d);
+    let input = rng.bytes(256 + 64);
+    for offset in 0..32 {
+        let mut fast = input.clone();
+        let mut reference = input.clone();
+        bgra_to_rgba(&mut fast[offset..]);
+        bgra_to_rgba_scalar(&mut reference[offset..]);
+        assert_eq!(fast, reference, "mismatch at offset {offset}");
+    }
+}
+
+#[test]
+fn every_backend_permutes_like_scalar() {
+    // Every length up to several 32-byte blocks, including partial pixels.
+    let mut rng = XorShift(0x5151_2323_7f7f_0101);
+    for backend in Backend::available() {
+        for len in 0..=100 {
+            let input = rng.bytes(len);
+
+            let mut fast = input.clone();
+            let mut reference = input;
+            backend.bgra_to_rgba(&mut fast);
+            Backend::Scalar.bgra_to_rgba(&mut reference);
+
+            assert_eq!(fast, reference, "{backend:?} mismatch for length {len}");
+        }
+    }
+}
+
+#[test]
+fn every_backend_expands_like_scalar() {
+    // Source lengths that leave partial pixels and stop short of a whole 16-byte load.
+    let mut rng = XorShift(0x0f0f_a5a5_3c3c_9696);
+    for backend in Backend::available() {
+        for src_len in 0..=80 {
+            for dst_len in [src_len / 3 * 4, src_len / 3 * 4 + 3, src_len / 3 * 2] {
+                let input = rng.bytes(src_len);
+                let output = rng.bytes(dst_len);
+
+                let mut fast = output.clone();
+                let mut reference = output;
+                backend.bgr_to_rgba(&input, &mut fast);
+                Backend::Scalar.bgr_to_rgba(&input, &mut reference);
+
+                assert_eq!(fast, reference, "{backend:?} mismatch for {src_len} source and {dst_len} destination bytes");
+            }
+        }
+    }
+}
+
+#[test]
+fn lists_the_backends_of_this_target() {
+    let backends = Backend::available();
+    assert_eq!(backends[0], Backend::Scalar);
+    assert_eq!(backends.contains(&Backend::Neon), cfg!(target_arch = "aarch64"));
+    assert_eq!(backends.contains(&Backend::Simd128), cfg!(all(target_arch = "wasm32", target_feature = "simd128")));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::{icon_resources, pe_file, solid, TempDir};
+use windows_ext_icons::{IconError, IconResources, IconSize, ResourceId, WindowsTree};
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+/// A tree whose `imageres.dll` is a stub, with its icons in a `.mun` file and a localized one in its MUI.
+fn tree(name: &str) -> (TempDir, WindowsTree) {
+    let dir = TempDir::new(name);
+    dir.write("windows/system32/imageres.dll", &pe_file(&Vec::new(), true));
+    dir.write(
+        "windows/SystemResources/imageres.dll.mun",
+        &pe_file(&icon_resources(&[(ResourceId::Id(2), vec![solid(32, RED)]), (ResourceId::Id(102), vec![solid(32, GREEN)])]), true),
+    );
+    dir.write(
+        "windows/system32/en-US/imageres.dll.mui",
+        &pe_file(&icon_resources(&[(ResourceId::Id(7000), vec![solid(16, BLUE)])]), true),
+    );
+    dir.write("windows/system32/plain.dll", &pe_file(&icon_resources(&[(ResourceId::Id(1), vec![solid(16, BLUE)])]), false));
+
+    let tree = WindowsTree::new(&dir.0);
+    (dir, tree)
+}
+
+fn color(tree: &WindowsTree, module: &str, index: i32) -> [u8; 4] {
+    tree.load_icon_as_image(module, index, IconSize::Large).unwrap().get_pixel(0, 0).0
+}
+
+#[test]
+fn resolves_icons_from_mun_files() {
+    let (_dir, tree) = tree("mun");
+    assert_eq!(color(&tree, "imageres.dll", -102), GREEN);
+    assert_eq!(color(&tree, "imageres.dll", 0), RED);
+    assert_eq!(color(&tree, "%SystemRoot%\\System32\\imageres.dll", -2), RED);
+    assert_eq!(color(&tree, "C:\\WINDOWS\\system32\\IMAGERES.DLL", 1), GREEN);
+}
+
+#[test]
+fn falls_back_to_mui_satellites() {
+    let (_dir, tree) = tree("mui");
+    assert_eq!(color(&tree, "imageres.dll", -7000), BLUE);
+
+    let resources = tree.module_resources("imageres.dll").unwrap();
+    let names: Vec<String> = resources.paths().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+    assert_eq!(names, ["imageres.dll.mun", "imageres.dll", "imageres.dll.mui"]);
+    assert_eq!(resources.icon_groups().unwrap(), [ResourceId::Id(2), ResourceId::Id(102)]);
+
+    let german = WindowsTree { languages: vec!["de-DE".into()], ..tree.clone() };
+    assert!(matches!(german.load_icon_as_image("imageres.dll", -7000, IconSize::Large), Err(IconError::NoIconAtIndex(-7000))));
+}
+
+#[test]
+fn reads_modules_without_redirection() {
+    let (_dir, tree) = tree("plain");
+    assert_eq!(color(&tree, "plain.dll", 0), BLUE);
+    assert!(tree.find_module("Plain.DLL").unwrap().ends_with("windows/system32/plain.dll"));
+    assert!(matches!(tree.module_resources("missing.dll"), Err(IconError::Io { .. })));
+}
+
+#[test]
+fn stops_parent_components_at_the_drive_root() {
+    let (dir, tree) = tree("parent");
+    assert_eq!(tree.host_path("C:\\..\\..\\etc\\passwd"), Some(dir.0.join("etc").join("passwd")));
+    assert_eq!(tree.host_path("..\\windows\\.\\system32\\..\\system32\\plain.dll"), tree.find_module("plain.dll"));
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use common::{icon_resources, pe_file, solid, TempDir};
+use std::path::Path;
+use windows_ext_icons::ico::{self, EncodeOptions};
+use windows_ext_icons::{IconError, IconProvider, IconSize, RegFile, RegValue, Registry, ResourceId, WineIconProvider};
+
+const APPLICATION: [u8; 4] = [1, 0, 0, 255];
+const TEXT: [u8; 4] = [2, 0, 0, 255];
+const APP_LARGE: [u8; 4] = [3, 0, 0, 255];
+const APP_SMALL: [u8; 4] = [4, 0, 0, 255];
+const EDITOR: [u8; 4] = [5, 0, 0, 255];
+const IMAGE: [u8; 4] = [6, 0, 0, 255];
+const ARCHIVE: [u8; 4] = [7, 0, 0, 255];
+
+const SYSTEM_REG: &str = r#"WINE REGISTRY Version 2
+;; All keys relative to \\Machine
+
+#arch=win64
+
+[Software\\Classes\\.exe] 1700000000
+#time=1da1e2b3c4d5e6f
+@="exefile"
+
+[Software\\Classes\\.ico] 1700000000
+@="icofile"
+
+[Software\\Classes\\.txt] 1700000000
+@="txtfile"
+"Content Type"="text/plain"
+
+[Software\\Classes\\.md] 1700000000
+@="txtfile"
+
+[Software\\Classes\\exefile\\DefaultIcon] 1700000000
+@="%1"
+
+[Software\\Classes\\icofile\\DefaultIcon] 1700000000
+@="%1"
+
+[Software\\Classes\\txtfile\\DefaultIcon] 1700000000
+@=str(2):"%SystemRoot%\\system32\\shell32.dll,-152"
+
+[Software\\Classes\\.zip\\DefaultIcon] 1700000000
+@="Z:\\share\\archive.ico,0"
+
+[Software\\Classes\\MIME\\Database\\Content Type\\text/plain] 1700000000
+"Extension"=".txt"
+
+[Software\\Wine\\Escapes] 1700000000
+"Text"="tab\there \"quoted\" caf\xe9 \101"
+"Multi"=str(7):"one\0two\0"
+"Path"=hex(2):25,00,53,00,79,00,73,00,00,00
+"#;
+
+const USER_REG: &str = r#"WINE REGISTRY Version 2
+;; All keys relative to \\User\\S-1-5-21-0-0-0-1000
+
+[Software\\Classes\\editor.md\\DefaultIcon] 1700000000
+@="C:\\Program Files\\Editor\\editor.exe,0"
+
+[Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\FileExts\\.md\\UserChoice] 1700000000
+"ProgId"="editor.md"
+"#;
+
+fn prefix() -> TempDir {
+    let dir = TempDir::new("wine");
+    dir.write("system.reg", SYSTEM_REG.as_bytes());
+    dir.write("user.reg", USER_REG.as_bytes());
+    dir.write(
+        "drive_c/windows/system32/shell32.dll",
+        &pe_file(&icon_resources(&[(ResourceId::Id(1), vec![solid(32, [9, 9, 9, 255])]), (ResourceId::Id(2), vec![solid(32, [8, 8, 8, 255])]), (ResourceId::Id(3), vec![solid(32, APPLICATION)]), (ResourceId::Id(152), vec![solid(32, TEXT)])]), true),
+    );
+    dir.write(
+        "drive_c/Program Files/App/app.exe",
+        &pe_file(&icon_resources(&[(ResourceId::Id(1), vec![solid(16, APP_SMALL), solid(32, APP_LARGE)])]), false),
+    );
+    dir.write("drive_c/Program Files/Editor/editor.exe", &pe_file(&icon_resources(&[(ResourceId::Id(1), vec![solid(32, EDITOR)])]), true));
+    dir.write("drive_c/Program Files/App/plain.exe", &pe_file(&vec![], false));
+    dir.write("home/picture.ico", &ico::encode(&[solid(32, IMAGE)], &EncodeOptions::default()).unwrap());
+    dir
+}
+
+fn color(result: Result<image::RgbaImage, IconError>) -> [u8; 4] {
+    result.unwrap().get_pixel(0, 0).0
+}
+
+#[test]
+fn parses_wine_registry_files() {
+    let file = RegFile::parse(SYSTEM_REG.as_bytes()).unwrap();
+    let value = |name| file.value(r"HKLM\Software\Wine\Escapes", name).unwrap();
+    assert_eq!(value("Text"), Some(RegValue::String("tab\there \"quoted\" café A".into())));
+    assert_eq!(value("Multi"), Some(RegValue::MultiString(vec!["one".into(), "two".into()])));
+    assert_eq!(value("Path"), Some(RegValue::ExpandString("%Sys".into())));
+    assert_eq!(file.string(r"HKEY_LOCAL_MACHINE\Software\Classes\.txt", "").unwrap().as_deref(), Some("txtfile"));
+
+    let user = RegFile::parse(USER_REG.as_bytes()).unwrap();
+    assert!(user.key_exists(r"HKEY_CURRENT_USER\Software\Classes\editor.md").unwrap());
+    let defaults = RegFile::parse(b"WINE REGISTRY Version 2\n;; All keys relative to \\\\User\\\\.Default\n\n[Control Panel] 1\n").unwrap();
+    assert!(defaults.key_exists(r"HKEY_USERS\.DEFAULT\Control Panel").unwrap());
+}
+
+#[test]
+fn resolves_extension_and_mime_icons() {
+    let dir = prefix();
+    let provider = WineIconProvider::open(&dir.0).unwrap();
+
+    assert_eq!(color(provider.icon_for_extension(".txt", IconSize::Large)), TEXT);
+    assert_eq!(color(provider.icon_for_extension("MD", IconSize::Large)), EDITOR);
+    assert_eq!(color(provider.icon_for_mime("text/plain", IconSize::Large)), TEXT);
+    assert!(matches!(provider.icon_for_mime("text/x-none", IconSize::Large), Err(IconError::NoIconForMime(_))));
+    assert!(matches!(provider.icon_for_extension("exe", IconSize::Large), Err(IconError::NoIconForExtension(_))));
+    assert!(matches!(provider.icon_for_extension("none", IconSize::Large), Err(IconError::NoIconForExtension(_))));
+}
+
+#[test]
+fn reads_icons_from_files_under_the_prefix() {
+    let dir = prefix();
+    let provider = WineIconProvider::open(&dir.0).unwrap();
+    let path = |relative: &str| dir.0.join(relative);
+    let icon = |path: &Path, size| color(provider.icon_for_path(path, size));
+
+    assert_eq!(icon(&path("drive_c/Program Files/App/app.exe"), IconSize::Large), APP_LARGE);
+    assert_eq!(icon(&path("drive_c/Program Files/App/app.exe"), IconSize::Small), APP_SMALL);
+    assert_eq!(icon(&path("drive_c/Program Files/App/plain.exe"), IconSize::Large), APPLICATION);
+    assert_eq!(icon(&path("home/picture.ico"), IconSize::Large), IMAGE);
+    assert_eq!(icon(&path("home/notes.txt"), IconSize::Large), TEXT);
+    assert!(matches!(provider.icon_for_path(&path("home/data.bin"), IconSize::Large), Err(IconError::NoIcon { .. })));
+}
+
+#[test]
+fn resolves_drives_through_dosdevices() {
+    let dir = prefix();
+    dir.write("drive_c/share/archive.ico", &ico::encode(&[solid(32, IMAGE)], &EncodeOptions::default()).unwrap());
+    dir.write("host/share/archive.ico", &ico::encode(&[solid(32, ARCHIVE)], &EncodeOptions::default()).unwrap());
+
+    // Without a `z:` link the drive is unknown, rather than an alias of `C:`.
+    let provider = WineIconProvider::open(&dir.0).unwrap();
+    assert!(matches!(provider.icon_for_extension("zip", IconSize::Large), Err(IconError::Io { .. })));
+
+    #[cfg(unix)]
+    {
+        std::fs::create_dir(dir.0.join("dosdevices")).unwrap();
+        std::os::unix::fs::symlink("../drive_c", dir.0.join("dosdevices/c:")).unwrap();
+        std::os::unix::fs::symlink("../host", dir.0.join("dosdevices/z:")).unwrap();
+        let provider = WineIconProvider::open(&dir.0).unwrap();
+        assert_eq!(color(provider.icon_for_extension("zip", IconSize::Large)), ARCHIVE);
+        assert_eq!(color(provider.icon_for_extension("txt", IconSize::Large)), TEXT);
+    }
+}
+
+#[test]
+fn requires_registry_files() {
+    let dir = TempDir::new("wine-empty");
+    assert!(matches!(WineIconProvider::open(&dir.0), Err(IconError::Io { .. })));
+}
+
+#[test]
+fn parses_large_registries() {
+    let mut text = String::from("WINE REGISTRY Version 2\n;; All keys relative to \\\\Machine\n\n");
+    for index in 0..20_000 {
+        text.push_str(&format!("[Software\\\\Classes\\\\.ext{index}] 1700000000\n#time=1d\n@=\"type{index}\"\n\n"));
+        text.push_str(&format!("[Software\\\\Classes\\\\type{index}\\\\DefaultIcon] 1700000000\n@=\"icon{index}.dll,{index}\"\n\n"));
+    }
+
+    let file = RegFile::parse(text.as_bytes()).unwrap();
+    assert_eq!(file.subkey_names(r"HKLM\Software\Classes").unwrap().map(|names| names.len()), Some(40_000));
+    assert_eq!(file.associations().default_icon("EXT19999").unwrap().as_deref(), Some("icon19999.dll,19999"));
+}